@@ -3,8 +3,27 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use secret_secret::msg::{CountResponse, HandleMsg, InitMsg, QueryMsg};
-use secret_secret::state::State;
+use secret_secret::attestation::{AttestFundsResponse, AttestationResponse};
+use secret_secret::clawback::ClawbacksResponse;
+use secret_secret::compliance::ComplianceStatusResponse;
+use secret_secret::cw20::{Cw20AllowanceResponse, Cw20BalanceResponse, TokenInfoResponse};
+use secret_secret::denom_ledger::DenomBalanceResponse;
+use secret_secret::fees::{CollectedFeesResponse, FeesResponse};
+use secret_secret::freeze::FreezeStatusResponse;
+use secret_secret::governance::VotingWeightResponse;
+use secret_secret::incoming::IncomingTransfersResponse;
+use secret_secret::marketing::MarketingInfoResponse;
+use secret_secret::msg::{
+    AccountStatsResponse, AccountTotalsResponse, ActiveSpendersResponse, AllowanceResponse,
+    BalanceResponse, BatchResponse, ContractStatusResponse, CreateViewingKeyResponse, HandleMsg,
+    InitMsg, PermitBalancesResponse, QueryAnswer, QueryMsg, ReserveBackingResponse,
+    RewardsResponse, TransferCountResponse, TransfersResponse,
+};
+use secret_secret::send_whitelist::SendWhitelistResponse;
+use secret_secret::signed::NonceResponse;
+use secret_secret::staking::{ExchangeRateResponse, UnbondingsResponse};
+use secret_secret::timelock::LockedBalanceResponse;
+use secret_secret::vesting::VestingSchedulesResponse;
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -15,4 +34,40 @@ fn main() {
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+
+    // answers
+    export_schema(&schema_for!(QueryAnswer), &out_dir);
+    export_schema(&schema_for!(AllowanceResponse), &out_dir);
+    export_schema(&schema_for!(ActiveSpendersResponse), &out_dir);
+    export_schema(&schema_for!(BalanceResponse), &out_dir);
+    export_schema(&schema_for!(DenomBalanceResponse), &out_dir);
+    export_schema(&schema_for!(CreateViewingKeyResponse), &out_dir);
+    export_schema(&schema_for!(TransfersResponse), &out_dir);
+    export_schema(&schema_for!(TransferCountResponse), &out_dir);
+    export_schema(&schema_for!(AccountStatsResponse), &out_dir);
+    export_schema(&schema_for!(AccountTotalsResponse), &out_dir);
+    export_schema(&schema_for!(PermitBalancesResponse), &out_dir);
+    export_schema(&schema_for!(BatchResponse), &out_dir);
+    export_schema(&schema_for!(RewardsResponse), &out_dir);
+    export_schema(&schema_for!(ContractStatusResponse), &out_dir);
+    export_schema(&schema_for!(ReserveBackingResponse), &out_dir);
+    export_schema(&schema_for!(ComplianceStatusResponse), &out_dir);
+    export_schema(&schema_for!(ClawbacksResponse), &out_dir);
+    export_schema(&schema_for!(FreezeStatusResponse), &out_dir);
+    export_schema(&schema_for!(AttestFundsResponse), &out_dir);
+    export_schema(&schema_for!(AttestationResponse), &out_dir);
+    export_schema(&schema_for!(NonceResponse), &out_dir);
+    export_schema(&schema_for!(VotingWeightResponse), &out_dir);
+    export_schema(&schema_for!(ExchangeRateResponse), &out_dir);
+    export_schema(&schema_for!(FeesResponse), &out_dir);
+    export_schema(&schema_for!(CollectedFeesResponse), &out_dir);
+    export_schema(&schema_for!(SendWhitelistResponse), &out_dir);
+    export_schema(&schema_for!(IncomingTransfersResponse), &out_dir);
+    export_schema(&schema_for!(MarketingInfoResponse), &out_dir);
+    export_schema(&schema_for!(Cw20BalanceResponse), &out_dir);
+    export_schema(&schema_for!(TokenInfoResponse), &out_dir);
+    export_schema(&schema_for!(Cw20AllowanceResponse), &out_dir);
+    export_schema(&schema_for!(UnbondingsResponse), &out_dir);
+    export_schema(&schema_for!(LockedBalanceResponse), &out_dir);
+    export_schema(&schema_for!(VestingSchedulesResponse), &out_dir);
 }
@@ -0,0 +1,176 @@
+//! Runs representative messages and queries against mock dependencies and prints the storage
+//! operations each one makes, with what they would cost in gas. Compare the output before and
+//! after changing the storage layer:
+//!
+//!     cargo run --example storage_benchmark
+
+use cosmwasm_std::{Binary, Coin, HumanAddr, Uint128};
+
+use secret_secret::contract::query;
+use secret_secret::msg::{HandleMsg, QueryMsg, TransferAction};
+use secret_secret::testing::{
+    default_init_msg, handle_as, handle_with_funds, init_token, set_viewing_key, MockDeps,
+    StorageOps,
+};
+
+const ALICE_KEY: &str = "api_key_AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+/// Transfers made before the history queries, so they read full pages
+const HISTORY_LENGTH: u32 = 30;
+
+fn main() {
+    let mut deps = init_token("admin", default_init_msg(&[("alice", 1_000_000_000)])).unwrap();
+    set_viewing_key(&mut deps, "alice", ALICE_KEY).unwrap();
+    handle_as(&mut deps, "router", register_receive()).unwrap();
+
+    println!(
+        "{:<32} {:>7} {:>7} {:>8} {:>11} {:>14} {:>10}",
+        "message", "reads", "writes", "removes", "bytes read", "bytes written", "gas"
+    );
+
+    let uscrt = vec![Coin {
+        denom: "uscrt".to_string(),
+        amount: Uint128(1_000_000),
+    }];
+    run(&mut deps, "deposit", "alice", &uscrt, HandleMsg::Deposit {});
+    run(&mut deps, "withdraw", "alice", &[], withdraw(500_000));
+    run(&mut deps, "transfer to a new account", "alice", &[], transfer("bob", 1_000));
+    run(&mut deps, "transfer", "alice", &[], transfer("bob", 1_000));
+    run(&mut deps, "send to a receiver", "alice", &[], send("router", 1_000));
+    run(&mut deps, "batch transfer of 5", "alice", &[], batch_transfer(5));
+    run(&mut deps, "approve", "alice", &[], approve("bob", 10_000));
+    run(&mut deps, "transfer from", "bob", &[], transfer_from("alice", "carol", 1_000));
+    run(&mut deps, "burn", "alice", &[], burn(1_000));
+    run(&mut deps, "set viewing key", "carol", &[], set_key());
+
+    for _ in 0..HISTORY_LENGTH {
+        handle_as(&mut deps, "alice", transfer("dave", 1)).unwrap();
+    }
+    run_query(&deps, "balance query", balance_query());
+    run_query(&deps, "transfers query, page of 10", transfers_query(10));
+    run_query(&deps, "wrong viewing key", wrong_key_query());
+}
+
+fn run(deps: &mut MockDeps, label: &str, sender: &str, funds: &[Coin], msg: HandleMsg) {
+    deps.storage.take_ops();
+    handle_with_funds(deps, sender, funds, msg).unwrap();
+    report(label, deps.storage.take_ops());
+}
+
+fn run_query(deps: &MockDeps, label: &str, msg: QueryMsg) {
+    deps.storage.take_ops();
+    query(deps, msg).unwrap();
+    report(label, deps.storage.take_ops());
+}
+
+fn report(label: &str, ops: StorageOps) {
+    println!(
+        "{:<32} {:>7} {:>7} {:>8} {:>11} {:>14} {:>10}",
+        label,
+        ops.reads,
+        ops.writes,
+        ops.removes,
+        ops.bytes_read,
+        ops.bytes_written,
+        ops.gas()
+    );
+}
+
+fn register_receive() -> HandleMsg {
+    HandleMsg::RegisterReceive {
+        code_hash: "router code hash".to_string(),
+    }
+}
+
+fn withdraw(amount: u128) -> HandleMsg {
+    HandleMsg::Withdraw {
+        amount: Uint128(amount),
+        denom: None,
+    }
+}
+
+fn transfer(recipient: &str, amount: u128) -> HandleMsg {
+    HandleMsg::Transfer {
+        recipient: HumanAddr::from(recipient),
+        amount: Uint128(amount),
+    }
+}
+
+fn send(recipient: &str, amount: u128) -> HandleMsg {
+    HandleMsg::Send {
+        recipient: HumanAddr::from(recipient),
+        recipient_code_hash: None,
+        amount: Uint128(amount),
+        msg: Some(Binary::from(b"{}".to_vec())),
+    }
+}
+
+fn batch_transfer(recipients: usize) -> HandleMsg {
+    HandleMsg::BatchTransfer {
+        actions: (0..recipients)
+            .map(|i| TransferAction {
+                recipient: HumanAddr::from(format!("payee{}", i)),
+                amount: Uint128(100),
+                memo: Some("payroll".to_string()),
+            })
+            .collect(),
+    }
+}
+
+fn approve(spender: &str, amount: u128) -> HandleMsg {
+    HandleMsg::Approve {
+        spender: HumanAddr::from(spender),
+        amount: Uint128(amount),
+        expected_current: None,
+        notify: None,
+    }
+}
+
+fn transfer_from(owner: &str, recipient: &str, amount: u128) -> HandleMsg {
+    HandleMsg::TransferFrom {
+        owner: HumanAddr::from(owner),
+        recipient: HumanAddr::from(recipient),
+        amount: Uint128(amount),
+    }
+}
+
+fn burn(amount: u128) -> HandleMsg {
+    HandleMsg::Burn {
+        amount: Uint128(amount),
+    }
+}
+
+fn set_key() -> HandleMsg {
+    HandleMsg::SetViewingKey {
+        key: "api_key_CCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC".to_string(),
+    }
+}
+
+fn balance_query() -> QueryMsg {
+    QueryMsg::Balance {
+        address: HumanAddr::from("alice"),
+        key: ALICE_KEY.to_string(),
+        denom: None,
+    }
+}
+
+fn transfers_query(page_size: u32) -> QueryMsg {
+    QueryMsg::Transfers {
+        address: HumanAddr::from("alice"),
+        key: ALICE_KEY.to_string(),
+        page: None,
+        page_size: Some(page_size),
+        direction: None,
+        counterparty: None,
+        start_time: None,
+        end_time: None,
+    }
+}
+
+fn wrong_key_query() -> QueryMsg {
+    QueryMsg::Balance {
+        address: HumanAddr::from("alice"),
+        key: "api_key_wrong".to_string(),
+        denom: None,
+    }
+}
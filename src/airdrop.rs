@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, Querier,
+    ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::compliance::check_account_compliance;
+use crate::contract::assert_admin;
+use crate::msg::InitialBalance;
+use crate::rand::sha_256;
+use crate::state::{
+    read_u128, store_transfer, Balances, ReadonlyVersionedStorage, TypedStore,
+    TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_AIRDROP: &[u8] = b"airdrop";
+pub const KEY_AIRDROP_STATE: &[u8] = b"state";
+pub const KEY_AIRDROP_POOL: &[u8] = b"pool";
+pub const PREFIX_AIRDROP_CLAIMED: &[u8] = b"airdrop_claimed";
+
+/// Registering a new Merkle root starts a new `stage`, with its own claimed bitmap
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AirdropState {
+    pub stage: u32,
+    pub merkle_root: Vec<u8>,
+}
+
+fn get_airdrop_state<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<AirdropState>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_AIRDROP, storage);
+    TypedStore::attach(&store).may_load(KEY_AIRDROP_STATE)
+}
+
+fn set_airdrop_state<S: Storage>(storage: &mut S, state: &AirdropState) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_AIRDROP, storage);
+    TypedStoreMut::attach(&mut store).store(KEY_AIRDROP_STATE, state)
+}
+
+/// Tokens set aside for airdrops, both Merkle claims and batch airdrops are paid from here
+fn get_pool<S: ReadonlyStorage>(storage: &S) -> StdResult<u128> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_AIRDROP, storage);
+    read_u128(&store, KEY_AIRDROP_POOL)
+}
+
+fn set_pool<S: Storage>(storage: &mut S, pool: u128) {
+    let mut store = VersionedStorage::new(PREFIX_AIRDROP, storage);
+    store.set(KEY_AIRDROP_POOL, &pool.to_be_bytes());
+}
+
+/// Moves `amount` of the admin's tokens into the pool
+fn fund_pool<S: Storage>(storage: &mut S, admin: &CanonicalAddr, amount: u128) -> StdResult<()> {
+    let mut balances = Balances::from_storage(storage);
+    let admin_balance = balances.balance(admin)?;
+    let admin_balance = admin_balance.checked_sub(amount).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            admin_balance, amount
+        ))
+    })?;
+    balances.set_balance(admin, admin_balance)?;
+
+    let pool = get_pool(storage)?
+        .checked_add(amount)
+        .ok_or_else(|| generic_err("Airdrop pool overflow"))?;
+    set_pool(storage, pool);
+    Ok(())
+}
+
+/// Takes `amount` out of the pool and credits it to `recipient`, if they pass the token's
+/// compliance rules
+fn pay_from_pool<S: Storage>(storage: &mut S, recipient: &CanonicalAddr, amount: u128) -> StdResult<()> {
+    check_account_compliance(storage, recipient)?;
+
+    let pool = get_pool(storage)?
+        .checked_sub(amount)
+        .ok_or_else(|| generic_err("Airdrop pool is exhausted"))?;
+    set_pool(storage, pool);
+
+    let mut balances = Balances::from_storage(storage);
+    let account_balance = balances
+        .balance(recipient)?
+        .checked_add(amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    balances.set_balance(recipient, account_balance)
+}
+
+/// Marks leaf `index` of `stage` as claimed. Fails if it already was
+fn set_claimed<S: Storage>(storage: &mut S, stage: u32, index: u32) -> StdResult<()> {
+    let stage_key = stage.to_be_bytes();
+    let mut store = VersionedStorage::multilevel(&[PREFIX_AIRDROP_CLAIMED, &stage_key], storage);
+
+    // one storage entry holds the flags of 256 leaves
+    let word_key = (index / 256).to_be_bytes();
+    let mut word = store.get(&word_key).unwrap_or_else(|| vec![0u8; 32]);
+    let byte = ((index % 256) / 8) as usize;
+    let bit = 1u8 << (index % 8);
+    if word.len() != 32 {
+        return Err(generic_err("Corrupted airdrop bitmap"));
+    }
+    if word[byte] & bit != 0 {
+        return Err(generic_err("Airdrop already claimed"));
+    }
+    word[byte] |= bit;
+    store.set(&word_key, &word);
+
+    Ok(())
+}
+
+/// Leaves are `sha256(index || address || amount)`, with big endian integers and the
+/// canonical address
+fn leaf_hash(index: u32, address: &CanonicalAddr, amount: u128) -> [u8; 32] {
+    let mut leaf = index.to_be_bytes().to_vec();
+    leaf.extend_from_slice(address.as_slice());
+    leaf.extend_from_slice(&amount.to_be_bytes());
+    sha_256(&leaf)
+}
+
+/// Hashes `leaf` up the tree with `proof`. Pairs are sorted before hashing, so the proof
+/// doesn't need to say which side each sibling is on
+fn merkle_root(leaf: [u8; 32], proof: &[Binary]) -> StdResult<[u8; 32]> {
+    let mut hash = leaf;
+    for sibling in proof {
+        let sibling = sibling.as_slice();
+        if sibling.len() != 32 {
+            return Err(generic_err("Merkle proof entries must be 32 bytes"));
+        }
+        let mut pair = Vec::with_capacity(64);
+        if hash.as_ref() <= sibling {
+            pair.extend_from_slice(&hash);
+            pair.extend_from_slice(sibling);
+        } else {
+            pair.extend_from_slice(sibling);
+            pair.extend_from_slice(&hash);
+        }
+        hash = sha_256(&pair);
+    }
+    Ok(hash)
+}
+
+/// Starts a new airdrop stage with `merkle_root`, and moves `amount` of the admin's tokens into
+/// the pool claims are paid from
+pub fn try_register_airdrop<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    merkle_root: Binary,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    if merkle_root.len() != 32 {
+        return Err(generic_err("Merkle root must be a 32 byte SHA-256 hash"));
+    }
+
+    fund_pool(&mut deps.storage, &env.message.sender, amount.u128())?;
+
+    let stage = match get_airdrop_state(&deps.storage)? {
+        Some(state) => state
+            .stage
+            .checked_add(1)
+            .ok_or_else(|| generic_err("Too many airdrop stages"))?,
+        None => 0,
+    };
+    let state = AirdropState {
+        stage,
+        merkle_root: merkle_root.0,
+    };
+    set_airdrop_state(&mut deps.storage, &state)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "register_airdrop"),
+            log("stage", state.stage),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Pays the sender `amount` if leaf `index` of the current stage's tree says so
+pub fn try_claim_airdrop<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    index: u32,
+    proof: Vec<Binary>,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let state = get_airdrop_state(&deps.storage)?
+        .ok_or_else(|| generic_err("No airdrop registered"))?;
+
+    let claimant = &env.message.sender;
+    let amount_raw = amount.u128();
+    let root = merkle_root(leaf_hash(index, claimant, amount_raw), &proof)?;
+    if root.as_ref() != state.merkle_root.as_slice() {
+        return Err(generic_err("Invalid airdrop proof"));
+    }
+
+    set_claimed(&mut deps.storage, state.stage, index)?;
+    pay_from_pool(&mut deps.storage, claimant, amount_raw)?;
+
+    store_transfer(&mut deps.storage, &env.contract.address, claimant, &amount, env.block.time)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "claim_airdrop"),
+            log("account", deps.api.human_address(claimant)?.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Credits every recipient from the pool, after adding `amount` of the admin's tokens to it
+pub fn try_batch_airdrop<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipients: Vec<InitialBalance>,
+    amount: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    if let Some(amount) = amount {
+        fund_pool(&mut deps.storage, &env.message.sender, amount.u128())?;
+    }
+
+    for recipient in recipients.iter() {
+        let recipient_raw = deps.api.canonical_address(&recipient.address)?;
+        pay_from_pool(&mut deps.storage, &recipient_raw, recipient.amount.u128())?;
+        store_transfer(
+            &mut deps.storage,
+            &env.contract.address,
+            &recipient_raw,
+            &recipient.amount,
+            env.block.time,
+        )?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "airdrop"),
+            log("recipients", recipients.len()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
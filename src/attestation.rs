@@ -0,0 +1,92 @@
+//! Proofs of funds. An account asks the token to attest that it holds at least some amount, and
+//! gets back a random id. Whoever it shares the id with, e.g. a lending contract, can query the
+//! attestation from the token itself, which is what makes it trustworthy, without learning the
+//! exact balance
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, StdResult, Storage, Uint128,
+};
+
+use crate::rand::contract_rng;
+use crate::state::{
+    ReadonlyBalances, ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_ATTESTATIONS: &[u8] = b"attestations";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Attestation {
+    account: CanonicalAddr,
+    min_amount: u128,
+    height: u64,
+    time: u64,
+}
+
+/// The id of a new attestation, in the `data` of `AttestFunds`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AttestFundsResponse {
+    pub id: String,
+}
+
+/// `address` held at least `min_amount` at block `height`, at block time `time`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AttestationResponse {
+    pub address: HumanAddr,
+    pub min_amount: Uint128,
+    pub height: u64,
+    pub time: u64,
+}
+
+/// Attests that the sender holds at least `min_amount`, and fails if it doesn't
+pub fn try_attest_funds<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    min_amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let account = &env.message.sender;
+    let balance = ReadonlyBalances::from_storage(&deps.storage).balance(account)?;
+    if balance < min_amount.u128() {
+        return Err(generic_err("The balance is below the amount to attest"));
+    }
+
+    let id = contract_rng(&mut deps.storage, &env, b"attestation").rand_bytes();
+    let attestation = Attestation {
+        account: account.clone(),
+        min_amount: min_amount.u128(),
+        height: env.block.height,
+        time: env.block.time,
+    };
+    let mut store = VersionedStorage::new(PREFIX_ATTESTATIONS, &mut deps.storage);
+    TypedStoreMut::attach(&mut store).store(&id, &attestation)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "attest_funds")],
+        data: Some(to_binary(&AttestFundsResponse {
+            id: base64::encode(id),
+        })?),
+    };
+    Ok(res)
+}
+
+/// Anyone who knows the id can read the attestation
+pub fn query_attestation<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    id: &str,
+) -> StdResult<Binary> {
+    let id = base64::decode(id).map_err(|_| generic_err("Invalid attestation id"))?;
+    let store = ReadonlyVersionedStorage::new(PREFIX_ATTESTATIONS, &deps.storage);
+    let attestation: Attestation = TypedStore::attach(&store)
+        .may_load(&id)?
+        .ok_or_else(|| generic_err("No attestation with this id"))?;
+
+    to_binary(&AttestationResponse {
+        address: deps.api.human_address(&attestation.account)?,
+        min_amount: Uint128(attestation.min_amount),
+        height: attestation.height,
+        time: attestation.time,
+    })
+}
@@ -0,0 +1,163 @@
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::assert_admin;
+use crate::staking::get_staking_state;
+use crate::state::{
+    store_mint, store_tagged_transfer, write_total_supply, Balances, ReadonlyVersionedStorage,
+    SupplyChange, VersionedStorage,
+};
+
+pub const PREFIX_BRIDGE: &[u8] = b"bridge";
+pub const KEY_BRIDGE_ADDRESS: &[u8] = b"address";
+pub const PREFIX_BRIDGE_MINTS: &[u8] = b"bridge_mints";
+
+/// Max length of an external transaction hash or recipient, in bytes
+pub const MAX_EXTERNAL_REFERENCE_LENGTH: usize = 128;
+
+fn get_bridge<S: ReadonlyStorage>(storage: &S) -> Option<CanonicalAddr> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_BRIDGE, storage);
+    store.get(KEY_BRIDGE_ADDRESS).map(CanonicalAddr::from)
+}
+
+fn check_external_reference(name: &str, reference: &str) -> StdResult<()> {
+    if reference.is_empty() || reference.len() > MAX_EXTERNAL_REFERENCE_LENGTH {
+        return Err(generic_err(format!(
+            "{} must be between 1 and {} bytes",
+            name, MAX_EXTERNAL_REFERENCE_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// Sets the address allowed to send `BridgeMint`, or removes it if `address` is not set
+pub fn try_set_bridge<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let address = match address {
+        Some(address) => Some(deps.api.canonical_address(&address)?),
+        None => None,
+    };
+    let mut store = VersionedStorage::new(PREFIX_BRIDGE, &mut deps.storage);
+    match address {
+        Some(address) => store.set(KEY_BRIDGE_ADDRESS, address.as_slice()),
+        None => store.remove(KEY_BRIDGE_ADDRESS),
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_bridge")],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Mints `amount` to `recipient` for tokens locked on the other chain in `tx_hash`. Each
+/// `tx_hash` can only be minted for once
+pub fn try_bridge_mint<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: Uint128,
+    tx_hash: String,
+) -> StdResult<HandleResponse> {
+    if get_bridge(&deps.storage) != Some(env.message.sender.clone()) {
+        return Err(unauthorized());
+    }
+    if get_staking_state(&deps.storage)?.is_some() {
+        return Err(generic_err(
+            "Bridged tokens can't be minted in staking mode, they aren't backed by delegations",
+        ));
+    }
+    check_external_reference("Transaction hash", &tx_hash)?;
+
+    let mut mints = VersionedStorage::new(PREFIX_BRIDGE_MINTS, &mut deps.storage);
+    if mints.get(tx_hash.as_bytes()).is_some() {
+        return Err(generic_err(format!("{} was already minted for", tx_hash)));
+    }
+    mints.set(tx_hash.as_bytes(), &[1]);
+
+    let recipient_raw = deps.api.canonical_address(recipient)?;
+    let amount_raw = amount.u128();
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances
+        .balance(&recipient_raw)?
+        .checked_add(amount_raw)
+        .ok_or_else(|| generic_err("Mint overflows the account balance"))?;
+    balances.set_balance(&recipient_raw, account_balance)?;
+
+    write_total_supply(&mut deps.storage, SupplyChange::Mint(amount_raw))?;
+
+    store_mint(
+        &mut deps.storage,
+        &env.message.sender,
+        &recipient_raw,
+        &amount,
+        env.block.time,
+        env.block.height,
+        &tx_hash,
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "bridge_mint"), log("tx_hash", &tx_hash)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Burns `amount` of the sender's tokens so the bridge releases them to `external_recipient`
+/// on the other chain. The bridge picks the burn up from the logs
+pub fn try_bridge_burn<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+    external_recipient: String,
+) -> StdResult<HandleResponse> {
+    if get_bridge(&deps.storage).is_none() {
+        return Err(generic_err("No bridge is set"));
+    }
+    check_external_reference("External recipient", &external_recipient)?;
+
+    let owner = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances.balance(owner)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "insufficient funds to burn: balance={}, required={}",
+            account_balance, amount_raw
+        ))
+    })?;
+    balances.set_balance(owner, account_balance)?;
+
+    write_total_supply(&mut deps.storage, SupplyChange::Burn(amount_raw))?;
+
+    store_tagged_transfer(
+        &mut deps.storage,
+        owner,
+        &env.contract.address,
+        &amount,
+        env.block.time,
+        Some(&external_recipient),
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "bridge_burn"),
+            log("amount", &amount.to_string()),
+            log("external_recipient", &external_recipient),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
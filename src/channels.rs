@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, unauthorized, Api, Binary, CanonicalAddr, Env, Extern,
+    HandleResponse, HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::compliance::check_account_compliance;
+use crate::freeze::check_not_frozen;
+use crate::permit::{pub_key_to_address, verify_signature};
+use crate::state::{
+    store_transfer, Balances, ReadonlyVersionedStorage, TypedStore, TypedStoreMut,
+    VersionedStorage,
+};
+
+pub const PREFIX_CHANNELS: &[u8] = b"channels";
+pub const KEY_CHANNEL_COUNT: &[u8] = b"channel_count";
+
+/// Funds the payer locked for off-chain payments to the recipient. The recipient settles with
+/// the latest balance proof before `expiry`, after that the payer can close the channel and
+/// take the deposit back
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Channel {
+    pub payer: CanonicalAddr,
+    pub recipient: CanonicalAddr,
+    pub deposit: u128,
+    pub expiry: u64,
+}
+
+/// What the payer signs for each off-chain payment. `amount` is the total paid through the
+/// channel so far, so only the latest proof matters. Fields are in alphabetical order, which is
+/// how wallets serialize them
+#[derive(Serialize)]
+struct BalanceProof<'a> {
+    amount: Uint128,
+    channel_id: u64,
+    contract: &'a HumanAddr,
+}
+
+fn store_channel<S: Storage>(storage: &mut S, channel: &Channel) -> StdResult<u64> {
+    let mut store = VersionedStorage::new(PREFIX_CHANNELS, storage);
+
+    let id = TypedStore::<u64, _>::attach(&store).may_load(KEY_CHANNEL_COUNT)?.unwrap_or(0) + 1;
+
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), channel)?;
+    TypedStoreMut::attach(&mut store).store(KEY_CHANNEL_COUNT, &id)?;
+
+    Ok(id)
+}
+
+fn get_channel<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<Channel> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_CHANNELS, storage);
+    TypedStore::attach(&store)
+        .may_load(&id.to_be_bytes())?
+        .ok_or_else(|| generic_err(format!("No channel with id {}", id)))
+}
+
+fn remove_channel<S: Storage>(storage: &mut S, id: u64) {
+    let mut store = VersionedStorage::new(PREFIX_CHANNELS, storage);
+    store.remove(&id.to_be_bytes());
+}
+
+fn credit<S: Storage>(storage: &mut S, account: &CanonicalAddr, amount: u128) -> StdResult<()> {
+    let mut balances = Balances::from_storage(storage);
+    let account_balance = balances
+        .balance(account)?
+        .checked_add(amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    balances.set_balance(account, account_balance)
+}
+
+pub fn try_open_channel<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+    expiry: u64,
+) -> StdResult<HandleResponse> {
+    let payer = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    if expiry <= env.block.time {
+        return Err(generic_err("Expiry must be in the future"));
+    }
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances.balance(payer)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            account_balance, amount_raw
+        ))
+    })?;
+    balances.set_balance(payer, account_balance)?;
+
+    let id = store_channel(
+        &mut deps.storage,
+        &Channel {
+            payer: payer.clone(),
+            recipient: deps.api.canonical_address(recipient)?,
+            deposit: amount_raw,
+            expiry,
+        },
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "open_channel"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Pays the recipient what the payer's balance proof says and refunds the rest of the deposit.
+/// Only the recipient can do this, and only before expiry
+pub fn try_settle_channel<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+    amount: Uint128,
+    signature: &Binary,
+    pub_key: &Binary,
+) -> StdResult<HandleResponse> {
+    let channel = get_channel(&deps.storage, id)?;
+
+    if channel.recipient != env.message.sender {
+        return Err(unauthorized());
+    }
+    if env.block.time >= channel.expiry {
+        return Err(generic_err("Channel has expired and can only be closed"));
+    }
+    check_not_frozen(&deps.storage, &channel.recipient)?;
+    check_account_compliance(&deps.storage, &channel.recipient)?;
+    if pub_key_to_address(pub_key.as_slice()) != channel.payer {
+        return Err(generic_err("Balance proof isn't signed by the payer"));
+    }
+
+    let contract = deps.api.human_address(&env.contract.address)?;
+    let proof = to_binary(&BalanceProof {
+        amount,
+        channel_id: id,
+        contract: &contract,
+    })?;
+    if !verify_signature(proof.as_slice(), signature.as_slice(), pub_key.as_slice())? {
+        return Err(generic_err("Failed to verify balance proof"));
+    }
+
+    let paid = amount.u128();
+    let refund = channel
+        .deposit
+        .checked_sub(paid)
+        .ok_or_else(|| generic_err("Balance proof exceeds the channel deposit"))?;
+
+    remove_channel(&mut deps.storage, id);
+
+    credit(&mut deps.storage, &channel.recipient, paid)?;
+    credit(&mut deps.storage, &channel.payer, refund)?;
+
+    store_transfer(&mut deps.storage, &channel.payer, &channel.recipient, &amount, env.block.time)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "settle_channel"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Returns the whole deposit to the payer once the channel expired unsettled
+pub fn try_close_channel<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let channel = get_channel(&deps.storage, id)?;
+
+    if channel.payer != env.message.sender {
+        return Err(unauthorized());
+    }
+    if env.block.time < channel.expiry {
+        return Err(generic_err("Channel can't be closed before it expires"));
+    }
+
+    remove_channel(&mut deps.storage, id);
+    credit(&mut deps.storage, &channel.payer, channel.deposit)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "close_channel"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
@@ -0,0 +1,154 @@
+//! Admin clawbacks, for deployments that are legally required to be able to seize tokens. Only
+//! tokens created with `clawback` in their init message allow them, and that can't be changed
+//! later, so holders can tell from the `clawbacks` query whether the admin has this power. Every
+//! clawback must give a reason, which anyone can read
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, Env, Extern, HandleResponse, HumanAddr, Querier,
+    ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::{assert_admin, credit, debit, MAX_MEMO_LENGTH};
+use crate::denom_ledger::ledger_transfer;
+use crate::state::{
+    store_tagged_transfer_with_memo, Balances, ReadonlyVersionedStorage, TypedStore,
+    TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_CLAWBACK: &[u8] = b"clawback";
+pub const KEY_CLAWBACK_ENABLED: &[u8] = b"enabled";
+pub const KEY_CLAWBACK_COUNT: &[u8] = b"count";
+
+/// The reference clawbacks are tagged with in the history of both accounts, along with the
+/// reason as memo
+pub const CLAWBACK_REFERENCE: &str = "clawback";
+
+/// A clawback as anyone can see it. The accounts involved only see it in their own history
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClawbackRecord {
+    pub amount: Uint128,
+    pub reason: String,
+    pub time: u64,
+}
+
+/// Whether the admin can claw back tokens, and the clawbacks so far, newest first
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClawbacksResponse {
+    pub enabled: bool,
+    pub clawbacks: Vec<ClawbackRecord>,
+}
+
+pub fn init_clawback<S: Storage>(storage: &mut S) {
+    let mut store = VersionedStorage::new(PREFIX_CLAWBACK, storage);
+    store.set(KEY_CLAWBACK_ENABLED, &[1]);
+}
+
+fn is_enabled<S: ReadonlyStorage>(storage: &S) -> bool {
+    let store = ReadonlyVersionedStorage::new(PREFIX_CLAWBACK, storage);
+    store.get(KEY_CLAWBACK_ENABLED).is_some()
+}
+
+fn count_clawbacks<S: ReadonlyStorage>(storage: &S) -> StdResult<u64> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_CLAWBACK, storage);
+    Ok(TypedStore::attach(&store).may_load(KEY_CLAWBACK_COUNT)?.unwrap_or(0))
+}
+
+fn store_clawback<S: Storage>(storage: &mut S, record: &ClawbackRecord) -> StdResult<()> {
+    let id = count_clawbacks(storage)? + 1;
+
+    let mut store = VersionedStorage::new(PREFIX_CLAWBACK, storage);
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), record)?;
+    TypedStoreMut::attach(&mut store).store(KEY_CLAWBACK_COUNT, &id)
+}
+
+/// Admin only, and only if the token was created with clawbacks enabled. Moves `amount` out of
+/// `owner`'s balance into `recipient`'s, around compliance checks and incoming transfer blocks,
+/// since the owner may well be on a deny list
+pub fn try_clawback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    recipient: &HumanAddr,
+    amount: Uint128,
+    reason: String,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if !is_enabled(&deps.storage) {
+        return Err(generic_err("Clawbacks weren't enabled when the token was created"));
+    }
+    if reason.trim().is_empty() {
+        return Err(generic_err("A clawback needs a reason"));
+    }
+    if reason.len() > MAX_MEMO_LENGTH {
+        return Err(generic_err(format!(
+            "Reasons can be at most {} bytes long",
+            MAX_MEMO_LENGTH
+        )));
+    }
+
+    let owner_raw = deps.api.canonical_address(owner)?;
+    let recipient_raw = deps.api.canonical_address(recipient)?;
+    let amount_raw = amount.u128();
+
+    ledger_transfer(&mut deps.storage, &owner_raw, &recipient_raw, amount_raw)?;
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let owner_balance = debit(balances.balance(&owner_raw)?, amount_raw)?;
+    balances.set_balance(&owner_raw, owner_balance)?;
+    let recipient_balance = credit(balances.balance(&recipient_raw)?, amount_raw)?;
+    balances.set_balance(&recipient_raw, recipient_balance)?;
+
+    store_tagged_transfer_with_memo(
+        &mut deps.storage,
+        &owner_raw,
+        &recipient_raw,
+        &amount,
+        env.block.time,
+        CLAWBACK_REFERENCE,
+        &reason,
+    )?;
+    store_clawback(
+        &mut deps.storage,
+        &ClawbackRecord {
+            amount,
+            reason: reason.clone(),
+            time: env.block.time,
+        },
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "clawback"),
+            log("owner", owner.as_str()),
+            log("recipient", recipient.as_str()),
+            log("amount", amount),
+            log("reason", reason),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_clawbacks<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let count = count_clawbacks(&deps.storage)?;
+    let skipped = page as u64 * page_size as u64;
+    let newest = count.saturating_sub(skipped);
+    let oldest = newest.saturating_sub(page_size as u64);
+
+    let store = ReadonlyVersionedStorage::new(PREFIX_CLAWBACK, &deps.storage);
+    let clawbacks = (oldest + 1..=newest)
+        .rev()
+        .map(|id| TypedStore::attach(&store).load(&id.to_be_bytes()))
+        .collect::<StdResult<Vec<ClawbackRecord>>>()?;
+
+    to_binary(&ClawbacksResponse {
+        enabled: is_enabled(&deps.storage),
+        clawbacks,
+    })
+}
@@ -0,0 +1,197 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "compliance")]
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage,
+};
+
+#[cfg(feature = "compliance")]
+use crate::contract::assert_admin;
+#[cfg(feature = "compliance")]
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_COMPLIANCE: &[u8] = b"compliance";
+pub const KEY_COMPLIANCE_MODE: &[u8] = b"mode";
+pub const PREFIX_COMPLIANCE_STATUS: &[u8] = b"compliance_status";
+
+/// How transfers are screened, chosen at init. Tokens created without one don't screen at all
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplianceMode {
+    /// Both sides of a transfer must be allowed
+    Allowlist,
+    /// Neither side of a transfer may be denied
+    Denylist,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplianceStatus {
+    Allowed,
+    Denied,
+    /// Not on either list
+    Unlisted,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ComplianceStatusResponse {
+    pub mode: Option<ComplianceMode>,
+    pub status: ComplianceStatus,
+}
+
+#[cfg(feature = "compliance")]
+fn get_mode<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<ComplianceMode>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_COMPLIANCE, storage);
+    TypedStore::attach(&store).may_load(KEY_COMPLIANCE_MODE)
+}
+
+#[cfg(feature = "compliance")]
+pub fn init_compliance<S: Storage>(storage: &mut S, mode: ComplianceMode) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_COMPLIANCE, storage);
+    TypedStoreMut::attach(&mut store).store(KEY_COMPLIANCE_MODE, &mode)
+}
+
+#[cfg(feature = "compliance")]
+fn get_status<S: ReadonlyStorage>(storage: &S, address: &CanonicalAddr) -> StdResult<ComplianceStatus> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_COMPLIANCE_STATUS, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(address.as_slice())?
+        .unwrap_or(ComplianceStatus::Unlisted))
+}
+
+/// Fails if the token screens transfers and `account` doesn't pass, for every message that takes
+/// funds out of it
+#[cfg(feature = "compliance")]
+pub fn check_account_compliance<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<()> {
+    let mode = match get_mode(storage)? {
+        Some(mode) => mode,
+        None => return Ok(()),
+    };
+
+    let status = get_status(storage, account)?;
+    let passes = match mode {
+        ComplianceMode::Allowlist => status == ComplianceStatus::Allowed,
+        ComplianceMode::Denylist => status != ComplianceStatus::Denied,
+    };
+    if !passes {
+        return Err(generic_err("Transfer rejected by the token's compliance rules"));
+    }
+    Ok(())
+}
+
+/// Fails if the token screens transfers and `from` or `to` doesn't pass
+#[cfg(feature = "compliance")]
+pub fn check_compliance<S: ReadonlyStorage>(
+    storage: &S,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
+) -> StdResult<()> {
+    check_account_compliance(storage, from)?;
+    check_account_compliance(storage, to)
+}
+
+/// Admin only. Puts `addresses` on the allow or deny list, or takes them off with `unlisted`
+#[cfg(feature = "compliance")]
+pub fn try_set_compliance_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    addresses: Vec<HumanAddr>,
+    status: ComplianceStatus,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if get_mode(&deps.storage)?.is_none() {
+        return Err(generic_err("Compliance lists weren't enabled when the token was created"));
+    }
+
+    let addresses = addresses
+        .iter()
+        .map(|address| deps.api.canonical_address(address))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut store = VersionedStorage::new(PREFIX_COMPLIANCE_STATUS, &mut deps.storage);
+    for address in addresses.iter() {
+        match status {
+            ComplianceStatus::Unlisted => store.remove(address.as_slice()),
+            _ => TypedStoreMut::attach(&mut store).store(address.as_slice(), &status)?,
+        }
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_compliance_status"),
+            log("addresses", addresses.len()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+#[cfg(feature = "compliance")]
+pub fn query_compliance_status<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(address)?;
+    to_binary(&ComplianceStatusResponse {
+        mode: get_mode(&deps.storage)?,
+        status: get_status(&deps.storage, &address)?,
+    })
+}
+
+/// Builds without the `compliance` feature can't enable the lists, so every transfer passes
+#[cfg(not(feature = "compliance"))]
+mod disabled {
+    use cosmwasm_std::{
+        generic_err, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+        HumanAddr, Querier, ReadonlyStorage, StdResult, Storage,
+    };
+
+    use super::{ComplianceMode, ComplianceStatus, ComplianceStatusResponse};
+
+    pub fn init_compliance<S: Storage>(_storage: &mut S, _mode: ComplianceMode) -> StdResult<()> {
+        Err(generic_err("Compliance lists are not supported by this build of the token"))
+    }
+
+    pub fn check_account_compliance<S: ReadonlyStorage>(
+        _storage: &S,
+        _account: &CanonicalAddr,
+    ) -> StdResult<()> {
+        Ok(())
+    }
+
+    pub fn check_compliance<S: ReadonlyStorage>(
+        _storage: &S,
+        _from: &CanonicalAddr,
+        _to: &CanonicalAddr,
+    ) -> StdResult<()> {
+        Ok(())
+    }
+
+    pub fn try_set_compliance_status<S: Storage, A: Api, Q: Querier>(
+        _deps: &mut Extern<S, A, Q>,
+        _env: Env,
+        _addresses: Vec<HumanAddr>,
+        _status: ComplianceStatus,
+    ) -> StdResult<HandleResponse> {
+        Err(generic_err("Compliance lists are not supported by this build of the token"))
+    }
+
+    pub fn query_compliance_status<S: Storage, A: Api, Q: Querier>(
+        _deps: &Extern<S, A, Q>,
+        _address: &HumanAddr,
+    ) -> StdResult<Binary> {
+        to_binary(&ComplianceStatusResponse {
+            mode: None,
+            status: ComplianceStatus::Unlisted,
+        })
+    }
+}
+
+#[cfg(not(feature = "compliance"))]
+pub use disabled::*;
@@ -1,43 +1,83 @@
-use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
-use std::convert::TryInto;
-
-use crate::msg::{AllowanceResponse, BalanceResponse, HandleMsg, InitMsg, QueryMsg};
-use cosmwasm_std::{log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, generic_err, InitResponse, Querier, ReadonlyStorage, StdResult, Storage, Uint128, CosmosMsg, BankMsg, Coin, Decimal, QueryResult};
-use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
-use crate::utils::{ConstLenStr, ct_slice_compare, create_hashed_password};
+use crate::msg::{AccountBalance, AccountStatsResponse, ActiveSpendersResponse, AccountTotalsResponse, AllowanceResponse, BatchResponse, ContractStatus, ContractStatusResponse, DenomBacking, RewardsResponse, BalanceResponse, HandleMsg, InitMsg, PermitBalancesResponse, QueryAnswer, QueryMsg, QueryWithPermit, ReserveBackingResponse, SpenderAllowance, TransferAction, TransferCountResponse, TransfersResponse};
+use cosmwasm_std::{log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, generic_err, InitResponse, Querier, ReadonlyStorage, StdResult, Storage, Uint128, CosmosMsg, unauthorized, to_binary, BankMsg, Coin, QueryResult};
+use crate::utils::{ct_slice_compare, pad_response};
 use crate::viewing_key::{ViewingKey, API_KEY_LENGTH};
-use crate::state::{store_transfer, get_transfers};
-
-#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, JsonSchema)]
-pub struct Constants {
-    pub name: String,
-    pub symbol: String,
-    pub decimals: u8,
-}
-
-pub const PREFIX_CONFIG: &[u8] = b"config";
-pub const PREFIX_BALANCES: &[u8] = b"balances";
-pub const PREFIX_ALLOWANCES: &[u8] = b"allowances";
-pub const PREFIX_VIEW_KEY: &[u8] = b"viewingkey";
-pub const KEY_CONSTANTS: &[u8] = b"constants";
-pub const KEY_TOTAL_SUPPLY: &[u8] = b"total_supply";
+#[cfg(feature = "debug-supply")]
+use crate::state::take_balance_delta;
+use crate::state::{Balances, Config, VersionedStorage, Constants, ReadonlyBalances, ReadonlyConfig, read_allowance, read_allowance_spenders, update_allowance, remove_allowances, read_viewing_key, write_viewing_key, remove_viewing_key, PREFIX_VIEW_KEY, store_burn, store_deposit, store_mint, store_redeem, read_total_supply, write_total_supply, SupplyChange, store_transfer, store_transfer_from, store_transfer_with_memo, get_transfers, count_transfers, store_pending_transfer, get_pending_transfer, remove_pending_transfer, PendingTransfer, store_claim, get_claim, remove_claim, HashClaim, set_receiver_hash, get_receiver_hash, touch_transfers, prune_transfers, TxFilter, get_account_stats, get_account_totals, update_account_stats, read_reward_per_token, write_reward_per_token, pending_rewards, take_rewards, REWARD_SCALE, BURN_REFERENCE, REWARDS_REFERENCE, DEFAULT_DENOM};
+use crate::incoming::{check_incoming, query_incoming_transfers, try_block_incoming_transfers};
+use crate::receiver::{Snip20AllowanceMsg, Snip20ReceiveMsg};
+use crate::permit::{Permission, Permit};
+use crate::rand::{contract_rng, init_prng, sha_256, update_entropy_pool};
+use crate::attestation::{query_attestation, try_attest_funds};
+use crate::airdrop::{try_batch_airdrop, try_claim_airdrop, try_register_airdrop};
+use crate::governance::{query_voting_weight, try_set_governance_key};
+use crate::bridge::{try_bridge_burn, try_bridge_mint, try_set_bridge};
+use crate::channels::{try_close_channel, try_open_channel, try_settle_channel};
+use crate::clawback::{init_clawback, query_clawbacks, try_clawback};
+use crate::compliance::{check_account_compliance, check_compliance, init_compliance, query_compliance_status, try_set_compliance_status};
+use crate::cw20::{query_cw20, TokenInfoResponse};
+use crate::denom_ledger::{denom_balances, ledger_deposit, ledger_redeem, ledger_transfer, query_denom_balance, remove_denom_balances};
+use crate::display::{format_amount, Rounding};
+use crate::fees::{charge_fee, charge_fee_in_units, get_fees, query_collected_fees, query_fees, try_set_fees, try_set_treasury, try_withdraw_fees};
+use crate::freeze::{check_frozen, check_not_frozen, init_freeze, query_freeze_status, try_set_frozen};
+use crate::escrow::{try_create_escrow, try_refund_escrow, try_release_escrow};
+use crate::staking::{bond, get_staking_state, init_staking, query_exchange_rate, query_unbondings, try_claim_unbonded, try_collect_rewards, unbond};
+use crate::hooks::{transfer_hook_messages, try_register_transfer_hook, try_remove_transfer_hook};
+use crate::inheritance::{record_activity, try_claim_inheritance, try_set_beneficiary};
+use crate::limits::{enforce_transfer_limit, try_set_transfer_limit};
+use crate::marketing::{query_marketing_info, try_update_marketing, try_upload_logo};
+use crate::multisig::{check_transfer_policy, outflow, try_approve_transfer, try_cancel_proposed_transfer, try_propose_transfer, try_release_transfer_policy, try_set_transfer_policy};
+use crate::payment_requests::{try_create_payment_request, try_pay_request};
+use crate::pull::{try_authorize_pull, try_pull, try_revoke_pull};
+use crate::redeem_limit::{record_redeem, try_set_redeem_limit};
+use crate::recovery::{try_approve_recovery, try_cancel_recovery, try_execute_recovery, try_set_guardians};
+use crate::send_whitelist::{check_send_whitelist, query_send_whitelist, try_set_send_whitelist};
+use crate::routing::{try_report_unspent, try_settle_call, try_transfer_and_call};
+use crate::signed::{query_nonce, try_execute_signed, try_permit_allowance};
+use crate::streams::{try_cancel_stream, try_create_stream, try_withdraw_from_stream};
+use crate::timelock::{query_locked_balance, release_unlocked, try_transfer_locked};
+use crate::vesting::{query_vesting_schedules, try_claim_vested, try_transfer_vesting};
+use crate::wrapped::{try_receive, try_register_wrapped_token, try_unwrap_token};
+
+pub const KEY_DUMMY: &[u8] = b"dummy";
+
+pub const DEFAULT_PAGE_SIZE: u32 = 10;
+
+/// Most history entries a `Transfers` query looks at, so that a filter few transfers pass or a
+/// huge page size can't make the query run out of gas
+pub const MAX_TRANSFERS_READ: u32 = 1000;
+
+/// Native coins have 6 decimals. Deposits and withdrawals convert between those and the token's
+pub const NATIVE_DECIMALS: u8 = 6;
+
+/// Most permits a `PermitBalances` query checks, each costs a signature verification
+pub const MAX_BALANCE_PERMITS: usize = 16;
+
+/// Longest memo a batch transfer action can carry, in bytes
+pub const MAX_MEMO_LENGTH: usize = 256;
+
+/// Every handle and query response, log value and data is padded to a multiple of this many
+/// bytes
+pub const RESPONSE_BLOCK_SIZE: usize = 256;
 
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
     let mut total_supply: u128 = 0;
     {
         // Initial balances
-        let mut balances_store = PrefixedStorage::new(PREFIX_BALANCES, &mut deps.storage);
+        let mut balances = Balances::from_storage(&mut deps.storage);
         for row in msg.initial_balances {
             let raw_address = deps.api.canonical_address(&row.address)?;
             let amount_raw = row.amount.u128();
-            balances_store.set(raw_address.as_slice(), &amount_raw.to_be_bytes());
-            total_supply += amount_raw;
+            balances.set_balance(&raw_address, amount_raw)?;
+            total_supply = total_supply
+                .checked_add(amount_raw)
+                .ok_or_else(|| generic_err("Initial balances overflow the total supply"))?;
         }
     }
 
@@ -55,17 +95,80 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     if msg.decimals > 18 {
         return Err(generic_err("Decimals must not exceed 18"));
     }
+    if msg.history_limit == Some(0) {
+        return Err(generic_err("History limit must be at least 1"));
+    }
+    if msg.staking.is_some() && total_supply != 0 {
+        return Err(generic_err(
+            "Initial balances can't be used in staking mode, they aren't backed by delegations",
+        ));
+    }
+    let supported_denoms = msg
+        .supported_denoms
+        .unwrap_or_else(|| vec![DEFAULT_DENOM.to_string()]);
+    if supported_denoms.is_empty() {
+        return Err(generic_err("At least one denom must be supported"));
+    }
+    for (i, denom) in supported_denoms.iter().enumerate() {
+        if denom.is_empty() || supported_denoms[..i].contains(denom) {
+            return Err(generic_err(format!("Invalid or repeated denom: {:?}", denom)));
+        }
+    }
+    if msg.staking.is_some() && supported_denoms != [DEFAULT_DENOM] {
+        return Err(generic_err("Staking mode only supports uscrt"));
+    }
+    if msg.staking.is_some() && msg.decimals != NATIVE_DECIMALS {
+        return Err(generic_err(format!(
+            "Staking mode tokens must have {} decimals, like uscrt",
+            NATIVE_DECIMALS
+        )));
+    }
 
-    let mut config_store = PrefixedStorage::new(PREFIX_CONFIG, &mut deps.storage);
-    let constants = bincode2::serialize(&Constants {
+    let mut config = Config::from_storage(&mut deps.storage);
+    config.set_constants(&Constants {
         name: msg.name,
         symbol: msg.symbol,
         decimals: msg.decimals,
-    }).unwrap();
-    config_store.set(KEY_CONSTANTS, &constants);
-    config_store.set(KEY_TOTAL_SUPPLY, &total_supply.to_be_bytes());
+    })?;
+    config.set_total_supply(total_supply);
+    config.set_history_limit(msg.history_limit);
+    config.set_supported_denoms(&supported_denoms)?;
+
+    let admin = match msg.admin {
+        Some(admin) => deps.api.canonical_address(&admin)?,
+        None => env.message.sender.clone(),
+    };
+    let mut config = Config::from_storage(&mut deps.storage);
+    config.set_admin(&admin);
+    config.set_contract_address(&env.contract.address);
+    init_prng(
+        &mut deps.storage,
+        &env,
+        msg.prng_seed.as_ref().map(|seed| seed.as_slice()),
+    );
+
+    if let Some(staking) = msg.staking {
+        init_staking(&mut deps.storage, staking)?;
+    }
+    if let Some(mode) = msg.compliance {
+        init_compliance(&mut deps.storage, mode)?;
+    }
+    if msg.clawback == Some(true) {
+        init_clawback(&mut deps.storage);
+    }
+    if msg.freezing == Some(true) {
+        init_freeze(&mut deps.storage);
+    }
+
+    let messages = match msg.callback {
+        Some(callback) => vec![callback.into_cosmos_msg()],
+        None => vec![],
+    };
 
-    Ok(InitResponse::default())
+    Ok(InitResponse {
+        messages,
+        log: vec![],
+    })
 }
 
 pub fn handle<S: Storage, A: Api, Q: Querier>(
@@ -73,61 +176,873 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
-    match msg {
-        HandleMsg::Withdraw { amount } => try_withdraw(deps, env, amount),
+    let footprint = StorageFootprint::of(&msg);
+    let sender = env.message.sender.clone();
+
+    if is_disabled_when_redeem_only(&msg) && ReadonlyConfig::from_storage(&deps.storage).redeem_only() {
+        return Err(generic_err(
+            "This token is being wound down, only withdrawals and settling existing commitments are possible",
+        ));
+    }
+
+    // balances moved into escrows and the like since the last supply change aren't this message's
+    #[cfg(feature = "debug-supply")]
+    take_balance_delta(&mut deps.storage);
+
+    // tokens instantiated before the address was stored at init
+    if ReadonlyConfig::from_storage(&deps.storage).contract_address().is_err() {
+        Config::from_storage(&mut deps.storage).set_contract_address(&env.contract.address);
+    }
+    update_entropy_pool(&mut deps.storage, &env);
+
+    release_unlocked(&mut deps.storage, &sender, env.block.time)?;
+    record_activity(&mut deps.storage, &sender, env.block.time)?;
+    check_transfer_policy(deps, &env, &msg)?;
+    enforce_transfer_limit(deps, &env, &msg)?;
+    // checked here rather than per handler, so no way of getting funds out is left unchecked
+    if let Some(owner) = debited_account(deps, &sender, &msg)? {
+        check_account_compliance(&deps.storage, &owner)?;
+        check_not_frozen(&deps.storage, &owner)?;
+    }
+
+    let response = match msg {
+        HandleMsg::Withdraw { amount, denom } => try_withdraw(deps, env, amount, denom),
+        HandleMsg::RedeemAndSend {
+            recipient,
+            amount,
+            denom,
+        } => try_redeem_and_send(deps, env, &recipient, amount, denom),
+        HandleMsg::CloseAccount {
+            redeem_to,
+            spenders,
+        } => try_close_account(deps, env, redeem_to, spenders.unwrap_or_default()),
         HandleMsg::Deposit {} => try_deposit(deps, env),
         HandleMsg::Balance {} => try_balance(deps, env),
         HandleMsg::Allowance {spender} => try_check_allowance(deps, env, spender),
-        HandleMsg::Approve { spender, amount } => try_approve(deps, env, &spender, &amount),
+        HandleMsg::Approve {
+            spender,
+            amount,
+            expected_current,
+            notify,
+        } => try_approve(
+            deps,
+            env,
+            &spender,
+            &amount,
+            expected_current,
+            notify.unwrap_or(false),
+        ),
         HandleMsg::Transfer { recipient, amount } => try_transfer(deps, env, &recipient, &amount),
+        HandleMsg::BatchTransfer { actions } => try_batch_transfer(deps, env, actions),
+        HandleMsg::WrapAndTransfer { recipient, amount } => {
+            try_wrap_and_transfer(deps, env, &recipient, &amount, None)
+        }
+        HandleMsg::WrapAndSend {
+            recipient,
+            recipient_code_hash,
+            amount,
+            msg,
+        } => try_wrap_and_transfer(
+            deps,
+            env,
+            &recipient,
+            &amount,
+            Some((recipient_code_hash, msg)),
+        ),
+        HandleMsg::Send {
+            recipient,
+            recipient_code_hash,
+            amount,
+            msg,
+        } => try_send(deps, env, &recipient, recipient_code_hash, &amount, msg),
+        HandleMsg::TransferAndCall {
+            recipient,
+            recipient_code_hash,
+            amount,
+            msg,
+            code_hash,
+        } => try_transfer_and_call(
+            deps,
+            env,
+            &recipient,
+            recipient_code_hash,
+            &amount,
+            msg,
+            code_hash,
+        ),
+        HandleMsg::ReportUnspent { amount } => try_report_unspent(deps, env, amount),
+        HandleMsg::SettleCall { id } => try_settle_call(deps, env, id),
+        HandleMsg::RegisterReceive { code_hash } => try_register_receive(deps, env, code_hash),
         HandleMsg::TransferFrom {
             owner,
             recipient,
             amount,
         } => try_transfer_from(deps, env, &owner, &recipient, &amount),
+        HandleMsg::SendFrom {
+            owner,
+            recipient,
+            recipient_code_hash,
+            amount,
+            msg,
+        } => try_send_from(deps, env, &owner, &recipient, recipient_code_hash, &amount, msg),
         HandleMsg::Burn { amount } => try_burn(deps, env, &amount),
+        HandleMsg::TransferPending {
+            recipient,
+            amount,
+            expiry,
+        } => try_transfer_pending(deps, env, &recipient, &amount, expiry),
+        HandleMsg::ClaimTransfer { id } => try_claim_transfer(deps, env, id),
+        HandleMsg::CancelTransfer { id } => try_cancel_transfer(deps, env, id),
+        HandleMsg::CreateClaim {
+            hash,
+            amount,
+            expiry,
+        } => try_create_claim(deps, env, &hash, &amount, expiry),
+        HandleMsg::RedeemClaim { preimage } => try_redeem_claim(deps, env, preimage),
+        HandleMsg::TransferVesting {
+            recipient,
+            amount,
+            cliff,
+            duration,
+        } => try_transfer_vesting(deps, env, &recipient, &amount, cliff, duration),
+        HandleMsg::ClaimVested {} => try_claim_vested(deps, env),
+        HandleMsg::TransferLocked {
+            recipient,
+            amount,
+            unlock_at,
+        } => try_transfer_locked(deps, env, &recipient, &amount, unlock_at),
+        HandleMsg::CreateEscrow {
+            payee,
+            arbiter,
+            amount,
+            expiry,
+        } => try_create_escrow(deps, env, &payee, &arbiter, &amount, expiry),
+        HandleMsg::ReleaseEscrow { id } => try_release_escrow(deps, env, id),
+        HandleMsg::RefundEscrow { id } => try_refund_escrow(deps, env, id),
+        HandleMsg::OpenChannel {
+            recipient,
+            amount,
+            expiry,
+        } => try_open_channel(deps, env, &recipient, &amount, expiry),
+        HandleMsg::SettleChannel {
+            id,
+            amount,
+            signature,
+            pub_key,
+        } => try_settle_channel(deps, env, id, amount, &signature, &pub_key),
+        HandleMsg::CloseChannel { id } => try_close_channel(deps, env, id),
+        HandleMsg::CreateStream {
+            recipient,
+            amount,
+            start,
+            end,
+        } => try_create_stream(deps, env, &recipient, &amount, start, end),
+        HandleMsg::WithdrawFromStream { id } => try_withdraw_from_stream(deps, env, id),
+        HandleMsg::CancelStream { id } => try_cancel_stream(deps, env, id),
+        HandleMsg::CreatePaymentRequest {
+            amount,
+            reference,
+            expiry,
+        } => try_create_payment_request(deps, env, amount, reference, expiry),
+        HandleMsg::PayRequest { id } => try_pay_request(deps, env, id),
+        HandleMsg::AuthorizePull {
+            contract,
+            max_amount,
+            frequency,
+        } => try_authorize_pull(deps, env, &contract, max_amount, frequency),
+        HandleMsg::RevokePull { contract } => try_revoke_pull(deps, env, &contract),
+        HandleMsg::Pull { owner, amount } => try_pull(deps, env, &owner, amount),
+        HandleMsg::ClaimUnbonded {} => try_claim_unbonded(deps, env),
+        HandleMsg::CollectRewards {} => try_collect_rewards(deps, env),
+        HandleMsg::DistributeRewards {} => try_distribute_rewards(deps, env),
+        HandleMsg::ClaimRewards {} => try_claim_rewards(deps, env),
+        HandleMsg::RegisterAirdrop {
+            merkle_root,
+            amount,
+        } => try_register_airdrop(deps, env, merkle_root, amount),
+        HandleMsg::ClaimAirdrop {
+            index,
+            proof,
+            amount,
+        } => try_claim_airdrop(deps, env, index, proof, amount),
+        HandleMsg::Airdrop { recipients, amount } => try_batch_airdrop(deps, env, recipients, amount),
+        HandleMsg::SetTransferPolicy {
+            co_signer,
+            threshold,
+        } => try_set_transfer_policy(deps, env, &co_signer, threshold),
+        HandleMsg::ReleaseTransferPolicy { account } => {
+            try_release_transfer_policy(deps, env, &account)
+        }
+        HandleMsg::ProposeTransfer { recipient, amount } => {
+            try_propose_transfer(deps, env, &recipient, amount)
+        }
+        HandleMsg::ApproveTransfer { id } => try_approve_transfer(deps, env, id),
+        HandleMsg::CancelProposedTransfer { id } => try_cancel_proposed_transfer(deps, env, id),
+        HandleMsg::SetGuardians {
+            guardians,
+            quorum,
+            delay,
+        } => try_set_guardians(deps, env, guardians, quorum, delay),
+        HandleMsg::ApproveRecovery {
+            account,
+            recovery_address,
+            viewing_key,
+        } => try_approve_recovery(deps, env, &account, &recovery_address, viewing_key),
+        HandleMsg::CancelRecovery {} => try_cancel_recovery(deps, env),
+        HandleMsg::ExecuteRecovery { account } => try_execute_recovery(deps, env, &account),
+        HandleMsg::SetTransferLimit { amount_per_day } => {
+            try_set_transfer_limit(deps, env, amount_per_day)
+        }
+        HandleMsg::SetSendWhitelist { contracts } => try_set_send_whitelist(deps, env, contracts),
+        HandleMsg::BlockIncomingTransfers {
+            block,
+            allowed_senders,
+        } => try_block_incoming_transfers(deps, env, block, allowed_senders.unwrap_or_default()),
+        HandleMsg::SetBeneficiary {
+            address,
+            inactivity_period,
+        } => try_set_beneficiary(deps, env, address, inactivity_period),
+        HandleMsg::ClaimInheritance { owner } => try_claim_inheritance(deps, env, &owner),
+        HandleMsg::SetBridge { address } => try_set_bridge(deps, env, address),
+        HandleMsg::BridgeMint {
+            recipient,
+            amount,
+            tx_hash,
+        } => try_bridge_mint(deps, env, &recipient, amount, tx_hash),
+        HandleMsg::BridgeBurn {
+            amount,
+            external_recipient,
+        } => try_bridge_burn(deps, env, amount, external_recipient),
+        HandleMsg::RegisterTransferHook {
+            contract,
+            code_hash,
+            threshold,
+        } => try_register_transfer_hook(deps, env, contract, code_hash, threshold),
+        HandleMsg::RemoveTransferHook { contract } => try_remove_transfer_hook(deps, env, contract),
+        HandleMsg::AttestFunds { min_amount } => try_attest_funds(deps, env, min_amount),
+        HandleMsg::SetComplianceStatus { addresses, status } => {
+            try_set_compliance_status(deps, env, addresses, status)
+        }
+        HandleMsg::FreezeAccount { account } => try_set_frozen(deps, env, &account, true),
+        HandleMsg::UnfreezeAccount { account } => try_set_frozen(deps, env, &account, false),
+        HandleMsg::Clawback {
+            owner,
+            recipient,
+            amount,
+            reason,
+        } => try_clawback(deps, env, &owner, &recipient, amount, reason),
+        HandleMsg::RegisterWrappedToken {
+            contract,
+            code_hash,
+            decimals,
+            own_code_hash,
+        } => try_register_wrapped_token(deps, env, contract, code_hash, decimals, own_code_hash),
+        HandleMsg::Receive { from, amount, .. } => try_receive(deps, env, &from, amount),
+        HandleMsg::UnwrapToken { token, amount } => try_unwrap_token(deps, env, token, amount),
+        HandleMsg::ExecuteSigned {
+            transfer,
+            nonce,
+            signature,
+        } => try_execute_signed(deps, env, transfer, nonce, signature),
+        HandleMsg::PermitAllowance {
+            owner,
+            spender,
+            amount,
+            deadline,
+            signature,
+        } => try_permit_allowance(deps, env, &owner, &spender, amount, deadline, signature),
+        HandleMsg::SetRedeemLimit { max_amount, period } => {
+            try_set_redeem_limit(deps, env, max_amount, period)
+        }
+        HandleMsg::UpdateMarketing {
+            project,
+            description,
+        } => try_update_marketing(deps, env, project, description),
+        HandleMsg::UploadLogo { logo } => try_upload_logo(deps, env, logo),
+        HandleMsg::PermanentRedeemOnly {} => try_permanent_redeem_only(deps, env),
+        HandleMsg::SetFees {
+            deposit_fee_bps,
+            redeem_fee_bps,
+        } => try_set_fees(deps, env, deposit_fee_bps, redeem_fee_bps),
+        HandleMsg::SetTreasury { treasury } => try_set_treasury(deps, env, &treasury),
+        HandleMsg::WithdrawFees { recipient, amount } => {
+            try_withdraw_fees(deps, env, &recipient, amount)
+        }
+        HandleMsg::RenameDenom { denom, new_denom } => {
+            try_rename_denom(deps, env, denom, new_denom)
+        }
+        HandleMsg::ChangeAdmin { address } => try_change_admin(deps, env, &address),
+        HandleMsg::SetGovernanceKey { contract, key } => {
+            try_set_governance_key(deps, env, &contract, key)
+        }
         HandleMsg::CreateViewingKey { entropy } => try_create_key(deps, env, entropy),
         HandleMsg::SetViewingKey { key } => try_set_key(deps, env, key),
+        HandleMsg::PruneHistory { keep_last } => try_prune_history(deps, env, keep_last),
+    };
+
+    if response.is_ok() {
+        pad_storage_access(&mut deps.storage, &sender, &footprint)?;
     }
+
+    pad_response(response, RESPONSE_BLOCK_SIZE)
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
+/// The account whose balance `msg` debits, if any. Besides the outflows transfer policies limit,
+/// that's the admin's balance when it funds airdrops. Moves that other messages settle, like
+/// inheritances or co-signed proposals, go through `perform_transfer`, which checks both sides
+fn debited_account<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
+    sender: &CanonicalAddr,
+    msg: &HandleMsg,
+) -> StdResult<Option<CanonicalAddr>> {
+    match msg {
+        HandleMsg::RegisterAirdrop { .. } | HandleMsg::Airdrop { .. } => Ok(Some(sender.clone())),
+        _ => Ok(outflow(deps, sender, msg)?.map(|(owner, _)| owner)),
+    }
+}
 
-    let (address, key) = msg.get_validation_params();
+/// Whether `msg` deposits, mints or starts moving tokens between accounts, which a token in
+/// redeem-only mode doesn't allow anymore. Settling what was set aside before, account settings
+/// and getting tokens out of the contract keep working
+fn is_disabled_when_redeem_only(msg: &HandleMsg) -> bool {
+    match msg {
+        HandleMsg::Deposit { .. }
+        | HandleMsg::Transfer { .. }
+        | HandleMsg::BatchTransfer { .. }
+        | HandleMsg::Send { .. }
+        | HandleMsg::TransferAndCall { .. }
+        | HandleMsg::WrapAndTransfer { .. }
+        | HandleMsg::WrapAndSend { .. }
+        | HandleMsg::TransferFrom { .. }
+        | HandleMsg::SendFrom { .. }
+        | HandleMsg::ExecuteSigned { .. }
+        | HandleMsg::TransferPending { .. }
+        | HandleMsg::CreateClaim { .. }
+        | HandleMsg::TransferVesting { .. }
+        | HandleMsg::TransferLocked { .. }
+        | HandleMsg::CreateEscrow { .. }
+        | HandleMsg::OpenChannel { .. }
+        | HandleMsg::CreateStream { .. }
+        | HandleMsg::CreatePaymentRequest { .. }
+        | HandleMsg::PayRequest { .. }
+        | HandleMsg::AuthorizePull { .. }
+        | HandleMsg::Pull { .. }
+        | HandleMsg::DistributeRewards { .. }
+        | HandleMsg::RegisterAirdrop { .. }
+        | HandleMsg::ClaimAirdrop { .. }
+        | HandleMsg::Airdrop { .. }
+        | HandleMsg::ProposeTransfer { .. }
+        | HandleMsg::ApproveTransfer { .. }
+        | HandleMsg::BridgeMint { .. }
+        | HandleMsg::Receive { .. } => true,
+        HandleMsg::Withdraw { .. }
+        | HandleMsg::RedeemAndSend { .. }
+        | HandleMsg::CloseAccount { .. }
+        | HandleMsg::Approve { .. }
+        | HandleMsg::PermitAllowance { .. }
+        | HandleMsg::ReportUnspent { .. }
+        | HandleMsg::SettleCall { .. }
+        | HandleMsg::RegisterReceive { .. }
+        | HandleMsg::Burn { .. }
+        | HandleMsg::Balance { .. }
+        | HandleMsg::Allowance { .. }
+        | HandleMsg::ClaimTransfer { .. }
+        | HandleMsg::CancelTransfer { .. }
+        | HandleMsg::RedeemClaim { .. }
+        | HandleMsg::ClaimVested { .. }
+        | HandleMsg::ReleaseEscrow { .. }
+        | HandleMsg::RefundEscrow { .. }
+        | HandleMsg::SettleChannel { .. }
+        | HandleMsg::CloseChannel { .. }
+        | HandleMsg::WithdrawFromStream { .. }
+        | HandleMsg::CancelStream { .. }
+        | HandleMsg::RevokePull { .. }
+        | HandleMsg::ClaimUnbonded { .. }
+        | HandleMsg::CollectRewards { .. }
+        | HandleMsg::ClaimRewards { .. }
+        | HandleMsg::SetTransferPolicy { .. }
+        | HandleMsg::ReleaseTransferPolicy { .. }
+        | HandleMsg::CancelProposedTransfer { .. }
+        | HandleMsg::SetGuardians { .. }
+        | HandleMsg::ApproveRecovery { .. }
+        | HandleMsg::CancelRecovery { .. }
+        | HandleMsg::ExecuteRecovery { .. }
+        | HandleMsg::SetTransferLimit { .. }
+        | HandleMsg::SetSendWhitelist { .. }
+        | HandleMsg::BlockIncomingTransfers { .. }
+        | HandleMsg::SetBeneficiary { .. }
+        | HandleMsg::ClaimInheritance { .. }
+        | HandleMsg::SetBridge { .. }
+        | HandleMsg::BridgeBurn { .. }
+        | HandleMsg::RegisterWrappedToken { .. }
+        | HandleMsg::UnwrapToken { .. }
+        | HandleMsg::RegisterTransferHook { .. }
+        | HandleMsg::RemoveTransferHook { .. }
+        | HandleMsg::AttestFunds { .. }
+        | HandleMsg::SetComplianceStatus { .. }
+        | HandleMsg::FreezeAccount { .. }
+        | HandleMsg::UnfreezeAccount { .. }
+        | HandleMsg::Clawback { .. }
+        | HandleMsg::SetRedeemLimit { .. }
+        | HandleMsg::UpdateMarketing { .. }
+        | HandleMsg::UploadLogo { .. }
+        | HandleMsg::PermanentRedeemOnly { .. }
+        | HandleMsg::SetFees { .. }
+        | HandleMsg::SetTreasury { .. }
+        | HandleMsg::WithdrawFees { .. }
+        | HandleMsg::RenameDenom { .. }
+        | HandleMsg::ChangeAdmin { .. }
+        | HandleMsg::SetGovernanceKey { .. }
+        | HandleMsg::SetViewingKey { .. }
+        | HandleMsg::PruneHistory { .. }
+        | HandleMsg::CreateViewingKey { .. } => false,
+    }
+}
 
-    let canonical_addr = deps.api.canonical_address(address)?;
+/// The storage namespaces a handle message writes to
+///
+/// Every handle message ends up touching all of these, the ones a message doesn't need get
+/// rewritten with their current value in `pad_storage_access`. This way the storage access trace
+/// of a transaction doesn't give away which message was executed
+#[derive(Default)]
+struct StorageFootprint {
+    balances: bool,
+    allowances: bool,
+    history: bool,
+    viewing_keys: bool,
+}
 
-    let expected_key = read_viewing_key(&deps.storage, &canonical_addr);
+impl StorageFootprint {
+    fn of(msg: &HandleMsg) -> Self {
+        let mut footprint = Self::default();
+        match msg {
+            HandleMsg::Withdraw { .. }
+            | HandleMsg::RedeemAndSend { .. }
+            | HandleMsg::Deposit { .. }
+            | HandleMsg::Burn { .. }
+            | HandleMsg::TransferPending { .. }
+            | HandleMsg::CancelTransfer { .. }
+            | HandleMsg::ProposeTransfer { .. }
+            | HandleMsg::CancelProposedTransfer { .. }
+            | HandleMsg::CreateClaim { .. }
+            | HandleMsg::TransferVesting { .. }
+            | HandleMsg::CreateEscrow { .. }
+            | HandleMsg::RefundEscrow { .. }
+            | HandleMsg::OpenChannel { .. }
+            | HandleMsg::CloseChannel { .. }
+            | HandleMsg::CreateStream { .. }
+            | HandleMsg::ClaimRewards { .. }
+            | HandleMsg::RegisterAirdrop { .. }
+            | HandleMsg::Airdrop { .. } => {
+                footprint.balances = true;
+            }
+            HandleMsg::Transfer { .. }
+            | HandleMsg::BatchTransfer { .. }
+            | HandleMsg::Send { .. }
+            | HandleMsg::TransferAndCall { .. }
+            | HandleMsg::SettleCall { .. }
+            | HandleMsg::WrapAndTransfer { .. }
+            | HandleMsg::WrapAndSend { .. }
+            | HandleMsg::ClaimTransfer { .. }
+            | HandleMsg::RedeemClaim { .. }
+            | HandleMsg::ClaimVested { .. }
+            | HandleMsg::TransferLocked { .. }
+            | HandleMsg::ReleaseEscrow { .. }
+            | HandleMsg::SettleChannel { .. }
+            | HandleMsg::WithdrawFromStream { .. }
+            | HandleMsg::CancelStream { .. }
+            | HandleMsg::PayRequest { .. }
+            | HandleMsg::BridgeMint { .. }
+            | HandleMsg::BridgeBurn { .. }
+            | HandleMsg::Receive { .. }
+            | HandleMsg::UnwrapToken { .. }
+            | HandleMsg::Pull { .. }
+            | HandleMsg::ClaimAirdrop { .. }
+            | HandleMsg::ExecuteSigned { .. }
+            | HandleMsg::ApproveTransfer { .. }
+            | HandleMsg::Clawback { .. }
+            | HandleMsg::ClaimInheritance { .. } => {
+                footprint.balances = true;
+                footprint.history = true;
+            }
+            HandleMsg::ExecuteRecovery { .. } => {
+                footprint.balances = true;
+                footprint.history = true;
+                footprint.viewing_keys = true;
+            }
+            HandleMsg::CloseAccount { .. } => {
+                footprint.balances = true;
+                footprint.allowances = true;
+                footprint.history = true;
+                footprint.viewing_keys = true;
+            }
+            HandleMsg::PruneHistory { .. } => {
+                footprint.history = true;
+            }
+            HandleMsg::TransferFrom { .. } | HandleMsg::SendFrom { .. } => {
+                footprint.balances = true;
+                footprint.allowances = true;
+                footprint.history = true;
+            }
+            HandleMsg::Approve { .. } | HandleMsg::PermitAllowance { .. } => {
+                footprint.allowances = true;
+            }
+            HandleMsg::SetViewingKey { .. } | HandleMsg::CreateViewingKey { .. } => {
+                footprint.viewing_keys = true;
+            }
+            HandleMsg::Balance { .. }
+            | HandleMsg::Allowance { .. }
+            | HandleMsg::RegisterReceive { .. }
+            | HandleMsg::ClaimUnbonded { .. }
+            | HandleMsg::CollectRewards { .. }
+            | HandleMsg::DistributeRewards { .. }
+            | HandleMsg::SetFees { .. }
+            | HandleMsg::SetTreasury { .. }
+            | HandleMsg::WithdrawFees { .. }
+            | HandleMsg::RenameDenom { .. }
+            | HandleMsg::ChangeAdmin { .. }
+            | HandleMsg::SetGovernanceKey { .. }
+            | HandleMsg::SetTransferPolicy { .. }
+            | HandleMsg::ReleaseTransferPolicy { .. }
+            | HandleMsg::SetGuardians { .. }
+            | HandleMsg::ApproveRecovery { .. }
+            | HandleMsg::CancelRecovery { .. }
+            | HandleMsg::SetBeneficiary { .. }
+            | HandleMsg::CreatePaymentRequest { .. }
+            | HandleMsg::SetBridge { .. }
+            | HandleMsg::RegisterWrappedToken { .. }
+            | HandleMsg::RegisterTransferHook { .. }
+            | HandleMsg::RemoveTransferHook { .. }
+            | HandleMsg::AuthorizePull { .. }
+            | HandleMsg::RevokePull { .. }
+            | HandleMsg::AttestFunds { .. }
+            | HandleMsg::SetComplianceStatus { .. }
+            | HandleMsg::FreezeAccount { .. }
+            | HandleMsg::UnfreezeAccount { .. }
+            | HandleMsg::ReportUnspent { .. }
+            | HandleMsg::SetTransferLimit { .. }
+            | HandleMsg::SetSendWhitelist { .. }
+            | HandleMsg::BlockIncomingTransfers { .. }
+            | HandleMsg::SetRedeemLimit { .. }
+            | HandleMsg::UpdateMarketing { .. }
+            | HandleMsg::UploadLogo { .. }
+            | HandleMsg::PermanentRedeemOnly { .. } => {}
+        }
+        footprint
+    }
+}
 
-    // checking the key will take significant time. We don't want to exit immediately if it isn't set
-    // in a way which will allow to time the command and determine if a viewing key doesn't exist
-    if let None = expected_key {
-        if !key.check_viewing_key(&[0u8; 24]) {
-            return Ok(Binary(b"Wrong viewing key for this address or viewing key not set".to_vec()));
+/// Reads and rewrites the sender's entry in every namespace the executed message didn't write to
+fn pad_storage_access<S: Storage>(
+    store: &mut S,
+    sender: &CanonicalAddr,
+    footprint: &StorageFootprint,
+) -> StdResult<()> {
+    if !footprint.balances {
+        let mut balances = Balances::from_storage(store);
+        let balance = balances.balance(sender)?;
+        balances.set_balance(sender, balance)?;
+    }
+    if !footprint.allowances {
+        update_allowance(store, sender, sender, Ok)?;
+    }
+    if !footprint.history {
+        touch_transfers(store, sender)?;
+    }
+    if !footprint.viewing_keys {
+        let mut vk_store = VersionedStorage::new(PREFIX_VIEW_KEY, store);
+        match vk_store.get(sender.as_slice()) {
+            Some(hashed) => vk_store.set(sender.as_slice(), &hashed),
+            // don't create a viewing key for accounts that never set one
+            None => vk_store.set(KEY_DUMMY, &NO_VIEWING_KEY),
         }
     }
+    Ok(())
+}
+
+/// Public queries are answered without looking at viewing keys, every other one goes through
+/// `authenticate` first
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    pad_response(route_query(deps, msg), RESPONSE_BLOCK_SIZE)
+}
+
+fn route_query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    if let Some(answer) = public_query(deps, &msg) {
+        return answer;
+    }
+
+    if !authenticate(deps, &msg)? {
+        return viewing_key_error();
+    }
 
-    if !key.check_viewing_key(expected_key.unwrap().as_slice()) {
-        return Ok(Binary(b"Wrong viewing key for this address or viewing key not set".to_vec()));
+    match msg {
+        QueryMsg::Batch { address, queries, .. } => query_batch(deps, &address, queries),
+        msg => authenticated_query(deps, msg),
     }
+}
+
+/// Whether the viewing key of a query that takes one is right. The one place they're checked
+fn authenticate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: &QueryMsg,
+) -> StdResult<bool> {
+    let (address, key) = msg.get_validation_params()?;
+    let address = deps.api.canonical_address(address)?;
+    Ok(is_viewing_key_valid(&deps.storage, &address, &key))
+}
+
+/// Stands in for the viewing key of an account without one, the size of a hashed key
+const NO_VIEWING_KEY: [u8; 32] = [0u8; 32];
+
+/// Whether `key` is `address`'s viewing key
+pub fn is_viewing_key_valid<S: ReadonlyStorage>(
+    storage: &S,
+    address: &CanonicalAddr,
+    key: &ViewingKey,
+) -> bool {
+    // the same read, hashes and compares happen whether the key is set or not, so neither the time nor the gas of a query tells if it exists
+    let stored_key = read_viewing_key(storage, address);
+    let expected_key = stored_key.as_deref().unwrap_or(&NO_VIEWING_KEY);
+    let matches = key.check_viewing_key(expected_key);
+    matches & stored_key.is_some()
+}
+
+pub fn viewing_key_error() -> StdResult<Binary> {
+    to_binary(&QueryAnswer::ViewingKeyError {
+        msg: "Wrong viewing key for this address or viewing key not set".to_string(),
+    })
+}
+
+/// Answers the queries that don't authenticate with a viewing key
+fn public_query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: &QueryMsg,
+) -> Option<StdResult<Binary>> {
+    let answer = match msg {
+        QueryMsg::TokenInfo {} => query_token_info(&deps),
+        QueryMsg::ContractStatus {} => query_contract_status(&deps),
+        QueryMsg::ReserveBacking {} => query_reserve_backing(&deps),
+        QueryMsg::ExchangeRate {} => query_exchange_rate(&deps),
+        QueryMsg::Fees {} => query_fees(&deps),
+        QueryMsg::MarketingInfo {} => query_marketing_info(&deps),
+        QueryMsg::Cw20 { query, auth } => query_cw20(&deps, query, auth.as_ref()),
+        QueryMsg::ComplianceStatus { address } => query_compliance_status(&deps, address),
+        QueryMsg::FreezeStatus { address } => query_freeze_status(&deps, address),
+        QueryMsg::Clawbacks { page, page_size } => query_clawbacks(
+            &deps,
+            page.unwrap_or(0),
+            page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+        ),
+        QueryMsg::Nonce { address } => query_nonce(&deps, address),
+        QueryMsg::Attestation { id } => query_attestation(&deps, id),
+        QueryMsg::PermitBalances { permits } => query_permit_balances(&deps, permits),
+        QueryMsg::WithPermit { permit, query } => query_with_permit(&deps, permit, query),
+        QueryMsg::VotingWeight {
+            permit,
+            governance_contract,
+            governance_key,
+        } => query_voting_weight(&deps, permit, governance_contract, governance_key),
+        _ => return None,
+    };
+    Some(answer)
+}
+
+pub fn query_token_info<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let config = ReadonlyConfig::from_storage(&deps.storage);
+    let constants = config.constants()?;
+    to_binary(&TokenInfoResponse {
+        name: constants.name,
+        symbol: constants.symbol,
+        decimals: constants.decimals,
+        total_supply: Uint128(config.total_supply()?),
+    })
+}
+
+fn query_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let status = if ReadonlyConfig::from_storage(&deps.storage).redeem_only() {
+        ContractStatus::RedeemOnly
+    } else {
+        ContractStatus::Normal
+    };
+    to_binary(&ContractStatusResponse { status })
+}
+
+fn query_reserve_backing<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let config = ReadonlyConfig::from_storage(&deps.storage);
+    let contract = deps.api.human_address(&config.contract_address()?)?;
+
+    let denoms = config
+        .supported_denoms()?
+        .into_iter()
+        .map(|denom| {
+            let balance = deps.querier.query_balance(contract.clone(), &denom)?.amount;
+            let reserve = Uint128(config.reserve(&denom)?);
+            Ok(DenomBacking {
+                denom,
+                balance,
+                reserve,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let total_supply = config.total_supply()?;
+    let whole_units = total_supply - total_supply % tokens_per_native(&deps.storage)?;
+    to_binary(&ReserveBackingResponse {
+        denoms,
+        total_supply_native: Uint128(tokens_to_native(&deps.storage, whole_units)?),
+    })
+}
 
+/// Answers a query whose viewing key was already checked
+fn authenticated_query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
     match msg {
+        QueryMsg::Balance { address, denom: Some(denom), .. } => {
+            query_denom_balance(&deps, &address, &denom)
+        }
         QueryMsg::Balance { address, .. } => { query_balance(&deps, &address) }
-        QueryMsg::Transfers { address, .. } => {query_transactions(&deps, &address)}
-        _ => {
-            unimplemented!()
+        QueryMsg::Transfers {
+            address,
+            page,
+            page_size,
+            direction,
+            counterparty,
+            start_time,
+            end_time,
+            ..
+        } => query_transactions(
+            &deps,
+            &address,
+            &TxFilter {
+                direction,
+                counterparty: counterparty.as_ref(),
+                start_time,
+                end_time,
+            },
+            page.unwrap_or(0),
+            page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+        ),
+        QueryMsg::TransferCount { address, .. } => query_transfer_count(&deps, &address),
+        QueryMsg::AccountStats { address, .. } => query_account_stats(&deps, &address),
+        QueryMsg::AccountTotals { address, .. } => query_account_totals(&deps, &address),
+        QueryMsg::VestingSchedules { address, .. } => query_vesting_schedules(&deps, &address),
+        QueryMsg::LockedBalance { address, .. } => query_locked_balance(&deps, &address),
+        QueryMsg::Unbondings { address, .. } => query_unbondings(&deps, &address),
+        QueryMsg::Rewards { address, .. } => query_rewards(&deps, &address),
+        QueryMsg::ActiveSpenders { address, .. } => query_active_spenders(&deps, &address),
+        QueryMsg::SendWhitelist { address, .. } => query_send_whitelist(&deps, &address),
+        QueryMsg::IncomingTransfers { address, .. } => query_incoming_transfers(&deps, &address),
+        QueryMsg::CollectedFees { address, .. } => query_collected_fees(&deps, &address),
+        _ => Err(generic_err("This query doesn't take a viewing key")),
+    }
+}
+
+/// Answers `queries` in order. The batch's viewing key was checked for `address`, so every
+/// query has to be for `address` too, and the keys they carry aren't checked again
+fn query_batch<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+    queries: Vec<QueryMsg>,
+) -> StdResult<Binary> {
+    let answers = queries
+        .into_iter()
+        .map(|query| {
+            if let Some(answer) = public_query(deps, &query) {
+                return answer;
+            }
+            if let QueryMsg::Batch { .. } = query {
+                return Err(generic_err("Batches can't be nested"));
+            }
+            if query.get_validation_params()?.0 != address {
+                return Err(generic_err("Every query in a batch must be for the batch's address"));
+            }
+            authenticated_query(deps, query)
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_binary(&BatchResponse { answers })
+}
+
+pub fn query_transactions<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    filter: &TxFilter,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+    let txs = get_transfers(&deps.api, &deps.storage, &address, filter, page, page_size, MAX_TRANSFERS_READ)?;
+
+    to_binary(&TransfersResponse { txs })
+}
+
+pub fn query_transfer_count<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+    let count = count_transfers(&deps.storage, &address)?;
+
+    to_binary(&TransferCountResponse { count })
+}
+
+/// Reads the allowances listed in the owner's spender index, instead of scanning them all
+pub fn query_active_spenders<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let owner = deps.api.canonical_address(account)?;
+    let mut spenders = vec![];
+    for spender in read_allowance_spenders(&deps.storage, &owner)? {
+        let allowance = read_allowance(&deps.storage, &owner, &spender)?;
+        if allowance > 0 {
+            spenders.push(SpenderAllowance {
+                spender: deps.api.human_address(&spender)?,
+                allowance: Uint128(allowance),
+            });
         }
     }
+
+    to_binary(&ActiveSpendersResponse { spenders })
 }
 
-pub fn query_transactions<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, account: &HumanAddr) -> StdResult<Binary>{
-    let address = deps.api.canonical_address(account).unwrap();
-    let address = get_transfers(&deps.storage, &address)?;
+pub fn query_account_stats<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+    let stats = get_account_stats(&deps.storage, &address)?;
+
+    to_binary(&AccountStatsResponse { stats })
+}
+
+pub fn query_account_totals<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+    let totals = get_account_totals(&deps.storage, &address)?;
+    let balance = ReadonlyBalances::from_storage(&deps.storage).balance(&address)?;
 
-    Ok(Binary(format!("{:?}", address).into_bytes().to_vec()))
+    to_binary(&AccountTotalsResponse {
+        received: Uint128(totals.received),
+        sent: Uint128(totals.sent),
+        balance: Uint128(balance),
+    })
 }
 
 pub fn query_balance<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, account: &HumanAddr) -> StdResult<Binary>{
@@ -137,6 +1052,81 @@ pub fn query_balance<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, acc
     Ok(Binary(Vec::from(get_balance(deps, &address)?)))
 }
 
+/// The account that signed `permit`, if the permit is for this token and grants `permission`
+pub fn validate_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: &Permit,
+    permission: Permission,
+) -> StdResult<CanonicalAddr> {
+    let contract = ReadonlyConfig::from_storage(&deps.storage).contract_address()?;
+    permit.validate(&deps.api.human_address(&contract)?, permission)
+}
+
+/// Answers `query` for the account that signed `permit`, if the permit grants what the query
+/// needs
+fn query_with_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: &Permit,
+    query: &QueryWithPermit,
+) -> StdResult<Binary> {
+    let permission = match query {
+        QueryWithPermit::Balance {} => Permission::Balance,
+        QueryWithPermit::Transfers { .. } | QueryWithPermit::TransferCount {} => Permission::History,
+        QueryWithPermit::Allowance { .. } => Permission::Allowance,
+    };
+    let account = validate_permit(deps, permit, permission)?;
+    let address = deps.api.human_address(&account)?;
+
+    match query {
+        QueryWithPermit::Balance {} => query_balance(deps, &address),
+        QueryWithPermit::Transfers { page, page_size } => query_transactions(
+            deps,
+            &address,
+            &TxFilter::default(),
+            page.unwrap_or(0),
+            page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+        ),
+        QueryWithPermit::TransferCount {} => query_transfer_count(deps, &address),
+        QueryWithPermit::Allowance { owner, spender } => {
+            let owner = deps.api.canonical_address(owner)?;
+            let spender = deps.api.canonical_address(spender)?;
+            if account != owner && account != spender {
+                return Err(unauthorized());
+            }
+            to_binary(&AllowanceResponse {
+                allowance: Uint128(read_allowance(&deps.storage, &owner, &spender)?),
+            })
+        }
+    }
+}
+
+/// Balances of the accounts that signed `permits`, in the same order
+pub fn query_permit_balances<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permits: &[Permit],
+) -> StdResult<Binary> {
+    if permits.len() > MAX_BALANCE_PERMITS {
+        return Err(generic_err(format!(
+            "Can check at most {} permits at once",
+            MAX_BALANCE_PERMITS
+        )));
+    }
+
+    let balances = ReadonlyBalances::from_storage(&deps.storage);
+    let balances = permits
+        .iter()
+        .map(|permit| {
+            let account = validate_permit(deps, permit, Permission::Balance)?;
+            Ok(AccountBalance {
+                address: deps.api.human_address(&account)?,
+                balance: Uint128(balances.balance(&account)?),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_binary(&PermitBalancesResponse { balances })
+}
+
 pub fn try_set_key<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
@@ -156,7 +1146,7 @@ pub fn try_set_key<S: Storage, A: Api, Q: Querier>(
         });
     }
 
-    write_viewing_key(&mut deps.storage, &env.message.sender, &vk)?;
+    write_viewing_key(&mut deps.storage, &env.message.sender, &vk);
 
     Ok(HandleResponse{
         messages: vec![],
@@ -174,9 +1164,10 @@ pub fn try_create_key<S: Storage, A: Api, Q: Querier>(
     entropy: String
 ) -> StdResult<HandleResponse> {
 
-    let vk = ViewingKey::new(&env, b"yo", (&entropy).as_ref());
+    let mut rng = contract_rng(&mut deps.storage, &env, entropy.as_bytes());
+    let vk = ViewingKey::new(&mut rng);
 
-    write_viewing_key(&mut deps.storage, &env.message.sender, &vk)?;
+    write_viewing_key(&mut deps.storage, &env.message.sender, &vk);
 
     Ok(HandleResponse{
         messages: vec![],
@@ -187,136 +1178,226 @@ pub fn try_create_key<S: Storage, A: Api, Q: Querier>(
     })
 }
 
-pub fn try_check_allowance<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    env: Env,
-    spender: HumanAddr) -> StdResult<HandleResponse> {
-
-    let sender_address_raw = &env.message.sender;
-    let allowance = read_allowance(&deps.storage, sender_address_raw, &deps.api.canonical_address(&spender)?);
-
-    if let Err(_e) = allowance {
-        Ok(HandleResponse {
-            messages: vec![],
-            log: vec![
-                log("action", "check_allowance"),
-                log(
-                    "account",
-                    deps.api.human_address(&env.message.sender)?.as_str(),
-                ),
-                log(
-                    "spender",
-                    &spender.as_str(),
-                ),
-                log("amount", ConstLenStr("0".to_string())),
-            ],
-            data: None,
-        })
-    }
-    else {
-        Ok(HandleResponse {
-            messages: vec![],
-            log: vec![
-                log("action", "check_allowance"),
-                log(
-                    "account",
-                    deps.api.human_address(&env.message.sender)?.as_str(),
-                ),
-                log(
-                    "spender",
-                    &spender.as_str(),
-                ),
-                log("amount", ConstLenStr(allowance.unwrap().to_string())),
-            ],
-            data: None,
-        })
+/// Fails unless the message was sent by the admin
+pub fn assert_admin<S: ReadonlyStorage>(storage: &S, env: &Env) -> StdResult<()> {
+    let admin = ReadonlyConfig::from_storage(storage).admin()?;
+    if admin != env.message.sender {
+        return Err(unauthorized());
     }
+    Ok(())
 }
 
-pub fn try_balance<S: Storage, A: Api, Q: Querier>(
+/// Puts the token in redeem-only mode for good, see `is_disabled_when_redeem_only`
+fn try_permanent_redeem_only<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: Env) -> StdResult<HandleResponse> {
-
-    let sender_address_raw = &env.message.sender;
-    let account_balance = get_balance(deps, sender_address_raw);
-
-    if let Err(_e) = account_balance {
-        Ok(HandleResponse {
-            messages: vec![],
-            log: vec![
-                log("action", "balance"),
-                log(
-                    "account",
-                    deps.api.human_address(&env.message.sender)?.as_str(),
-                ),
-                log("amount", ConstLenStr("0".to_string())),
-            ],
-            data: None,
-        })
-    }
-    else {
-        Ok(HandleResponse {
-            messages: vec![],
-            log: vec![
-                log("action", "balance"),
-                log(
-                    "account",
-                    deps.api.human_address(&env.message.sender)?.as_str(),
-                ),
-                log("amount", ConstLenStr(account_balance.unwrap())),
-            ],
-            data: None,
-        })
-    }
-}
-
-fn get_balance<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, account: &CanonicalAddr) -> StdResult<String> {
-    let account_balance = read_balance(&deps.storage, account);
+    env: Env,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
 
-    let consts = read_constants(&deps.storage)?;
+    Config::from_storage(&mut deps.storage).set_redeem_only();
 
-    Ok(to_display_token(account_balance?, &consts.symbol, consts.decimals))
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "permanent_redeem_only")],
+        data: None,
+    };
+    Ok(res)
 }
 
-fn try_deposit<S: Storage, A: Api, Q: Querier>(
+/// Admin only, replaces `denom` with `new_denom` among the supported denoms, in the same place,
+/// and moves its reserve over
+fn try_rename_denom<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: Env) -> StdResult<HandleResponse> {
+    env: Env,
+    denom: String,
+    new_denom: String,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if get_staking_state(&deps.storage)?.is_some() {
+        return Err(generic_err("Staking mode only supports uscrt"));
+    }
 
-    let mut amount_raw: Uint128 = Uint128::default();
+    let mut config = Config::from_storage(&mut deps.storage);
+    let mut supported_denoms = config.supported_denoms()?;
+    if new_denom.is_empty() || supported_denoms.contains(&new_denom) {
+        return Err(generic_err(format!("Invalid or repeated denom: {:?}", new_denom)));
+    }
+    let position = supported_denoms
+        .iter()
+        .position(|supported| *supported == denom)
+        .ok_or_else(|| generic_err(format!("Unsupported denom: {}", denom)))?;
+
+    // read before the denoms are stored, tokens that never stored them keep the reserve of the
+    // default denom implicitly
+    let reserve = config.reserve(&denom)?;
+    supported_denoms[position] = new_denom.clone();
+    config.set_supported_denoms(&supported_denoms)?;
+    config.remove_reserve(&denom);
+    config.set_reserve(&new_denom, reserve);
 
-    for coin in &env.message.sent_funds {
-        if coin.denom == "uscrt" {
-            amount_raw = coin.amount
-        }
-    }
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "rename_denom"),
+            log("denom", &denom),
+            log("new_denom", &new_denom),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
 
-    if amount_raw == Uint128::default() {
-        return Err(generic_err(format!("Lol send some funds dude")));
-    }
+fn try_change_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
 
-    let amount = amount_raw.u128();
+    let new_admin = deps.api.canonical_address(address)?;
+    Config::from_storage(&mut deps.storage).set_admin(&new_admin);
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "change_admin"), log("admin", address.as_str())],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Delete all but the newest `keep_last` transfers from the signer's history
+pub fn try_prune_history<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    keep_last: u32,
+) -> StdResult<HandleResponse> {
+    let removed = prune_transfers(&mut deps.storage, &env.message.sender, keep_last)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "prune_history"),
+            log("removed", removed),
+        ],
+        data: None,
+    })
+}
+
+/// Deprecated in favor of the queries, which don't cost gas. The allowance is only in `data`,
+/// which unlike the logs is encrypted for the sender
+pub fn try_check_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    spender: HumanAddr) -> StdResult<HandleResponse> {
 
     let sender_address_raw = &env.message.sender;
+    let allowance = read_allowance(&deps.storage, sender_address_raw, &deps.api.canonical_address(&spender)?)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "check_allowance")],
+        data: Some(to_binary(&AllowanceResponse {
+            allowance: Uint128(allowance),
+        })?),
+    })
+}
+
+/// Deprecated like `try_check_allowance`
+pub fn try_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env) -> StdResult<HandleResponse> {
+
+    let balance = ReadonlyBalances::from_storage(&deps.storage).balance(&env.message.sender)?;
+    let symbol = ReadonlyConfig::from_storage(&deps.storage).constants()?.symbol;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "balance")],
+        data: Some(to_binary(&BalanceResponse {
+            balance: Uint128(balance),
+            token: symbol,
+        })?),
+    })
+}
 
-    let mut account_balance = read_balance(&deps.storage, sender_address_raw)?;
+fn get_balance<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, account: &CanonicalAddr) -> StdResult<String> {
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(account);
 
-    account_balance += amount;
+    let consts = ReadonlyConfig::from_storage(&deps.storage).constants()?;
 
-    let mut balances_store = PrefixedStorage::new(PREFIX_BALANCES, &mut deps.storage);
-    balances_store.set(sender_address_raw.as_slice(), &account_balance.to_be_bytes());
+    Ok(to_display_token(account_balance?, &consts.symbol, consts.decimals))
+}
 
-    let mut config_store = PrefixedStorage::new(PREFIX_CONFIG, &mut deps.storage);
-    let data = config_store
-        .get(KEY_TOTAL_SUPPLY)
-        .expect("no total supply data stored");
-    let mut total_supply = bytes_to_u128(&data).unwrap();
+fn try_deposit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env) -> StdResult<HandleResponse> {
 
-    total_supply += amount;
+    let deposits = supported_funds(&deps.storage, &env)?;
+    let deposited = deposits
+        .iter()
+        .try_fold(0u128, |sum, (_, amount)| sum.checked_add(*amount))
+        .ok_or_else(|| generic_err("Deposit overflow"))?;
 
-    config_store.set(KEY_TOTAL_SUPPLY, &total_supply.to_be_bytes());
+    if deposited == 0 {
+        return Err(generic_err(format!("Lol send some funds dude")));
+    }
+
+    let sender_address_raw = &env.message.sender;
+
+    // in staking mode the deposit is delegated, and tokens are minted at the exchange rate
+    let total_supply = ReadonlyConfig::from_storage(&deps.storage).total_supply()?;
+    let (minted, messages) = match bond(&mut deps.storage, deposited, total_supply)? {
+        Some((bonded, messages)) => (vec![(DEFAULT_DENOM.to_string(), bonded)], messages),
+        None => {
+            add_to_reserves(&mut deps.storage, &deposits)?;
+            let minted = deposits
+                .iter()
+                .filter(|(_, native)| *native > 0)
+                .map(|(denom, native)| Ok((denom.clone(), native_to_tokens(&deps.storage, *native)?)))
+                .collect::<StdResult<Vec<_>>>()?;
+            (minted, vec![])
+        }
+    };
+    let amount = minted
+        .iter()
+        .try_fold(0u128, |sum, (_, tokens)| sum.checked_add(*tokens))
+        .ok_or_else(|| generic_err("Deposit overflow"))?;
+
+    ledger_deposit(&mut deps.storage, sender_address_raw, &minted)?;
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances
+        .balance(sender_address_raw)?
+        .checked_add(amount)
+        .ok_or_else(|| generic_err("Deposit overflows the account balance"))?;
+    balances.set_balance(sender_address_raw, account_balance)?;
+
+    write_total_supply(&mut deps.storage, SupplyChange::Mint(amount))?;
+
+    update_account_stats(&mut deps.storage, sender_address_raw, |stats| {
+        stats.deposits = stats.deposits.saturating_add(1)
+    })?;
+    for (denom, tokens) in &minted {
+        store_deposit(
+            &mut deps.storage,
+            &env.contract.address,
+            sender_address_raw,
+            &Uint128(*tokens),
+            denom,
+            env.block.time,
+            env.block.height,
+        )?;
+    }
+    let fee_bps = get_fees(&deps.storage)?.deposit_fee_bps;
+    let fee = charge_fee(
+        &mut deps.storage,
+        sender_address_raw,
+        amount,
+        fee_bps,
+        env.block.time,
+    )?;
 
     let res = HandleResponse {
-        messages: vec![],
+        messages,
         log: vec![
             log("action", "deposit"),
             log(
@@ -324,6 +1405,7 @@ fn try_deposit<S: Storage, A: Api, Q: Querier>(
                 deps.api.human_address(&env.message.sender)?.as_str(),
             ),
             log("amount", &amount.to_string()),
+            log("fee", &fee.to_string()),
         ],
         data: None,
     };
@@ -332,73 +1414,435 @@ fn try_deposit<S: Storage, A: Api, Q: Querier>(
 
 }
 
+/// Adds up the coins sent with the message by denom, ignoring the denoms the token doesn't wrap.
+/// An unsupported denom that comes more than once is rejected, it's most likely a malformed
+/// message rather than coins sent along by accident
+fn supported_funds<S: ReadonlyStorage>(storage: &S, env: &Env) -> StdResult<Vec<(String, u128)>> {
+    let supported_denoms = ReadonlyConfig::from_storage(storage).supported_denoms()?;
+
+    let mut funds: Vec<(String, u128)> = vec![];
+    let mut unsupported: Vec<&str> = vec![];
+    for coin in &env.message.sent_funds {
+        if !supported_denoms.contains(&coin.denom) {
+            if unsupported.contains(&coin.denom.as_str()) {
+                return Err(generic_err(format!(
+                    "Unsupported denom {} was sent more than once",
+                    coin.denom
+                )));
+            }
+            unsupported.push(&coin.denom);
+            continue;
+        }
+        match funds.iter_mut().find(|(denom, _)| *denom == coin.denom) {
+            Some((_, amount)) => {
+                *amount = amount
+                    .checked_add(coin.amount.u128())
+                    .ok_or_else(|| generic_err("Deposit overflow"))?
+            }
+            None => funds.push((coin.denom.clone(), coin.amount.u128())),
+        }
+    }
+    Ok(funds)
+}
+
+/// How many tokens `native` units of a native coin are worth. Fails instead of rounding, so no
+/// part of a deposit is ever lost
+fn native_to_tokens<S: ReadonlyStorage>(storage: &S, native: u128) -> StdResult<u128> {
+    let decimals = ReadonlyConfig::from_storage(storage).constants()?.decimals;
+    convert_decimals(native, NATIVE_DECIMALS, decimals)
+}
+
+/// How many native units `tokens` are worth. Fails instead of rounding, so withdrawals have to
+/// be whole native units
+fn tokens_to_native<S: ReadonlyStorage>(storage: &S, tokens: u128) -> StdResult<u128> {
+    let decimals = ReadonlyConfig::from_storage(storage).constants()?.decimals;
+    convert_decimals(tokens, decimals, NATIVE_DECIMALS)
+}
+
+/// The fewest tokens worth a whole native unit
+fn tokens_per_native<S: ReadonlyStorage>(storage: &S) -> StdResult<u128> {
+    let decimals = ReadonlyConfig::from_storage(storage).constants()?.decimals;
+    Ok(10u128.pow(decimals.saturating_sub(NATIVE_DECIMALS) as u32))
+}
+
+/// Converts `amount` between units with different decimals, failing rather than rounding
+pub fn convert_decimals(amount: u128, from_decimals: u8, to_decimals: u8) -> StdResult<u128> {
+    if to_decimals >= from_decimals {
+        let factor = 10u128.pow((to_decimals - from_decimals) as u32);
+        amount
+            .checked_mul(factor)
+            .ok_or_else(|| generic_err("Amount overflows when converting decimals"))
+    } else {
+        let factor = 10u128.pow((from_decimals - to_decimals) as u32);
+        if amount % factor != 0 {
+            return Err(generic_err(format!(
+                "Amount must be a multiple of {} to convert from {} to {} decimals",
+                factor, from_decimals, to_decimals
+            )));
+        }
+        Ok(amount / factor)
+    }
+}
+
+fn add_to_reserves<S: Storage>(storage: &mut S, funds: &[(String, u128)]) -> StdResult<()> {
+    let mut config = Config::from_storage(storage);
+    for (denom, amount) in funds {
+        let reserve = config
+            .reserve(denom)?
+            .checked_add(*amount)
+            .ok_or_else(|| generic_err(format!("{} reserve overflow", denom)))?;
+        config.set_reserve(denom, reserve);
+    }
+    Ok(())
+}
+
 fn try_withdraw<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    amount: Uint128) -> StdResult<HandleResponse> {
+    amount: Uint128,
+    denom: Option<String>,
+) -> StdResult<HandleResponse> {
     let owner_address_raw = &env.message.sender;
-    let amount_raw = amount.u128();
+    let messages = redeem(deps, &env, owner_address_raw, amount.u128(), denom, owner_address_raw)?;
 
-    let mut account_balance = read_balance(&deps.storage, owner_address_raw)?;
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "withdraw"),
+            log(
+                "account",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+            log("amount", &amount.to_string()),
+        ],
+        data: None,
+    };
 
-    if account_balance < amount_raw {
-        return Err(generic_err(format!(
+    Ok(res)
+
+}
+
+/// Withdraws `amount` like `Withdraw`, but pays the native coins to `recipient`
+fn try_redeem_and_send<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: Uint128,
+    denom: Option<String>,
+) -> StdResult<HandleResponse> {
+    let owner_address_raw = &env.message.sender;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    let messages = redeem(deps, &env, owner_address_raw, amount.u128(), denom, &recipient_address_raw)?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "redeem_and_send"),
+            log("account", deps.api.human_address(owner_address_raw)?.as_str()),
+            log("recipient", recipient.as_str()),
+            log("amount", &amount.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Burns `amount` of `owner_address_raw`'s tokens and pays their value out of the reserve of
+/// `denom` to `recipient_address_raw`. In staking mode the tokens are unbonded instead, and
+/// `ClaimUnbonded` pays the owner once they are
+fn redeem<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    owner_address_raw: &CanonicalAddr,
+    amount_raw: u128,
+    denom: Option<String>,
+    recipient_address_raw: &CanonicalAddr,
+) -> StdResult<Vec<CosmosMsg>> {
+    record_redeem(&mut deps.storage, amount_raw, env.block.time)?;
+
+    // the fee stays behind as tokens, only the rest is redeemed. Since only whole native units
+    // can be paid out, the fee also keeps what doesn't make one
+    let fee_bps = get_fees(&deps.storage)?.redeem_fee_bps;
+    let unit = tokens_per_native(&deps.storage)?;
+    let fee = charge_fee_in_units(
+        &mut deps.storage,
+        owner_address_raw,
+        amount_raw,
+        fee_bps,
+        unit,
+        env.block.time,
+    )?;
+    let amount_raw = amount_raw - fee;
+
+    let supported_denoms = ReadonlyConfig::from_storage(&deps.storage).supported_denoms()?;
+    let denom = match denom {
+        Some(denom) if supported_denoms.contains(&denom) => denom,
+        Some(denom) => return Err(generic_err(format!("Unsupported denom: {}", denom))),
+        None => supported_denoms
+            .first()
+            .cloned()
+            .ok_or_else(|| generic_err("The token has no supported denoms"))?,
+    };
+
+    ledger_redeem(&mut deps.storage, owner_address_raw, &denom, amount_raw)?;
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances.balance(owner_address_raw)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
             "insufficient funds to burn: balance={}, required={}",
             account_balance, amount_raw
-        )));
+        ))
+    })?;
+    balances.set_balance(owner_address_raw, account_balance)?;
+
+    let old_total_supply = read_total_supply(&deps.storage)?;
+    write_total_supply(&mut deps.storage, SupplyChange::Burn(amount_raw))?;
+
+    update_account_stats(&mut deps.storage, owner_address_raw, |stats| {
+        stats.redeems = stats.redeems.saturating_add(1)
+    })?;
+    store_redeem(
+        &mut deps.storage,
+        owner_address_raw,
+        &env.contract.address,
+        &Uint128(amount_raw),
+        &denom,
+        env.block.time,
+        env.block.height,
+    )?;
+
+    // in staking mode the withdrawal has to unbond first, it's paid out by `ClaimUnbonded`
+    let unbonding = unbond(
+        &mut deps.storage,
+        owner_address_raw,
+        amount_raw,
+        old_total_supply,
+        env.block.time,
+    )?;
+    let messages = match unbonding {
+        Some(_) if recipient_address_raw != owner_address_raw => {
+            return Err(generic_err(
+                "Staked tokens can only be redeemed to the account that holds them",
+            ));
+        }
+        Some(messages) => messages,
+        None => {
+            let native = tokens_to_native(&deps.storage, amount_raw)?;
+
+            let mut config = Config::from_storage(&mut deps.storage);
+            let reserve = config.reserve(&denom)?;
+            let reserve = reserve.checked_sub(native).ok_or_else(|| {
+                generic_err(format!(
+                    "Insufficient {} reserve: available={}, required={}",
+                    denom, reserve, native
+                ))
+            })?;
+            config.set_reserve(&denom, reserve);
+
+            let contract_addr = deps.api.human_address(&env.contract.address)?;
+            let withdrawl_addr = deps.api.human_address(recipient_address_raw)?;
+
+            let withdrawl_coins: Vec<Coin> = vec![Coin {denom, amount: Uint128(native)}];
+
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: contract_addr,
+                to_address: withdrawl_addr,
+                amount: withdrawl_coins,
+            })]
+        }
+    };
+
+    Ok(messages)
+}
+
+/// Redeems the sender's whole balance, to `redeem_to` if set, and deletes what the contract
+/// stores for the account: its balance, viewing key, the allowances it gave and its history.
+/// Rewards it earned so far can still be claimed
+fn try_close_account<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    redeem_to: Option<HumanAddr>,
+    legacy_spenders: Vec<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let account = &env.message.sender;
+    let recipient = match &redeem_to {
+        Some(redeem_to) => deps.api.canonical_address(redeem_to)?,
+        None => account.clone(),
+    };
+    let legacy_spenders = legacy_spenders
+        .iter()
+        .map(|spender| deps.api.canonical_address(spender))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let balance = ReadonlyBalances::from_storage(&deps.storage).balance(account)?;
+    // each part of the balance is paid out in the denom it came from
+    let denoms = ReadonlyConfig::from_storage(&deps.storage).supported_denoms()?;
+    let amounts = denom_balances(&deps.storage, account)?;
+    let mut messages = vec![];
+    for (denom, amount) in denoms.into_iter().zip(amounts) {
+        if amount > 0 {
+            messages.extend(redeem(deps, &env, account, amount, Some(denom), &recipient)?);
+        }
     }
-    account_balance -= amount_raw;
 
-    let mut balances_store = PrefixedStorage::new(PREFIX_BALANCES, &mut deps.storage);
-    balances_store.set(owner_address_raw.as_slice(), &account_balance.to_be_bytes());
+    Balances::from_storage(&mut deps.storage).remove_balance(account)?;
+    remove_denom_balances(&mut deps.storage, account);
+    remove_allowances(&mut deps.storage, account, &legacy_spenders)?;
+    remove_viewing_key(&mut deps.storage, account);
+    prune_transfers(&mut deps.storage, account, 0)?;
 
-    let mut config_store = PrefixedStorage::new(PREFIX_CONFIG, &mut deps.storage);
-    let data = config_store
-        .get(KEY_TOTAL_SUPPLY)
-        .expect("no total supply data stored");
-    let mut total_supply = bytes_to_u128(&data).unwrap();
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "close_account"),
+            log("account", deps.api.human_address(account)?.as_str()),
+            log("amount", &balance.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
 
-    total_supply -= amount_raw;
+fn try_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = &env.message.sender;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    let amount_raw = amount.u128();
 
-    config_store.set(KEY_TOTAL_SUPPLY, &total_supply.to_be_bytes());
+    check_incoming(&deps.storage, sender_address_raw, &recipient_address_raw)?;
+    perform_transfer(
+        &mut deps.storage,
+        &sender_address_raw,
+        &recipient_address_raw,
+        amount_raw,
+    )?;
 
-    let contract_addr = deps.api.human_address(&env.contract.address)?;
-    let withdrawl_addr = deps.api.human_address(owner_address_raw)?;
 
-    let withdrawl_coins: Vec<Coin> = vec![Coin {denom: "uscrt".to_string(), amount}];
+    store_transfer(&mut deps.storage, sender_address_raw, &recipient_address_raw, amount, env.block.time)?;
 
+    let messages = transfer_hook_messages(&deps.storage, &deps.api, sender_address_raw, &recipient_address_raw, amount_raw)?;
 
     let res = HandleResponse {
-        messages: vec![CosmosMsg::Bank(BankMsg::Send {
-            from_address: contract_addr,
-            to_address: withdrawl_addr,
-            amount: withdrawl_coins,
-        })],
+        messages,
         log: vec![
-            log("action", "withdraw"),
+            log("action", "transfer"),
             log(
-                "account",
+                "sender",
                 deps.api.human_address(&env.message.sender)?.as_str(),
             ),
-            log("amount", &amount.to_string()),
+            log("recipient", recipient.as_str()),
         ],
         data: None,
     };
-
     Ok(res)
+}
 
+/// Deposits the coins sent with the message and transfers `amount` of the minted tokens to
+/// `recipient`. With `send_msg` they are sent instead, like `Send` does with its `msg`
+fn try_wrap_and_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+    send_msg: Option<(Option<String>, Option<Binary>)>,
+) -> StdResult<HandleResponse> {
+    let deposit = try_deposit(deps, env.clone())?;
+    let transfer = match send_msg {
+        Some((code_hash, msg)) => try_send(deps, env, recipient, code_hash, amount, msg)?,
+        None => try_transfer(deps, env, recipient, amount)?,
+    };
+    Ok(chain_responses(deposit, transfer))
 }
 
-fn try_transfer<S: Storage, A: Api, Q: Querier>(
+/// One response doing what `first` and then `second` do
+fn chain_responses(mut first: HandleResponse, second: HandleResponse) -> HandleResponse {
+    first.messages.extend(second.messages);
+    first.log.extend(second.log);
+    first.data = second.data.or(first.data);
+    first
+}
+
+/// Transfers to every recipient of `actions` in order, recording each action's memo in both
+/// histories. Fails as a whole if any of them fails
+fn try_batch_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    actions: Vec<TransferAction>,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = &env.message.sender;
+
+    let mut messages = vec![];
+    for action in actions.iter() {
+        if action.memo.as_ref().map_or(false, |memo| memo.len() > MAX_MEMO_LENGTH) {
+            return Err(generic_err(format!(
+                "Memos can be at most {} bytes long",
+                MAX_MEMO_LENGTH
+            )));
+        }
+        let recipient_address_raw = deps.api.canonical_address(&action.recipient)?;
+        let amount_raw = action.amount.u128();
+
+        check_incoming(&deps.storage, sender_address_raw, &recipient_address_raw)?;
+        perform_transfer(
+            &mut deps.storage,
+            sender_address_raw,
+            &recipient_address_raw,
+            amount_raw,
+        )?;
+        store_transfer_with_memo(
+            &mut deps.storage,
+            sender_address_raw,
+            &recipient_address_raw,
+            &action.amount,
+            env.block.time,
+            action.memo.as_deref(),
+        )?;
+        messages.extend(transfer_hook_messages(
+            &deps.storage,
+            &deps.api,
+            sender_address_raw,
+            &recipient_address_raw,
+            amount_raw,
+        )?);
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "batch_transfer"),
+            log(
+                "sender",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+            log("recipients", actions.len()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Transfer tokens to a contract
+///
+/// Works like `Transfer`, but if the recipient registered itself with `RegisterReceive`, or the
+/// sender passed its code hash, it is also sent a `Snip20ReceiveMsg` callback carrying `msg`
+pub fn try_send<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     recipient: &HumanAddr,
+    recipient_code_hash: Option<String>,
     amount: &Uint128,
+    msg: Option<Binary>,
 ) -> StdResult<HandleResponse> {
     let sender_address_raw = &env.message.sender;
     let recipient_address_raw = deps.api.canonical_address(recipient)?;
     let amount_raw = amount.u128();
 
+    check_send_whitelist(&deps.storage, sender_address_raw, &recipient_address_raw)?;
+    check_incoming(&deps.storage, sender_address_raw, &recipient_address_raw)?;
     perform_transfer(
         &mut deps.storage,
         &sender_address_raw,
@@ -406,72 +1850,404 @@ fn try_transfer<S: Storage, A: Api, Q: Querier>(
         amount_raw,
     )?;
 
-    let symbol = read_constants(&deps.storage)?.symbol;
 
-    store_transfer(&deps.api, &mut deps.storage, sender_address_raw, &recipient_address_raw, amount, symbol);
+    store_transfer(&mut deps.storage, sender_address_raw, &recipient_address_raw, amount, env.block.time)?;
+
+    let sender = deps.api.human_address(&env.message.sender)?;
+
+    let mut messages = transfer_hook_messages(&deps.storage, &deps.api, sender_address_raw, &recipient_address_raw, amount_raw)?;
+    let code_hash = match recipient_code_hash {
+        Some(code_hash) => Some(code_hash),
+        None => get_receiver_hash(&deps.storage, &recipient_address_raw).transpose()?,
+    };
+    if let Some(code_hash) = code_hash {
+        let receive_msg = Snip20ReceiveMsg::new(sender.clone(), sender.clone(), *amount, msg);
+        messages.push(receive_msg.into_cosmos_msg(code_hash, recipient.clone())?);
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "send"),
+            log("sender", sender.as_str()),
+            log("recipient", recipient.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Register the signer as a contract that wants `Snip20ReceiveMsg` callbacks on `Send`
+fn try_register_receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    code_hash: String,
+) -> StdResult<HandleResponse> {
+    set_receiver_hash(&mut deps.storage, &env.message.sender, code_hash);
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "register_receive"),
+            log(
+                "contract",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn try_transfer_from<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+) -> StdResult<HandleResponse> {
+    let owner_address_raw = deps.api.canonical_address(owner)?;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+
+    let messages = spend_and_transfer(
+        deps,
+        &env,
+        &owner_address_raw,
+        &recipient_address_raw,
+        amount,
+    )?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "transfer_from"),
+            log(
+                "spender",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+            log("sender", owner.as_str()),
+            log("recipient", recipient.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Works like `TransferFrom`, and calls the recipient back like `Send`. The callback's `sender`
+/// is the spender and its `from` is the owner
+fn try_send_from<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    recipient: &HumanAddr,
+    recipient_code_hash: Option<String>,
+    amount: &Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    let owner_address_raw = deps.api.canonical_address(owner)?;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+
+    check_send_whitelist(&deps.storage, &owner_address_raw, &recipient_address_raw)?;
+    let mut messages = spend_and_transfer(
+        deps,
+        &env,
+        &owner_address_raw,
+        &recipient_address_raw,
+        amount,
+    )?;
+
+    let spender = deps.api.human_address(&env.message.sender)?;
+    let code_hash = match recipient_code_hash {
+        Some(code_hash) => Some(code_hash),
+        None => get_receiver_hash(&deps.storage, &recipient_address_raw).transpose()?,
+    };
+    if let Some(code_hash) = code_hash {
+        let receive_msg = Snip20ReceiveMsg::new(spender.clone(), owner.clone(), *amount, msg);
+        messages.push(receive_msg.into_cosmos_msg(code_hash, recipient.clone())?);
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "send_from"),
+            log("spender", spender.as_str()),
+            log("sender", owner.as_str()),
+            log("recipient", recipient.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Spends the sender's allowance from `owner` and moves `amount` to `recipient`, for
+/// `TransferFrom` and `SendFrom`. Returns the transfer hook messages
+fn spend_and_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    owner_address_raw: &CanonicalAddr,
+    recipient_address_raw: &CanonicalAddr,
+    amount: &Uint128,
+) -> StdResult<Vec<CosmosMsg>> {
+    let spender_address_raw = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    check_incoming(&deps.storage, owner_address_raw, recipient_address_raw)?;
+    update_allowance(
+        &mut deps.storage,
+        owner_address_raw,
+        spender_address_raw,
+        |allowance| spend_allowance(allowance, amount_raw),
+    )?;
+    perform_transfer(
+        &mut deps.storage,
+        owner_address_raw,
+        recipient_address_raw,
+        amount_raw,
+    )?;
+
+    store_transfer_from(
+        &mut deps.storage,
+        owner_address_raw,
+        recipient_address_raw,
+        spender_address_raw,
+        amount,
+        env.block.time,
+    )?;
+
+    transfer_hook_messages(&deps.storage, &deps.api, owner_address_raw, recipient_address_raw, amount_raw)
+}
+
+/// Start a claimable transfer
+///
+/// Moves `amount` out of the signer's balance and holds it until the recipient claims it with
+/// `ClaimTransfer`, or until `expiry` passes and the signer takes it back with `CancelTransfer`
+fn try_transfer_pending<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+    expiry: u64,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = &env.message.sender;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    let amount_raw = amount.u128();
+
+    if expiry <= env.block.time {
+        return Err(generic_err("Expiry must be in the future"));
+    }
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(sender_address_raw)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            account_balance, amount_raw
+        ))
+    })?;
+    Balances::from_storage(&mut deps.storage).set_balance(sender_address_raw, account_balance)?;
+
+    let id = store_pending_transfer(
+        &mut deps.storage,
+        &PendingTransfer {
+            sender: sender_address_raw.clone(),
+            recipient: recipient_address_raw,
+            amount: amount_raw,
+            expiry,
+        },
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "transfer_pending"),
+            log(
+                "sender",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+            log("recipient", recipient.as_str()),
+            log("id", id),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn try_claim_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let pending = get_pending_transfer(&deps.storage, id)?
+        .ok_or_else(|| generic_err(format!("No pending transfer with id {}", id)))?;
+
+    if pending.recipient != env.message.sender {
+        return Err(unauthorized());
+    }
+    if env.block.time >= pending.expiry {
+        return Err(generic_err("Pending transfer has expired"));
+    }
+    check_not_frozen(&deps.storage, &pending.recipient)?;
+    check_account_compliance(&deps.storage, &pending.recipient)?;
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(&pending.recipient)?
+        .checked_add(pending.amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    Balances::from_storage(&mut deps.storage).set_balance(&pending.recipient, account_balance)?;
+
+    remove_pending_transfer(&mut deps.storage, id);
+
+
+    store_transfer(&mut deps.storage, &pending.sender, &pending.recipient, &Uint128(pending.amount), env.block.time)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "claim_transfer"),
+            log("sender", deps.api.human_address(&pending.sender)?.as_str()),
+            log(
+                "recipient",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+            log("id", id),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn try_cancel_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let pending = get_pending_transfer(&deps.storage, id)?
+        .ok_or_else(|| generic_err(format!("No pending transfer with id {}", id)))?;
+
+    if pending.sender != env.message.sender {
+        return Err(unauthorized());
+    }
+    if env.block.time < pending.expiry {
+        return Err(generic_err("Pending transfer can only be cancelled after it expires"));
+    }
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(&pending.sender)?
+        .checked_add(pending.amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    Balances::from_storage(&mut deps.storage).set_balance(&pending.sender, account_balance)?;
+
+    remove_pending_transfer(&mut deps.storage, id);
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "cancel_transfer"),
+            log(
+                "sender",
+                deps.api.human_address(&env.message.sender)?.as_str(),
+            ),
+            log("id", id),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Lock funds behind a secret
+///
+/// Moves `amount` out of the signer's balance and locks it under `hash`, the SHA-256 of a secret
+/// that can be handed out off-chain (e.g. as a gift link). Anyone redeeming the secret before
+/// `expiry` receives the funds
+fn try_create_claim<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    hash: &Binary,
+    amount: &Uint128,
+    expiry: u64,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    if hash.len() != 32 {
+        return Err(generic_err("Claim hash must be a 32 byte SHA-256 digest"));
+    }
+    if expiry <= env.block.time {
+        return Err(generic_err("Expiry must be in the future"));
+    }
+    if get_claim(&deps.storage, hash.as_slice())?.is_some() {
+        return Err(generic_err("A claim with this hash already exists"));
+    }
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(sender_address_raw)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            account_balance, amount_raw
+        ))
+    })?;
+    Balances::from_storage(&mut deps.storage).set_balance(sender_address_raw, account_balance)?;
+
+    store_claim(
+        &mut deps.storage,
+        hash.as_slice(),
+        &HashClaim {
+            creator: sender_address_raw.clone(),
+            amount: amount_raw,
+            expiry,
+        },
+    )?;
 
     let res = HandleResponse {
         messages: vec![],
         log: vec![
-            log("action", "transfer"),
+            log("action", "create_claim"),
             log(
-                "sender",
+                "creator",
                 deps.api.human_address(&env.message.sender)?.as_str(),
             ),
-            log("recipient", recipient.as_str()),
         ],
         data: None,
     };
     Ok(res)
 }
 
-fn try_transfer_from<S: Storage, A: Api, Q: Querier>(
+/// Redeem a secret
+///
+/// Before expiry the funds go to whoever presents the preimage. After expiry redeeming refunds
+/// the creator instead, no matter who sends the message
+fn try_redeem_claim<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    owner: &HumanAddr,
-    recipient: &HumanAddr,
-    amount: &Uint128,
+    preimage: String,
 ) -> StdResult<HandleResponse> {
-    let spender_address_raw = &env.message.sender;
-    let owner_address_raw = deps.api.canonical_address(owner)?;
-    let recipient_address_raw = deps.api.canonical_address(recipient)?;
-    let amount_raw = amount.u128();
+    let hash = sha_256(preimage.as_bytes());
 
-    let mut allowance = read_allowance(&deps.storage, &owner_address_raw, &spender_address_raw)?;
-    if allowance < amount_raw {
-        return Err(generic_err(format!(
-            "Insufficient allowance: allowance={}, required={}",
-            allowance, amount_raw
-        )));
-    }
-    allowance -= amount_raw;
-    write_allowance(
-        &mut deps.storage,
-        &owner_address_raw,
-        &spender_address_raw,
-        allowance,
-    )?;
-    perform_transfer(
-        &mut deps.storage,
-        &owner_address_raw,
-        &recipient_address_raw,
-        amount_raw,
-    )?;
+    let claim = get_claim(&deps.storage, &hash)?
+        .ok_or_else(|| generic_err("No claim matches this secret"))?;
+
+    let expired = env.block.time >= claim.expiry;
+    let receiver = if expired {
+        claim.creator.clone()
+    } else {
+        env.message.sender.clone()
+    };
+    check_not_frozen(&deps.storage, &receiver)?;
+    check_account_compliance(&deps.storage, &receiver)?;
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(&receiver)?
+        .checked_add(claim.amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    Balances::from_storage(&mut deps.storage).set_balance(&receiver, account_balance)?;
 
-    let symbol = read_constants(&deps.storage)?.symbol;
+    remove_claim(&mut deps.storage, &hash);
 
-    store_transfer(&deps.api, &mut deps.storage, &owner_address_raw, &recipient_address_raw, amount, symbol);
+    if !expired {
+        store_transfer(&mut deps.storage, &claim.creator, &receiver, &Uint128(claim.amount), env.block.time)?;
+    }
 
     let res = HandleResponse {
         messages: vec![],
         log: vec![
-            log("action", "transfer_from"),
-            log(
-                "spender",
-                deps.api.human_address(&env.message.sender)?.as_str(),
-            ),
-            log("sender", owner.as_str()),
-            log("recipient", recipient.as_str()),
+            log("action", if expired { "refund_claim" } else { "redeem_claim" }),
+            log("recipient", deps.api.human_address(&receiver)?.as_str()),
         ],
         data: None,
     };
@@ -483,17 +2259,43 @@ fn try_approve<S: Storage, A: Api, Q: Querier>(
     env: Env,
     spender: &HumanAddr,
     amount: &Uint128,
+    expected_current: Option<Uint128>,
+    notify: bool,
 ) -> StdResult<HandleResponse> {
     let owner_address_raw = &env.message.sender;
     let spender_address_raw = deps.api.canonical_address(spender)?;
-    write_allowance(
+    let spender_code_hash = if notify {
+        let code_hash = get_receiver_hash(&deps.storage, &spender_address_raw).ok_or_else(|| {
+            generic_err("The spender didn't register to receive callbacks, it can't be notified")
+        })?;
+        Some(code_hash?)
+    } else {
+        None
+    };
+    update_allowance(
         &mut deps.storage,
         &owner_address_raw,
         &spender_address_raw,
-        amount.u128(),
+        |allowance| match expected_current {
+            Some(expected_current) if allowance != expected_current.u128() => {
+                Err(generic_err(format!(
+                    "Allowance changed: allowance={}, expected={}",
+                    allowance, expected_current
+                )))
+            }
+            _ => Ok(amount.u128()),
+        },
     )?;
+
+    let mut messages = vec![];
+    if let Some(code_hash) = spender_code_hash {
+        let owner = deps.api.human_address(owner_address_raw)?;
+        let allowance_msg = Snip20AllowanceMsg::new(owner, *amount);
+        messages.push(allowance_msg.into_cosmos_msg(code_hash, spender.clone())?);
+    }
+
     let res = HandleResponse {
-        messages: vec![],
+        messages,
         log: vec![
             log("action", "approve"),
             log(
@@ -520,28 +2322,27 @@ fn try_burn<S: Storage, A: Api, Q: Querier>(
     let owner_address_raw = &env.message.sender;
     let amount_raw = amount.u128();
 
-    let mut account_balance = read_balance(&deps.storage, owner_address_raw)?;
-
-    if account_balance < amount_raw {
-        return Err(generic_err(format!(
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances.balance(owner_address_raw)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
             "insufficient funds to burn: balance={}, required={}",
             account_balance, amount_raw
-        )));
-    }
-    account_balance -= amount_raw;
-
-    let mut balances_store = PrefixedStorage::new(PREFIX_BALANCES, &mut deps.storage);
-    balances_store.set(owner_address_raw.as_slice(), &account_balance.to_be_bytes());
+        ))
+    })?;
+    balances.set_balance(owner_address_raw, account_balance)?;
 
-    let mut config_store = PrefixedStorage::new(PREFIX_CONFIG, &mut deps.storage);
-    let data = config_store
-        .get(KEY_TOTAL_SUPPLY)
-        .expect("no total supply data stored");
-    let mut total_supply = bytes_to_u128(&data).unwrap();
+    write_total_supply(&mut deps.storage, SupplyChange::Burn(amount_raw))?;
 
-    total_supply -= amount_raw;
-
-    config_store.set(KEY_TOTAL_SUPPLY, &total_supply.to_be_bytes());
+    store_burn(
+        &mut deps.storage,
+        owner_address_raw,
+        &env.contract.address,
+        amount,
+        env.block.time,
+        env.block.height,
+        BURN_REFERENCE,
+    )?;
 
     let res = HandleResponse {
         messages: vec![],
@@ -559,91 +2360,157 @@ fn try_burn<S: Storage, A: Api, Q: Querier>(
     Ok(res)
 }
 
-fn perform_transfer<T: Storage>(
-    store: &mut T,
-    from: &CanonicalAddr,
-    to: &CanonicalAddr,
-    amount: u128,
-) -> StdResult<()> {
-    let mut balances_store = PrefixedStorage::new(PREFIX_BALANCES, store);
+/// Splits the supported coins sent with this message between all holders, pro rata to their
+/// balances. The coins back the tokens holders get when they claim their rewards
+fn try_distribute_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    if get_staking_state(&deps.storage)?.is_some() {
+        return Err(generic_err(
+            "In staking mode rewards are distributed through the exchange rate",
+        ));
+    }
 
-    let mut from_balance = read_u128(&balances_store, from.as_slice())?;
-    if from_balance < amount {
-        return Err(generic_err(format!(
-            "Insufficient funds: balance={}, required={}",
-            from_balance, amount
-        )));
+    let funds = supported_funds(&deps.storage, &env)?;
+    let amount = funds
+        .iter()
+        .try_fold(0u128, |sum, (_, amount)| sum.checked_add(*amount))
+        .ok_or_else(|| generic_err("Reward amount overflow"))?;
+    if amount == 0 {
+        return Err(generic_err("No supported coins sent to distribute"));
     }
-    from_balance -= amount;
-    balances_store.set(from.as_slice(), &from_balance.to_be_bytes());
 
-    let mut to_balance = read_u128(&balances_store, to.as_slice())?;
-    to_balance += amount;
-    balances_store.set(to.as_slice(), &to_balance.to_be_bytes());
+    let total_supply = ReadonlyConfig::from_storage(&deps.storage).total_supply()?;
+    if total_supply == 0 {
+        return Err(generic_err("There are no holders to distribute rewards to"));
+    }
 
-    Ok(())
-}
+    let added = native_to_tokens(&deps.storage, amount)?
+        .checked_mul(REWARD_SCALE)
+        .ok_or_else(|| generic_err("Reward overflow"))?
+        / total_supply;
+    let reward_per_token = read_reward_per_token(&deps.storage)?
+        .checked_add(added)
+        .ok_or_else(|| generic_err("Reward overflow"))?;
+    write_reward_per_token(&mut deps.storage, reward_per_token);
+    add_to_reserves(&mut deps.storage, &funds)?;
 
-// Converts 16 bytes value into u128
-// Errors if data found that is not 16 bytes
-pub fn bytes_to_u128(data: &[u8]) -> StdResult<u128> {
-    match data[0..16].try_into() {
-        Ok(bytes) => Ok(u128::from_be_bytes(bytes)),
-        Err(_) => Err(generic_err(
-            "Corrupted data found. 16 byte expected.",
-        )),
-    }
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "distribute_rewards"),
+            log("amount", &amount.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
 }
 
-// Reads 16 byte storage value into u128
-// Returns zero if key does not exist. Errors if data found that is not 16 bytes
-pub fn read_u128<S: ReadonlyStorage>(store: &S, key: &[u8]) -> StdResult<u128> {
-    let result = store.get(key);
-    match result {
-        Some(data) => bytes_to_u128(&data),
-        None => Ok(0u128),
+/// Mints the sender's rewards into their balance
+fn try_claim_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let account = &env.message.sender;
+
+    let rewards = take_rewards(&mut deps.storage, account)?;
+    if rewards == 0 {
+        return Err(generic_err("No rewards to claim"));
     }
-}
 
-fn write_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr, key: &ViewingKey) -> StdResult<()> {
-    let mut balance_store = PrefixedStorage::new(PREFIX_VIEW_KEY, store);
-    balance_store.set(owner.as_slice(), key.to_hashed().as_ref());
-    Ok(())
-}
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances
+        .balance(account)?
+        .checked_add(rewards)
+        .ok_or_else(|| generic_err("Rewards overflow the account balance"))?;
+    balances.set_balance(account, account_balance)?;
 
+    write_total_supply(&mut deps.storage, SupplyChange::Mint(rewards))?;
 
-fn read_viewing_key<S: Storage>(store: &S, owner: &CanonicalAddr) -> Option<Vec<u8>> {
-    let balance_store = ReadonlyPrefixedStorage::new(PREFIX_VIEW_KEY, store);
-    balance_store.get(owner.as_slice())
-}
+    store_mint(
+        &mut deps.storage,
+        &env.contract.address,
+        account,
+        &Uint128(rewards),
+        env.block.time,
+        env.block.height,
+        REWARDS_REFERENCE,
+    )?;
 
-fn read_balance<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<u128> {
-    let balance_store = ReadonlyPrefixedStorage::new(PREFIX_BALANCES, store);
-    read_u128(&balance_store, owner.as_slice())
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "claim_rewards"),
+            log("account", deps.api.human_address(account)?.as_str()),
+            log("amount", &rewards.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
 }
 
-fn read_allowance<S: Storage>(
-    store: &S,
-    owner: &CanonicalAddr,
-    spender: &CanonicalAddr,
-) -> StdResult<u128> {
-    let allowances_store = ReadonlyPrefixedStorage::new(PREFIX_ALLOWANCES, store);
-    let owner_store = ReadonlyPrefixedStorage::new(owner.as_slice(), &allowances_store);
-    read_u128(&owner_store, spender.as_slice())
+pub fn query_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+    let rewards = pending_rewards(&deps.storage, &address)?;
+
+    to_binary(&RewardsResponse {
+        rewards: Uint128(rewards),
+    })
 }
 
-fn write_allowance<S: Storage>(
-    store: &mut S,
-    owner: &CanonicalAddr,
-    spender: &CanonicalAddr,
+pub fn perform_transfer<T: Storage>(
+    store: &mut T,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
     amount: u128,
 ) -> StdResult<()> {
-    let mut allowances_store = PrefixedStorage::new(PREFIX_ALLOWANCES, store);
-    let mut owner_store = PrefixedStorage::new(owner.as_slice(), &mut allowances_store);
-    owner_store.set(spender.as_slice(), &amount.to_be_bytes());
+    check_compliance(store, from, to)?;
+    check_frozen(store, from, to)?;
+    ledger_transfer(store, from, to, amount)?;
+
+    let mut balances = Balances::from_storage(store);
+
+    // `from` and `to` may be the same account, so the credit has to read the debited balance
+    let from_balance = debit(balances.balance(from)?, amount)?;
+    balances.set_balance(from, from_balance)?;
+
+    let to_balance = credit(balances.balance(to)?, amount)?;
+    balances.set_balance(to, to_balance)?;
+
     Ok(())
 }
 
+/// The sender's balance after `amount` leaves it
+pub fn debit(balance: u128, amount: u128) -> StdResult<u128> {
+    balance.checked_sub(amount).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            balance, amount
+        ))
+    })
+}
+
+/// The recipient's balance after `amount` arrives
+pub fn credit(balance: u128, amount: u128) -> StdResult<u128> {
+    balance
+        .checked_add(amount)
+        .ok_or_else(|| generic_err("Transfer overflows the recipient's balance"))
+}
+
+/// What's left of an allowance after the spender uses `amount` of it
+pub fn spend_allowance(allowance: u128, amount: u128) -> StdResult<u128> {
+    allowance.checked_sub(amount).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient allowance: allowance={}, required={}",
+            allowance, amount
+        ))
+    })
+}
+
 fn is_valid_name(name: &str) -> bool {
     let bytes = name.as_bytes();
     if bytes.len() < 3 || bytes.len() > 30 {
@@ -665,22 +2532,8 @@ fn is_valid_symbol(symbol: &str) -> bool {
     true
 }
 
-fn read_constants<S: Storage>(
-    store: &S,
-) -> StdResult<Constants> {
-    let config_store = ReadonlyPrefixedStorage::new(PREFIX_CONFIG, store);
-    let consts_bytes = config_store.get(KEY_CONSTANTS).unwrap();
-
-    let consts: Constants = bincode2::deserialize(&consts_bytes).unwrap();
-
-    Ok(consts)
-}
-
 fn to_display_token(amount: u128, symbol: &String, decimals: u8) -> String {
-
-    let base: u32 = 10;
-
-    let amnt: Decimal = Decimal::from_ratio(amount, (base.pow(decimals.into())) as u64);
+    let amnt = format_amount(amount, decimals, decimals, Rounding::Down);
 
     format!("{} {}", amnt, symbol)
 }
@@ -691,4 +2544,1113 @@ fn to_display_token(amount: u128, symbol: &String, decimals: u8) -> String {
 //     _msg: MigrateMsg,
 // ) -> StdResult<MigrateResponse> {
 //     Ok(MigrateResponse::default())
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{from_binary, HumanAddr, WasmMsg};
+    use cosmwasm_storage::PrefixedStorage;
+
+    use crate::attestation::{AttestFundsResponse, AttestationResponse};
+    use crate::clawback::ClawbacksResponse;
+    #[cfg(feature = "compliance")]
+    use crate::compliance::{ComplianceMode, ComplianceStatus};
+    use crate::cw20::TokenInfoResponse;
+    use crate::inheritance::MIN_INACTIVITY_PERIOD;
+    use crate::limits::LIMIT_WINDOW;
+    use crate::msg::{QueryAnswer, TransferCountResponse, TransfersResponse};
+    use crate::receiver::ReceiverHandleMsg;
+    use crate::recovery::MIN_RECOVERY_DELAY;
+    use crate::state::{CONFIG_KEY, KEY_SYMBOL, PREFIX_ALLOWANCES};
+    use crate::utils::create_legacy_hashed_password;
+    use crate::testing::{
+        default_init_msg, env_at, handle_as, handle_with_funds, init_token, query_answer,
+        query_balance, set_viewing_key, MockDeps, CANONICAL_LENGTH,
+    };
+
+    const ALICE_KEY: &str = "api_key_AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const BOB_KEY: &str = "api_key_BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+
+    fn new_token(initial_balances: &[(&str, u128)]) -> MockDeps {
+        let mut deps = init_token("admin", default_init_msg(initial_balances)).unwrap();
+        set_viewing_key(&mut deps, "alice", ALICE_KEY).unwrap();
+        set_viewing_key(&mut deps, "bob", BOB_KEY).unwrap();
+        deps
+    }
+
+    fn uscrt(amount: u128) -> Vec<Coin> {
+        vec![Coin {
+            denom: "uscrt".to_string(),
+            amount: Uint128(amount),
+        }]
+    }
+
+    fn balance(deps: &MockDeps, address: &str) -> u128 {
+        let address = deps.api.canonical_address(&HumanAddr::from(address)).unwrap();
+        ReadonlyBalances::from_storage(&deps.storage).balance(&address).unwrap()
+    }
+
+    fn allowance(deps: &MockDeps, owner: &str, spender: &str) -> u128 {
+        let owner = deps.api.canonical_address(&HumanAddr::from(owner)).unwrap();
+        let spender = deps.api.canonical_address(&HumanAddr::from(spender)).unwrap();
+        read_allowance(&deps.storage, &owner, &spender).unwrap()
+    }
+
+    fn total_supply(deps: &MockDeps) -> u128 {
+        read_total_supply(&deps.storage).unwrap()
+    }
+
+    fn transfer(recipient: &str, amount: u128) -> HandleMsg {
+        HandleMsg::Transfer {
+            recipient: HumanAddr::from(recipient),
+            amount: Uint128(amount),
+        }
+    }
+
+    // Init
+
+    #[test]
+    fn init_sums_initial_balances_into_the_supply() {
+        let deps = new_token(&[("alice", 300), ("bob", 200)]);
+
+        let info: TokenInfoResponse = query_answer(&deps, QueryMsg::TokenInfo {}).unwrap();
+        assert_eq!(info.symbol, "SECSEC");
+        assert_eq!(info.total_supply, Uint128(500));
+        assert_eq!(balance(&deps, "alice"), 300);
+    }
+
+    #[test]
+    fn init_rejects_invalid_settings() {
+        let mut msg = default_init_msg(&[]);
+        msg.symbol = "secsec".to_string();
+        assert!(init_token("admin", msg).is_err());
+
+        let mut msg = default_init_msg(&[]);
+        msg.name = "ab".to_string();
+        assert!(init_token("admin", msg).is_err());
+
+        let mut msg = default_init_msg(&[]);
+        msg.decimals = 19;
+        assert!(init_token("admin", msg).is_err());
+
+        let mut msg = default_init_msg(&[]);
+        msg.history_limit = Some(0);
+        assert!(init_token("admin", msg).is_err());
+    }
+
+    #[test]
+    fn init_rejects_initial_balances_overflowing_the_supply() {
+        let msg = default_init_msg(&[("alice", u128::MAX), ("bob", 1)]);
+        assert!(init_token("admin", msg).is_err());
+    }
+
+    #[test]
+    fn symbol_falls_back_to_the_constants() {
+        let mut deps = new_token(&[]);
+        // tokens created before the symbol was stored on its own only have the constants
+        VersionedStorage::new(CONFIG_KEY, &mut deps.storage).remove(KEY_SYMBOL);
+
+        assert_eq!(ReadonlyConfig::from_storage(&deps.storage).symbol().unwrap(), "SECSEC");
+        let info: TokenInfoResponse = query_answer(&deps, QueryMsg::TokenInfo {}).unwrap();
+        assert_eq!(info.symbol, "SECSEC");
+    }
+
+    // Transfers and allowances
+
+    #[test]
+    fn transfer_records_both_histories() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        handle_as(&mut deps, "alice", transfer("bob", 400)).unwrap();
+
+        assert_eq!(balance(&deps, "alice"), 600);
+        assert_eq!(balance(&deps, "bob"), 400);
+        assert_eq!(total_supply(&deps), 1_000);
+
+        let history: TransfersResponse = query_answer(
+            &deps,
+            QueryMsg::Transfers {
+                address: HumanAddr::from("bob"),
+                key: BOB_KEY.to_string(),
+                page: None,
+                page_size: None,
+                direction: None,
+                counterparty: None,
+                start_time: None,
+                end_time: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(history.txs.len(), 1);
+        assert_eq!(history.txs[0].sender, HumanAddr::from("alice"));
+        assert_eq!(history.txs[0].coins.amount, Uint128(400));
+    }
+
+    #[test]
+    fn failed_transfer_changes_nothing() {
+        let mut deps = new_token(&[("alice", 100)]);
+        assert!(handle_as(&mut deps, "alice", transfer("bob", 101)).is_err());
+
+        assert_eq!(balance(&deps, "alice"), 100);
+        assert_eq!(balance(&deps, "bob"), 0);
+        let count: TransferCountResponse = query_answer(
+            &deps,
+            QueryMsg::TransferCount {
+                address: HumanAddr::from("alice"),
+                key: ALICE_KEY.to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(count.count, 0);
+    }
+
+    #[test]
+    fn transfer_from_spends_the_allowance() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let approve = HandleMsg::Approve {
+            spender: HumanAddr::from("bob"),
+            amount: Uint128(300),
+            expected_current: None,
+            notify: None,
+        };
+        handle_as(&mut deps, "alice", approve).unwrap();
+
+        let transfer_from = |amount| HandleMsg::TransferFrom {
+            owner: HumanAddr::from("alice"),
+            recipient: HumanAddr::from("carol"),
+            amount: Uint128(amount),
+        };
+        handle_as(&mut deps, "bob", transfer_from(200)).unwrap();
+        assert_eq!(allowance(&deps, "alice", "bob"), 100);
+        assert_eq!(balance(&deps, "carol"), 200);
+
+        assert!(handle_as(&mut deps, "bob", transfer_from(101)).is_err());
+        assert!(handle_as(&mut deps, "carol", transfer_from(1)).is_err());
+        assert_eq!(balance(&deps, "alice"), 800);
+    }
+
+    #[test]
+    fn approve_checks_the_expected_allowance() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let approve = |amount, expected_current: Option<u128>| HandleMsg::Approve {
+            spender: HumanAddr::from("bob"),
+            amount: Uint128(amount),
+            expected_current: expected_current.map(Uint128),
+            notify: None,
+        };
+        handle_as(&mut deps, "alice", approve(300, None)).unwrap();
+
+        assert!(handle_as(&mut deps, "alice", approve(50, Some(200))).is_err());
+        assert_eq!(allowance(&deps, "alice", "bob"), 300);
+
+        handle_as(&mut deps, "alice", approve(50, Some(300))).unwrap();
+        assert_eq!(allowance(&deps, "alice", "bob"), 50);
+    }
+
+    #[test]
+    fn send_from_callback_credits_the_owner() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let approve = HandleMsg::Approve {
+            spender: HumanAddr::from("bob"),
+            amount: Uint128(300),
+            expected_current: None,
+            notify: None,
+        };
+        handle_as(&mut deps, "alice", approve).unwrap();
+
+        let send_from = HandleMsg::SendFrom {
+            owner: HumanAddr::from("alice"),
+            recipient: HumanAddr::from("vault"),
+            recipient_code_hash: Some("vault code hash".to_string()),
+            amount: Uint128(200),
+            msg: None,
+        };
+        let res = handle_as(&mut deps, "bob", send_from).unwrap();
+        assert_eq!(balance(&deps, "vault"), 200);
+        assert_eq!(allowance(&deps, "alice", "bob"), 100);
+
+        let callback: ReceiverHandleMsg = match &res.messages[..] {
+            [CosmosMsg::Wasm(WasmMsg::Execute { msg, .. })] => from_binary(msg).unwrap(),
+            messages => panic!("unexpected messages: {:?}", messages),
+        };
+        let expected = ReceiverHandleMsg::Receive(Snip20ReceiveMsg::new(
+            HumanAddr::from("bob"),
+            HumanAddr::from("alice"),
+            Uint128(200),
+            None,
+        ));
+        assert_eq!(callback, expected);
+    }
+
+    fn transfer_and_call(amount: u128) -> HandleMsg {
+        HandleMsg::TransferAndCall {
+            recipient: HumanAddr::from("router"),
+            recipient_code_hash: Some("router code hash".to_string()),
+            amount: Uint128(amount),
+            msg: None,
+            code_hash: "token code hash".to_string(),
+        }
+    }
+
+    #[test]
+    fn calls_refund_what_the_recipient_reports_unspent() {
+        let mut deps = new_token(&[("alice", 1_000), ("carol", 1_000)]);
+        handle_as(&mut deps, "alice", transfer_and_call(600)).unwrap();
+        assert!(handle_as(&mut deps, "carol", transfer_and_call(1)).is_err());
+
+        // the router passes 400 on, and gets paid by someone else meanwhile
+        handle_as(&mut deps, "router", transfer("pool", 400)).unwrap();
+        handle_as(&mut deps, "carol", transfer("router", 50)).unwrap();
+        let report = |amount| HandleMsg::ReportUnspent {
+            amount: Uint128(amount),
+        };
+        assert!(handle_as(&mut deps, "carol", report(200)).is_err());
+        assert!(handle_as(&mut deps, "router", report(601)).is_err());
+        handle_as(&mut deps, "router", report(200)).unwrap();
+
+        let settle = || HandleMsg::SettleCall { id: 1 };
+        assert!(handle_as(&mut deps, "router", settle()).is_err());
+        handle_as(&mut deps, MOCK_CONTRACT_ADDR, settle()).unwrap();
+        assert_eq!(balance(&deps, "alice"), 600);
+        assert_eq!(balance(&deps, "router"), 50);
+        assert_eq!(balance(&deps, "pool"), 400);
+
+        // settled calls are gone, so the router can be called again
+        handle_as(&mut deps, "carol", transfer_and_call(1)).unwrap();
+    }
+
+    #[test]
+    fn calls_without_a_report_refund_nothing() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        handle_as(&mut deps, "alice", transfer_and_call(600)).unwrap();
+        // e.g. the router kept a fee and sent the rest on
+        handle_as(&mut deps, "router", transfer("pool", 500)).unwrap();
+
+        handle_as(&mut deps, MOCK_CONTRACT_ADDR, HandleMsg::SettleCall { id: 1 }).unwrap();
+        assert_eq!(balance(&deps, "alice"), 400);
+        assert_eq!(balance(&deps, "router"), 100);
+    }
+
+    #[test]
+    fn blocked_accounts_only_accept_allowed_senders() {
+        let mut deps = new_token(&[("alice", 1_000), ("carol", 1_000)]);
+        let block = HandleMsg::BlockIncomingTransfers {
+            block: true,
+            allowed_senders: Some(vec![HumanAddr::from("carol")]),
+        };
+        handle_as(&mut deps, "bob", block).unwrap();
+
+        assert!(handle_as(&mut deps, "alice", transfer("bob", 1)).is_err());
+        handle_as(&mut deps, "carol", transfer("bob", 1)).unwrap();
+        assert_eq!(balance(&deps, "bob"), 1);
+
+        let unblock = HandleMsg::BlockIncomingTransfers {
+            block: false,
+            allowed_senders: None,
+        };
+        handle_as(&mut deps, "bob", unblock).unwrap();
+        handle_as(&mut deps, "alice", transfer("bob", 1)).unwrap();
+        assert_eq!(balance(&deps, "bob"), 2);
+    }
+
+    #[test]
+    fn clawback_needs_the_init_flag_and_a_reason() {
+        let clawback = |reason: &str| HandleMsg::Clawback {
+            owner: HumanAddr::from("alice"),
+            recipient: HumanAddr::from("admin"),
+            amount: Uint128(400),
+            reason: reason.to_string(),
+        };
+        let mut deps = new_token(&[("alice", 1_000)]);
+        assert!(handle_as(&mut deps, "admin", clawback("court order")).is_err());
+
+        let mut msg = default_init_msg(&[("alice", 1_000)]);
+        msg.clawback = Some(true);
+        let mut deps = init_token("admin", msg).unwrap();
+        assert!(handle_as(&mut deps, "bob", clawback("court order")).is_err());
+        assert!(handle_as(&mut deps, "admin", clawback(" ")).is_err());
+
+        handle_as(&mut deps, "admin", clawback("court order")).unwrap();
+        assert_eq!(balance(&deps, "alice"), 600);
+        assert_eq!(balance(&deps, "admin"), 400);
+        let clawbacks: ClawbacksResponse = query_answer(
+            &deps,
+            QueryMsg::Clawbacks {
+                page: None,
+                page_size: None,
+            },
+        )
+        .unwrap();
+        assert!(clawbacks.enabled);
+        assert_eq!(clawbacks.clawbacks[0].reason, "court order");
+    }
+
+    #[test]
+    fn frozen_accounts_cant_send_or_receive() {
+        let mut msg = default_init_msg(&[("alice", 1_000), ("bob", 1_000)]);
+        msg.freezing = Some(true);
+        let mut deps = init_token("admin", msg).unwrap();
+        let freeze = || HandleMsg::FreezeAccount {
+            account: HumanAddr::from("bob"),
+        };
+        assert!(handle_as(&mut deps, "alice", freeze()).is_err());
+        handle_as(&mut deps, "admin", freeze()).unwrap();
+
+        assert!(handle_as(&mut deps, "alice", transfer("bob", 1)).is_err());
+        assert!(handle_as(&mut deps, "bob", transfer("alice", 1)).is_err());
+
+        let unfreeze = HandleMsg::UnfreezeAccount {
+            account: HumanAddr::from("bob"),
+        };
+        handle_as(&mut deps, "admin", unfreeze).unwrap();
+        handle_as(&mut deps, "bob", transfer("alice", 1)).unwrap();
+        assert_eq!(balance(&deps, "alice"), 1_001);
+    }
+
+    #[test]
+    fn frozen_accounts_cant_collect_what_was_set_aside_for_them() {
+        let mut msg = default_init_msg(&[("alice", 1_000)]);
+        msg.freezing = Some(true);
+        let mut deps = init_token("admin", msg).unwrap();
+        let pending = HandleMsg::TransferPending {
+            recipient: HumanAddr::from("bob"),
+            amount: Uint128(100),
+            expiry: u64::MAX,
+        };
+        handle_as(&mut deps, "alice", pending).unwrap();
+        let escrow = HandleMsg::CreateEscrow {
+            payee: HumanAddr::from("bob"),
+            arbiter: HumanAddr::from("carol"),
+            amount: Uint128(100),
+            expiry: u64::MAX,
+        };
+        handle_as(&mut deps, "alice", escrow).unwrap();
+        let freeze = HandleMsg::FreezeAccount {
+            account: HumanAddr::from("bob"),
+        };
+        handle_as(&mut deps, "admin", freeze).unwrap();
+
+        let err = handle_as(&mut deps, "bob", HandleMsg::ClaimTransfer { id: 1 }).unwrap_err();
+        assert!(err.to_string().contains("frozen"), "{}", err);
+        let err = handle_as(&mut deps, "carol", HandleMsg::ReleaseEscrow { id: 1 }).unwrap_err();
+        assert!(err.to_string().contains("frozen"), "{}", err);
+        assert_eq!(balance(&deps, "bob"), 0);
+    }
+
+    /// A token where alice has deposited uscrt, given bob an allowance and authorized "puller"
+    fn token_with_debitable_alice(msg: InitMsg) -> MockDeps {
+        let mut deps = init_token("admin", msg).unwrap();
+        handle_with_funds(&mut deps, "alice", &uscrt(5_000), HandleMsg::Deposit {}).unwrap();
+        let approve = HandleMsg::Approve {
+            spender: HumanAddr::from("bob"),
+            amount: Uint128(1_000),
+            expected_current: None,
+            notify: None,
+        };
+        handle_as(&mut deps, "alice", approve).unwrap();
+        let authorize = HandleMsg::AuthorizePull {
+            contract: HumanAddr::from("puller"),
+            max_amount: Uint128(1_000),
+            frequency: 1,
+        };
+        handle_as(&mut deps, "alice", authorize).unwrap();
+        deps
+    }
+
+    /// Every way of getting funds out of alice's balance, with who sends it
+    fn debiting_messages() -> Vec<(&'static str, HandleMsg)> {
+        let amount = Uint128(100);
+        let bob = || HumanAddr::from("bob");
+        vec![
+            ("alice", HandleMsg::Withdraw { amount, denom: None }),
+            (
+                "alice",
+                HandleMsg::RedeemAndSend {
+                    recipient: bob(),
+                    amount,
+                    denom: None,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::CloseAccount {
+                    redeem_to: None,
+                    spenders: None,
+                },
+            ),
+            ("alice", HandleMsg::Burn { amount }),
+            (
+                "alice",
+                HandleMsg::TransferPending {
+                    recipient: bob(),
+                    amount,
+                    expiry: u64::MAX,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::CreateClaim {
+                    hash: Binary(vec![0; 32]),
+                    amount,
+                    expiry: u64::MAX,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::CreateEscrow {
+                    payee: bob(),
+                    arbiter: HumanAddr::from("carol"),
+                    amount,
+                    expiry: u64::MAX,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::TransferVesting {
+                    recipient: bob(),
+                    amount,
+                    cliff: 0,
+                    duration: 100,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::TransferLocked {
+                    recipient: bob(),
+                    amount,
+                    unlock_at: u64::MAX,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::OpenChannel {
+                    recipient: bob(),
+                    amount,
+                    expiry: u64::MAX,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::CreateStream {
+                    recipient: bob(),
+                    amount,
+                    start: 0,
+                    end: u64::MAX,
+                },
+            ),
+            (
+                "alice",
+                HandleMsg::UnwrapToken {
+                    token: HumanAddr::from("wrapped"),
+                    amount,
+                },
+            ),
+            (
+                "bob",
+                HandleMsg::TransferFrom {
+                    owner: HumanAddr::from("alice"),
+                    recipient: bob(),
+                    amount,
+                },
+            ),
+            (
+                "bob",
+                HandleMsg::SendFrom {
+                    owner: HumanAddr::from("alice"),
+                    recipient: bob(),
+                    recipient_code_hash: None,
+                    amount,
+                    msg: None,
+                },
+            ),
+            (
+                "puller",
+                HandleMsg::Pull {
+                    owner: HumanAddr::from("alice"),
+                    amount,
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn frozen_accounts_cant_move_funds_out_any_way() {
+        let mut msg = default_init_msg(&[]);
+        msg.freezing = Some(true);
+        let mut deps = token_with_debitable_alice(msg);
+        let freeze = HandleMsg::FreezeAccount {
+            account: HumanAddr::from("alice"),
+        };
+        handle_as(&mut deps, "admin", freeze).unwrap();
+
+        for (sender, msg) in debiting_messages() {
+            let json = String::from_utf8(to_binary(&msg).unwrap().0).unwrap();
+            let err = handle_as(&mut deps, sender, msg).unwrap_err();
+            assert!(err.to_string().contains("frozen"), "{} failed with {}", json, err);
+        }
+        assert_eq!(balance(&deps, "alice"), 5_000);
+        assert_eq!(allowance(&deps, "alice", "bob"), 1_000);
+    }
+
+    #[test]
+    fn attestations_prove_a_minimum_balance() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let attest = |min_amount| HandleMsg::AttestFunds {
+            min_amount: Uint128(min_amount),
+        };
+        assert!(handle_as(&mut deps, "alice", attest(1_001)).is_err());
+
+        let res = handle_as(&mut deps, "alice", attest(800)).unwrap();
+        let attested: AttestFundsResponse = from_binary(&res.data.unwrap()).unwrap();
+        let attestation: AttestationResponse =
+            query_answer(&deps, QueryMsg::Attestation { id: attested.id }).unwrap();
+        assert_eq!(attestation.address, HumanAddr::from("alice"));
+        assert_eq!(attestation.min_amount, Uint128(800));
+    }
+
+    #[cfg(feature = "compliance")]
+    #[test]
+    fn denied_accounts_cant_move_funds_out_any_way() {
+        let mut msg = default_init_msg(&[]);
+        msg.compliance = Some(ComplianceMode::Denylist);
+        let mut deps = token_with_debitable_alice(msg);
+        let deny = HandleMsg::SetComplianceStatus {
+            addresses: vec![HumanAddr::from("alice")],
+            status: ComplianceStatus::Denied,
+        };
+        handle_as(&mut deps, "admin", deny).unwrap();
+
+        for (sender, msg) in debiting_messages() {
+            let json = String::from_utf8(to_binary(&msg).unwrap().0).unwrap();
+            let err = handle_as(&mut deps, sender, msg).unwrap_err();
+            assert!(err.to_string().contains("compliance"), "{} failed with {}", json, err);
+        }
+        assert_eq!(balance(&deps, "alice"), 5_000);
+        assert_eq!(allowance(&deps, "alice", "bob"), 1_000);
+    }
+
+    #[cfg(feature = "compliance")]
+    #[test]
+    fn denied_accounts_cant_collect_what_was_set_aside_for_them() {
+        let mut msg = default_init_msg(&[("alice", 1_000), ("admin", 1_000)]);
+        msg.compliance = Some(ComplianceMode::Denylist);
+        let mut deps = init_token("admin", msg).unwrap();
+        let pending = HandleMsg::TransferPending {
+            recipient: HumanAddr::from("bob"),
+            amount: Uint128(100),
+            expiry: u64::MAX,
+        };
+        handle_as(&mut deps, "alice", pending).unwrap();
+        let deny = HandleMsg::SetComplianceStatus {
+            addresses: vec![HumanAddr::from("bob")],
+            status: ComplianceStatus::Denied,
+        };
+        handle_as(&mut deps, "admin", deny).unwrap();
+
+        let err = handle_as(&mut deps, "bob", HandleMsg::ClaimTransfer { id: 1 }).unwrap_err();
+        assert!(err.to_string().contains("compliance"), "{}", err);
+        let airdrop = HandleMsg::Airdrop {
+            recipients: vec![crate::msg::InitialBalance {
+                address: HumanAddr::from("bob"),
+                amount: Uint128(100),
+            }],
+            amount: Some(Uint128(100)),
+        };
+        let err = handle_as(&mut deps, "admin", airdrop).unwrap_err();
+        assert!(err.to_string().contains("compliance"), "{}", err);
+        assert_eq!(balance(&deps, "bob"), 0);
+    }
+
+    // Co-signed transfers
+
+    #[test]
+    fn proposed_transfers_hold_the_amount_until_approved() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let propose = || HandleMsg::ProposeTransfer {
+            recipient: HumanAddr::from("bob"),
+            amount: Uint128(600),
+        };
+        assert!(handle_as(&mut deps, "alice", propose()).is_err());
+
+        let policy = HandleMsg::SetTransferPolicy {
+            co_signer: HumanAddr::from("carol"),
+            threshold: Uint128(100),
+        };
+        handle_as(&mut deps, "alice", policy).unwrap();
+        handle_as(&mut deps, "alice", propose()).unwrap();
+        assert_eq!(balance(&deps, "alice"), 400);
+        handle_as(&mut deps, "alice", HandleMsg::CancelProposedTransfer { id: 1 }).unwrap();
+        assert_eq!(balance(&deps, "alice"), 1_000);
+
+        handle_as(&mut deps, "alice", propose()).unwrap();
+        assert!(handle_as(&mut deps, "alice", HandleMsg::ApproveTransfer { id: 2 }).is_err());
+        handle_as(&mut deps, "carol", HandleMsg::ApproveTransfer { id: 2 }).unwrap();
+        assert_eq!(balance(&deps, "alice"), 400);
+        assert_eq!(balance(&deps, "bob"), 600);
+    }
+
+    // Inheritance
+
+    #[test]
+    fn inheritance_is_held_to_the_owners_limit() {
+        let mut deps = new_token(&[("alice", 400), ("carol", 600)]);
+        let now = mock_env(&deps.api, "alice", &[]).block.time;
+        let set_beneficiary = |inactivity_period| HandleMsg::SetBeneficiary {
+            address: Some(HumanAddr::from("bob")),
+            inactivity_period,
+        };
+        assert!(handle_as(&mut deps, "alice", set_beneficiary(LIMIT_WINDOW)).is_err());
+
+        let limit = HandleMsg::SetTransferLimit {
+            amount_per_day: Some(Uint128(500)),
+        };
+        handle_as(&mut deps, "alice", limit).unwrap();
+        handle_as(&mut deps, "alice", set_beneficiary(MIN_INACTIVITY_PERIOD)).unwrap();
+
+        let claim_at = |deps: &mut MockDeps, time| {
+            let claim = HandleMsg::ClaimInheritance {
+                owner: HumanAddr::from("alice"),
+            };
+            let snapshot = deps.storage.clone();
+            let res = handle(deps, env_at(deps, "bob", &[], time), claim);
+            if res.is_err() {
+                deps.storage = snapshot;
+            }
+            res
+        };
+        // the new beneficiary only counts once the limit's window passed
+        let err = claim_at(&mut deps, now + MIN_INACTIVITY_PERIOD).unwrap_err();
+        assert!(err.to_string().contains("too recently"), "{}", err);
+
+        handle_as(&mut deps, "carol", transfer("alice", 600)).unwrap();
+        let err = claim_at(&mut deps, now + LIMIT_WINDOW + MIN_INACTIVITY_PERIOD).unwrap_err();
+        assert!(err.to_string().contains("daily limit"), "{}", err);
+        assert_eq!(balance(&deps, "alice"), 1_000);
+    }
+
+    #[test]
+    fn recovery_waits_for_the_delay_and_the_owners_limit() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let now = mock_env(&deps.api, "alice", &[]).block.time;
+        let set_guardians = |guardian: &str, delay| HandleMsg::SetGuardians {
+            guardians: vec![HumanAddr::from(guardian)],
+            quorum: 1,
+            delay,
+        };
+        assert!(handle_as(&mut deps, "alice", set_guardians("carol", 60)).is_err());
+        handle_as(&mut deps, "alice", set_guardians("carol", MIN_RECOVERY_DELAY)).unwrap();
+
+        // replacing guardians waits for the current ones' delay
+        handle_as(&mut deps, "alice", set_guardians("dave", MIN_RECOVERY_DELAY)).unwrap();
+        let approve = || HandleMsg::ApproveRecovery {
+            account: HumanAddr::from("alice"),
+            recovery_address: HumanAddr::from("bob"),
+            viewing_key: None,
+        };
+        assert!(handle_as(&mut deps, "dave", approve()).is_err());
+        handle_as(&mut deps, "carol", approve()).unwrap();
+
+        let limit = HandleMsg::SetTransferLimit {
+            amount_per_day: Some(Uint128(500)),
+        };
+        handle_as(&mut deps, "alice", limit).unwrap();
+        let execute = HandleMsg::ExecuteRecovery {
+            account: HumanAddr::from("alice"),
+        };
+        let env = env_at(&deps, "bob", &[], now + MIN_RECOVERY_DELAY);
+        let err = handle(&mut deps, env, execute).unwrap_err();
+        assert!(err.to_string().contains("daily limit"), "{}", err);
+    }
+
+    // Deposits and withdrawals
+
+    #[test]
+    fn deposit_mints_and_fills_the_reserve() {
+        let mut deps = new_token(&[]);
+        handle_with_funds(&mut deps, "alice", &uscrt(5_000), HandleMsg::Deposit {}).unwrap();
+
+        assert_eq!(balance(&deps, "alice"), 5_000);
+        assert_eq!(total_supply(&deps), 5_000);
+        let reserve = ReadonlyConfig::from_storage(&deps.storage).reserve("uscrt").unwrap();
+        assert_eq!(reserve, 5_000);
+    }
+
+    #[test]
+    fn deposit_needs_supported_funds() {
+        let mut deps = new_token(&[]);
+        assert!(handle_as(&mut deps, "alice", HandleMsg::Deposit {}).is_err());
+
+        let other = vec![Coin {
+            denom: "uatom".to_string(),
+            amount: Uint128(5_000),
+        }];
+        assert!(handle_with_funds(&mut deps, "alice", &other, HandleMsg::Deposit {}).is_err());
+        assert_eq!(total_supply(&deps), 0);
+    }
+
+    #[test]
+    fn withdraw_burns_and_pays_out_from_the_reserve() {
+        let mut deps = new_token(&[]);
+        handle_with_funds(&mut deps, "alice", &uscrt(5_000), HandleMsg::Deposit {}).unwrap();
+
+        let withdraw = HandleMsg::Withdraw {
+            amount: Uint128(2_000),
+            denom: None,
+        };
+        let res = handle_as(&mut deps, "alice", withdraw).unwrap();
+
+        assert_eq!(balance(&deps, "alice"), 3_000);
+        assert_eq!(total_supply(&deps), 3_000);
+        let reserve = ReadonlyConfig::from_storage(&deps.storage).reserve("uscrt").unwrap();
+        assert_eq!(reserve, 3_000);
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(cosmwasm_std::testing::MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("alice"),
+                amount: uscrt(2_000),
+            })]
+        );
+    }
+
+    /// The mock bank doesn't run the messages the contract returns, so tests move its coins
+    fn set_contract_balance(deps: &mut MockDeps, coins: &[Coin]) {
+        deps.querier = mock_dependencies(CANONICAL_LENGTH, coins).querier;
+    }
+
+    fn reserve_backing(deps: &MockDeps) -> ReserveBackingResponse {
+        query_answer(deps, QueryMsg::ReserveBacking {}).unwrap()
+    }
+
+    #[test]
+    fn reserve_backing_covers_the_supply() {
+        let mut msg = default_init_msg(&[]);
+        msg.decimals = 18;
+        msg.supported_denoms = Some(vec!["uscrt".to_string(), "uatom".to_string()]);
+        let mut deps = init_token("admin", msg).unwrap();
+        let coins = |uscrt, uatom| {
+            vec![
+                Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128(uscrt),
+                },
+                Coin {
+                    denom: "uatom".to_string(),
+                    amount: Uint128(uatom),
+                },
+            ]
+        };
+        let backing = |uscrt, uatom| ReserveBackingResponse {
+            denoms: coins(uscrt, uatom)
+                .into_iter()
+                .map(|coin| DenomBacking {
+                    denom: coin.denom,
+                    balance: coin.amount,
+                    reserve: coin.amount,
+                })
+                .collect(),
+            total_supply_native: Uint128(uscrt + uatom),
+        };
+        handle_with_funds(&mut deps, "alice", &coins(5_000, 2_000), HandleMsg::Deposit {}).unwrap();
+        set_contract_balance(&mut deps, &coins(5_000, 2_000));
+        assert_eq!(reserve_backing(&deps), backing(5_000, 2_000));
+
+        let withdraw = HandleMsg::Withdraw {
+            amount: Uint128(1_500 * 10u128.pow(12)),
+            denom: Some("uatom".to_string()),
+        };
+        let res = handle_as(&mut deps, "alice", withdraw).unwrap();
+        let paid = match &res.messages[..] {
+            [CosmosMsg::Bank(BankMsg::Send { amount, .. })] => amount[0].amount.u128(),
+            messages => panic!("unexpected messages: {:?}", messages),
+        };
+        set_contract_balance(&mut deps, &coins(5_000, 2_000 - paid));
+        assert_eq!(reserve_backing(&deps), backing(5_000, 500));
+    }
+
+    #[test]
+    fn redeem_fee_keeps_what_doesnt_make_a_native_unit() {
+        let mut msg = default_init_msg(&[]);
+        msg.decimals = 18;
+        let mut deps = init_token("admin", msg).unwrap();
+        let fees = HandleMsg::SetFees {
+            deposit_fee_bps: 0,
+            redeem_fee_bps: 30,
+        };
+        handle_as(&mut deps, "admin", fees).unwrap();
+        handle_with_funds(&mut deps, "alice", &uscrt(5_000), HandleMsg::Deposit {}).unwrap();
+
+        // 0.3% of the amount, rounded up so the rest is worth a whole 1_994 uscrt
+        let units = 10u128.pow(12);
+        let withdraw = HandleMsg::Withdraw {
+            amount: Uint128(2_000 * units + 1_000),
+            denom: None,
+        };
+        let res = handle_as(&mut deps, "alice", withdraw).unwrap();
+        let paid = match &res.messages[..] {
+            [CosmosMsg::Bank(BankMsg::Send { amount, .. })] => amount[0].amount.u128(),
+            messages => panic!("unexpected messages: {:?}", messages),
+        };
+        assert_eq!(paid, 1_994);
+        assert_eq!(balance(&deps, MOCK_CONTRACT_ADDR), 6 * units + 1_000);
+        assert_eq!(balance(&deps, "alice"), 3_000 * units - 1_000);
+        assert_eq!(total_supply(&deps), 3_006 * units);
+    }
+
+    #[test]
+    fn withdraw_pays_out_of_the_chosen_denoms_reserve_after_the_fee() {
+        let mut msg = default_init_msg(&[]);
+        msg.decimals = 18;
+        msg.supported_denoms = Some(vec!["uscrt".to_string(), "uatom".to_string()]);
+        let mut deps = init_token("admin", msg).unwrap();
+        let fees = HandleMsg::SetFees {
+            deposit_fee_bps: 0,
+            redeem_fee_bps: 30,
+        };
+        handle_as(&mut deps, "admin", fees).unwrap();
+        let funds = vec![
+            Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint128(3_000),
+            },
+            Coin {
+                denom: "uatom".to_string(),
+                amount: Uint128(2_000),
+            },
+        ];
+        handle_with_funds(&mut deps, "alice", &funds, HandleMsg::Deposit {}).unwrap();
+
+        let withdraw = HandleMsg::Withdraw {
+            amount: Uint128(1_000 * 10u128.pow(12)),
+            denom: Some("uatom".to_string()),
+        };
+        let res = handle_as(&mut deps, "alice", withdraw).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("alice"),
+                amount: vec![Coin {
+                    denom: "uatom".to_string(),
+                    amount: Uint128(997),
+                }],
+            })]
+        );
+        let config = ReadonlyConfig::from_storage(&deps.storage);
+        assert_eq!(config.reserve("uscrt").unwrap(), 3_000);
+        assert_eq!(config.reserve("uatom").unwrap(), 1_003);
+    }
+
+    #[test]
+    fn withdraw_beyond_balance_fails() {
+        let mut deps = new_token(&[("alice", 10_000)]);
+        handle_with_funds(&mut deps, "bob", &uscrt(5_000), HandleMsg::Deposit {}).unwrap();
+
+        let withdraw = |amount| HandleMsg::Withdraw {
+            amount: Uint128(amount),
+            denom: None,
+        };
+        assert!(handle_as(&mut deps, "bob", withdraw(5_001)).is_err());
+        // initial balances aren't backed by the reserve
+        assert!(handle_as(&mut deps, "alice", withdraw(5_001)).is_err());
+        assert_eq!(total_supply(&deps), 15_000);
+    }
+
+    #[test]
+    fn burn_lowers_the_supply() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let burn = |amount| HandleMsg::Burn {
+            amount: Uint128(amount),
+        };
+        handle_as(&mut deps, "alice", burn(250)).unwrap();
+        assert!(handle_as(&mut deps, "alice", burn(751)).is_err());
+
+        assert_eq!(balance(&deps, "alice"), 750);
+        assert_eq!(total_supply(&deps), 750);
+    }
+
+    fn close_account(spenders: &[&str]) -> HandleMsg {
+        HandleMsg::CloseAccount {
+            redeem_to: None,
+            spenders: Some(spenders.iter().map(|spender| HumanAddr::from(*spender)).collect()),
+        }
+    }
+
+    #[test]
+    fn close_account_redeems_every_denom() {
+        let mut msg = default_init_msg(&[]);
+        msg.supported_denoms = Some(vec!["uscrt".to_string(), "uatom".to_string()]);
+        let mut deps = init_token("admin", msg).unwrap();
+        let funds = vec![
+            Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint128(3_000),
+            },
+            Coin {
+                denom: "uatom".to_string(),
+                amount: Uint128(2_000),
+            },
+        ];
+        handle_with_funds(&mut deps, "alice", &funds, HandleMsg::Deposit {}).unwrap();
+
+        let res = handle_as(&mut deps, "alice", close_account(&[])).unwrap();
+        let paid: Vec<Coin> = res
+            .messages
+            .into_iter()
+            .flat_map(|msg| match msg {
+                CosmosMsg::Bank(BankMsg::Send { amount, .. }) => amount,
+                msg => panic!("unexpected message: {:?}", msg),
+            })
+            .collect();
+        assert_eq!(paid, funds);
+        assert_eq!(total_supply(&deps), 0);
+        let config = ReadonlyConfig::from_storage(&deps.storage);
+        assert_eq!(config.reserve("uatom").unwrap(), 0);
+    }
+
+    #[test]
+    fn close_account_pays_whole_units_of_every_denom_after_the_fee() {
+        let mut msg = default_init_msg(&[]);
+        msg.decimals = 18;
+        msg.supported_denoms = Some(vec!["uscrt".to_string(), "uatom".to_string()]);
+        let mut deps = init_token("admin", msg).unwrap();
+        let fees = HandleMsg::SetFees {
+            deposit_fee_bps: 0,
+            redeem_fee_bps: 30,
+        };
+        handle_as(&mut deps, "admin", fees).unwrap();
+        let coins = |uscrt, uatom| {
+            vec![
+                Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128(uscrt),
+                },
+                Coin {
+                    denom: "uatom".to_string(),
+                    amount: Uint128(uatom),
+                },
+            ]
+        };
+        handle_with_funds(&mut deps, "alice", &coins(3_000, 2_000), HandleMsg::Deposit {}).unwrap();
+        // leaves alice with a balance that isn't whole native units
+        handle_as(&mut deps, "alice", transfer("bob", 1_000)).unwrap();
+
+        let res = handle_as(&mut deps, "alice", close_account(&[])).unwrap();
+        let paid: Vec<Coin> = res
+            .messages
+            .into_iter()
+            .flat_map(|msg| match msg {
+                CosmosMsg::Bank(BankMsg::Send { amount, .. }) => amount,
+                msg => panic!("unexpected message: {:?}", msg),
+            })
+            .collect();
+        let units = 10u128.pow(12);
+        // the uscrt part is short of 2_991 whole uscrt after the fee, so it rounds down
+        assert_eq!(paid, coins(2_990, 1_994));
+        assert_eq!(balance(&deps, "alice"), 0);
+        assert_eq!(balance(&deps, MOCK_CONTRACT_ADDR), 16 * units - 1_000);
+        assert_eq!(total_supply(&deps), 16 * units);
+        let config = ReadonlyConfig::from_storage(&deps.storage);
+        assert_eq!(config.reserve("uscrt").unwrap(), 10);
+        assert_eq!(config.reserve("uatom").unwrap(), 6);
+    }
+
+    #[test]
+    fn close_account_removes_legacy_allowances() {
+        let mut deps = new_token(&[]);
+        handle_with_funds(&mut deps, "alice", &uscrt(1_000), HandleMsg::Deposit {}).unwrap();
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        let bob = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        // the way allowances were stored before the spender list
+        let mut allowances = VersionedStorage::new(PREFIX_ALLOWANCES, &mut deps.storage);
+        let mut alice_allowances = PrefixedStorage::new(alice.as_slice(), &mut allowances);
+        alice_allowances.set(bob.as_slice(), &300u128.to_be_bytes());
+        let approve = HandleMsg::Approve {
+            spender: HumanAddr::from("carol"),
+            amount: Uint128(200),
+            expected_current: None,
+            notify: None,
+        };
+        handle_as(&mut deps, "alice", approve).unwrap();
+
+        handle_as(&mut deps, "alice", close_account(&["bob"])).unwrap();
+        assert_eq!(allowance(&deps, "alice", "bob"), 0);
+        assert_eq!(allowance(&deps, "alice", "carol"), 0);
+    }
+
+    #[test]
+    fn redeem_only_mode_still_allows_withdrawals() {
+        let mut deps = new_token(&[]);
+        handle_with_funds(&mut deps, "alice", &uscrt(5_000), HandleMsg::Deposit {}).unwrap();
+
+        assert!(handle_as(&mut deps, "alice", HandleMsg::PermanentRedeemOnly {}).is_err());
+        handle_as(&mut deps, "admin", HandleMsg::PermanentRedeemOnly {}).unwrap();
+
+        assert!(handle_as(&mut deps, "alice", transfer("bob", 1)).is_err());
+        assert!(handle_with_funds(&mut deps, "bob", &uscrt(1), HandleMsg::Deposit {}).is_err());
+        let withdraw = HandleMsg::Withdraw {
+            amount: Uint128(5_000),
+            denom: None,
+        };
+        handle_as(&mut deps, "alice", withdraw).unwrap();
+        assert_eq!(total_supply(&deps), 0);
+    }
+
+    // Viewing keys
+
+    #[test]
+    fn queries_need_the_right_viewing_key() {
+        let deps = new_token(&[("alice", 2_000_000)]);
+        assert_eq!(query_balance(&deps, "alice", ALICE_KEY).unwrap(), "2 SECSEC");
+
+        for (address, key) in &[("alice", BOB_KEY), ("carol", ALICE_KEY)] {
+            let msg = QueryMsg::Balance {
+                address: HumanAddr::from(*address),
+                key: key.to_string(),
+                denom: None,
+            };
+            let answer: QueryAnswer = from_binary(&query(&deps, msg).unwrap()).unwrap();
+            assert!(matches!(answer, QueryAnswer::ViewingKeyError { .. }));
+        }
+    }
+
+    #[test]
+    fn legacy_viewing_keys_still_work() {
+        let mut deps = new_token(&[("carol", 1_000_000)]);
+        let carol = deps.api.canonical_address(&HumanAddr::from("carol")).unwrap();
+        let legacy_hash = create_legacy_hashed_password(ALICE_KEY);
+        let mut keys = VersionedStorage::new(PREFIX_VIEW_KEY, &mut deps.storage);
+        keys.set(carol.as_slice(), &legacy_hash);
+
+        assert_eq!(query_balance(&deps, "carol", ALICE_KEY).unwrap(), "1 SECSEC");
+        assert!(!ViewingKey(ALICE_KEY.to_string()).check_viewing_key(&NO_VIEWING_KEY));
+        assert!(!ViewingKey(BOB_KEY.to_string()).check_viewing_key(&legacy_hash));
+    }
+
+    #[test]
+    fn created_viewing_key_is_returned_in_the_log() {
+        let mut deps = new_token(&[("carol", 1_000_000)]);
+        let create = HandleMsg::CreateViewingKey {
+            entropy: "some entropy".to_string(),
+        };
+        let res = handle_as(&mut deps, "carol", create).unwrap();
+        let key = res
+            .log
+            .iter()
+            .find(|log| log.key == "viewing key")
+            .map(|log| log.value.trim_end().to_string())
+            .unwrap();
+
+        assert_eq!(query_balance(&deps, "carol", &key).unwrap(), "1 SECSEC");
+    }
+
+    #[test]
+    fn responses_are_padded() {
+        let mut deps = new_token(&[("alice", 1_000)]);
+        let res = handle_as(&mut deps, "alice", transfer("bob", 1)).unwrap();
+        assert!(res.log.iter().all(|log| log.value.len() % RESPONSE_BLOCK_SIZE == 0));
+
+        let answer = query(&deps, QueryMsg::TokenInfo {}).unwrap();
+        assert_eq!(answer.len() % RESPONSE_BLOCK_SIZE, 0);
+    }
+}
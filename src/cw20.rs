@@ -0,0 +1,118 @@
+//! Answers queries shaped like cw20's, so tooling written for plain CosmWasm tokens only has to
+//! wrap its queries in `{"cw20": {"query": ..., "auth": ...}}`
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, to_binary, unauthorized, Api, Binary, CanonicalAddr, Extern, HumanAddr, Querier,
+    StdResult, Storage, Uint128,
+};
+
+use crate::contract::{is_viewing_key_valid, query_token_info, validate_permit, viewing_key_error};
+use crate::permit::{Permission, Permit};
+use crate::state::{read_allowance, ReadonlyBalances};
+use crate::viewing_key::ViewingKey;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20QueryMsg {
+    Balance { address: HumanAddr },
+    TokenInfo {},
+    Allowance { owner: HumanAddr, spender: HumanAddr },
+}
+
+/// Who is asking. `balance` is only answered for that account, `allowance` only for its owner
+/// or spender, and `token_info` doesn't need it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20Auth {
+    ViewingKey { address: HumanAddr, key: String },
+    Permit(Permit),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Cw20BalanceResponse {
+    pub balance: Uint128,
+}
+
+/// Also the answer to the token's own `token_info` query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenInfoResponse {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: Uint128,
+}
+
+/// Allowances here don't expire, so `expires` is always `{"never": {}}`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Cw20AllowanceResponse {
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiration {
+    Never {},
+}
+
+/// The account `auth` proves the query is from, `None` if the viewing key is wrong. Permits
+/// have to grant `permission`
+fn authenticate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: Option<&Cw20Auth>,
+    permission: Permission,
+) -> StdResult<Option<CanonicalAddr>> {
+    match auth {
+        Some(Cw20Auth::ViewingKey { address, key }) => {
+            let address = deps.api.canonical_address(address)?;
+            if is_viewing_key_valid(&deps.storage, &address, &ViewingKey(key.clone())) {
+                Ok(Some(address))
+            } else {
+                Ok(None)
+            }
+        }
+        Some(Cw20Auth::Permit(permit)) => validate_permit(deps, permit, permission).map(Some),
+        None => Err(generic_err("This query needs a viewing key or a permit")),
+    }
+}
+
+pub fn query_cw20<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    query: &Cw20QueryMsg,
+    auth: Option<&Cw20Auth>,
+) -> StdResult<Binary> {
+    match query {
+        Cw20QueryMsg::TokenInfo {} => query_token_info(deps),
+        Cw20QueryMsg::Balance { address } => {
+            let account = match authenticate(deps, auth, Permission::Balance)? {
+                Some(account) => account,
+                None => return viewing_key_error(),
+            };
+            if deps.api.canonical_address(address)? != account {
+                return Err(unauthorized());
+            }
+            let balance = ReadonlyBalances::from_storage(&deps.storage).balance(&account)?;
+            to_binary(&Cw20BalanceResponse {
+                balance: Uint128(balance),
+            })
+        }
+        Cw20QueryMsg::Allowance { owner, spender } => {
+            let account = match authenticate(deps, auth, Permission::Allowance)? {
+                Some(account) => account,
+                None => return viewing_key_error(),
+            };
+            let owner = deps.api.canonical_address(owner)?;
+            let spender = deps.api.canonical_address(spender)?;
+            if account != owner && account != spender {
+                return Err(unauthorized());
+            }
+            to_binary(&Cw20AllowanceResponse {
+                allowance: Uint128(read_allowance(&deps.storage, &owner, &spender)?),
+                expires: Expiration::Never {},
+            })
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! For tokens that wrap several native denoms, which of them each account's balance came from.
+//! Entries are indexed by the position of the denom among the supported denoms, so renaming a
+//! denom doesn't have to touch them
+//!
+//! Only deposits, withdrawals and transfers say which denoms move. Tokens that arrive any other
+//! way count as the first denom, and tokens that leave any other way are taken from the denoms
+//! in order, the next time the ledger is read
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, to_binary, Api, Binary, CanonicalAddr, Extern, HumanAddr, Querier,
+    ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::state::{
+    ReadonlyBalances, ReadonlyConfig, ReadonlyVersionedStorage, TypedStore, TypedStoreMut,
+    VersionedStorage,
+};
+
+pub const PREFIX_DENOM_BALANCES: &[u8] = b"denom_balances";
+
+/// The part of an account's balance that came from `denom`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomBalanceResponse {
+    pub denom: String,
+    pub balance: Uint128,
+}
+
+/// How much of `account`'s balance each supported denom makes up, in their order. Always adds
+/// up to the balance
+pub fn denom_balances<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> StdResult<Vec<u128>> {
+    let denoms = ReadonlyConfig::from_storage(storage).supported_denoms()?.len();
+    let balance = ReadonlyBalances::from_storage(storage).balance(account)?;
+    if denoms == 1 {
+        return Ok(vec![balance]);
+    }
+
+    let store = ReadonlyVersionedStorage::new(PREFIX_DENOM_BALANCES, storage);
+    let mut amounts: Vec<u128> = TypedStore::attach(&store)
+        .may_load(account.as_slice())?
+        .unwrap_or_default();
+    amounts.resize(denoms, 0);
+
+    let recorded = amounts.iter().fold(0u128, |sum, amount| sum.saturating_add(*amount));
+    if recorded > balance {
+        take_in_order(&mut amounts, recorded - balance);
+    } else {
+        amounts[0] += balance - recorded;
+    }
+    Ok(amounts)
+}
+
+fn set_denom_balances<S: Storage>(storage: &mut S, account: &CanonicalAddr, amounts: &[u128]) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_DENOM_BALANCES, storage);
+    TypedStoreMut::attach(&mut store).store(account.as_slice(), &amounts.to_vec())
+}
+
+pub fn remove_denom_balances<S: Storage>(storage: &mut S, account: &CanonicalAddr) {
+    let mut store = VersionedStorage::new(PREFIX_DENOM_BALANCES, storage);
+    store.remove(account.as_slice());
+}
+
+/// Takes up to `amount` out of `amounts`, first denom first, and returns how much came from each
+fn take_in_order(amounts: &mut [u128], mut amount: u128) -> Vec<u128> {
+    let mut taken = vec![0; amounts.len()];
+    for (available, taken) in amounts.iter_mut().zip(taken.iter_mut()) {
+        *taken = amount.min(*available);
+        *available -= *taken;
+        amount -= *taken;
+    }
+    taken
+}
+
+fn denom_position<S: ReadonlyStorage>(storage: &S, denom: &str) -> StdResult<usize> {
+    ReadonlyConfig::from_storage(storage)
+        .supported_denoms()?
+        .iter()
+        .position(|supported| supported == denom)
+        .ok_or_else(|| generic_err(format!("Unsupported denom: {}", denom)))
+}
+
+/// Credits the tokens minted for each deposited denom. Has to run before the balance changes
+pub fn ledger_deposit<S: Storage>(storage: &mut S, account: &CanonicalAddr, minted: &[(String, u128)]) -> StdResult<()> {
+    let mut amounts = denom_balances(storage, account)?;
+    if amounts.len() == 1 {
+        return Ok(());
+    }
+    for (denom, tokens) in minted {
+        let position = denom_position(storage, denom)?;
+        amounts[position] = amounts[position].saturating_add(*tokens);
+    }
+    set_denom_balances(storage, account, &amounts)
+}
+
+/// Takes `amount` redeemed in `denom` out of that denom first, and whatever it doesn't cover out
+/// of the others. Has to run before the balance changes
+pub fn ledger_redeem<S: Storage>(storage: &mut S, account: &CanonicalAddr, denom: &str, amount: u128) -> StdResult<()> {
+    let mut amounts = denom_balances(storage, account)?;
+    if amounts.len() == 1 {
+        return Ok(());
+    }
+    let position = denom_position(storage, denom)?;
+    let from_denom = amount.min(amounts[position]);
+    amounts[position] -= from_denom;
+    take_in_order(&mut amounts, amount - from_denom);
+    set_denom_balances(storage, account, &amounts)
+}
+
+/// Moves `amount` from `from` to `to`, out of the sender's denoms in order. Has to run before
+/// the balances change
+pub fn ledger_transfer<S: Storage>(
+    storage: &mut S,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
+    amount: u128,
+) -> StdResult<()> {
+    if from == to {
+        return Ok(());
+    }
+    let mut from_amounts = denom_balances(storage, from)?;
+    if from_amounts.len() == 1 {
+        return Ok(());
+    }
+    let moved = take_in_order(&mut from_amounts, amount);
+    let mut to_amounts = denom_balances(storage, to)?;
+    for (to_amount, moved) in to_amounts.iter_mut().zip(moved) {
+        *to_amount = to_amount.saturating_add(moved);
+    }
+    set_denom_balances(storage, from, &from_amounts)?;
+    set_denom_balances(storage, to, &to_amounts)
+}
+
+/// Answers a `Balance` query that asks for one denom
+pub fn query_denom_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    denom: &str,
+) -> StdResult<Binary> {
+    let account = deps.api.canonical_address(account)?;
+    let position = denom_position(&deps.storage, denom)?;
+    let amounts = denom_balances(&deps.storage, &account)?;
+    to_binary(&DenomBalanceResponse {
+        denom: denom.to_string(),
+        balance: Uint128(amounts[position]),
+    })
+}
@@ -0,0 +1,57 @@
+/// What to do with the digits `format_amount` doesn't show
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rounding {
+    /// Towards zero
+    Down,
+    /// Away from zero, if any hidden digit isn't zero
+    Up,
+    /// To the nearest, halves away from zero
+    HalfUp,
+}
+
+/// Formats `amount` base units of a token with `decimals` decimals, e.g. 1234500 with 6 decimals
+/// as "1.2345". Digits after the first `max_fraction_digits` are rounded away with `rounding`,
+/// and trailing zeros are left out. Works digit by digit, so every u128 is exact whatever
+/// `decimals` is
+pub fn format_amount(amount: u128, decimals: u8, max_fraction_digits: u8, rounding: Rounding) -> String {
+    let decimals = decimals as usize;
+    let fraction_digits = decimals.min(max_fraction_digits as usize);
+
+    // at least one integer digit, so "0.5" rather than ".5"
+    let mut digits = format!("{:0>width$}", amount, width = decimals + 1).into_bytes();
+    let kept = digits.len() - (decimals - fraction_digits);
+
+    let hidden = &digits[kept..];
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => hidden.iter().any(|digit| *digit != b'0'),
+        Rounding::HalfUp => hidden.first().map_or(false, |digit| *digit >= b'5'),
+    };
+    digits.truncate(kept);
+    if round_up {
+        increment(&mut digits);
+    }
+
+    let (integer, fraction) = digits.split_at(digits.len() - fraction_digits);
+    let fraction_len = fraction.iter().rposition(|digit| *digit != b'0').map_or(0, |last| last + 1);
+
+    let mut formatted: String = integer.iter().map(|digit| char::from(*digit)).collect();
+    if fraction_len > 0 {
+        formatted.push('.');
+        formatted.extend(fraction[..fraction_len].iter().map(|digit| char::from(*digit)));
+    }
+    formatted
+}
+
+/// Adds one to a number written as ASCII digits
+fn increment(digits: &mut Vec<u8>) {
+    for digit in digits.iter_mut().rev() {
+        if *digit == b'9' {
+            *digit = b'0';
+        } else {
+            *digit += 1;
+            return;
+        }
+    }
+    digits.insert(0, b'1');
+}
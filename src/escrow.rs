@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::compliance::check_account_compliance;
+use crate::freeze::check_not_frozen;
+use crate::state::{
+    store_transfer, Balances, ReadonlyBalances, ReadonlyVersionedStorage,
+    TypedStore, TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_ESCROWS: &[u8] = b"escrows";
+pub const KEY_ESCROW_COUNT: &[u8] = b"escrow_count";
+
+/// Funds taken from the payer that the arbiter either releases to the payee or refunds. Once
+/// `expiry` (block time, in seconds) has passed the escrow can only be refunded
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Escrow {
+    pub payer: CanonicalAddr,
+    pub payee: CanonicalAddr,
+    pub arbiter: CanonicalAddr,
+    pub amount: u128,
+    pub expiry: u64,
+}
+
+pub fn store_escrow<S: Storage>(storage: &mut S, escrow: &Escrow) -> StdResult<u64> {
+    let mut store = VersionedStorage::new(PREFIX_ESCROWS, storage);
+
+    let id = TypedStore::<u64, _>::attach(&store).may_load(KEY_ESCROW_COUNT)?.unwrap_or(0) + 1;
+
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), escrow)?;
+    TypedStoreMut::attach(&mut store).store(KEY_ESCROW_COUNT, &id)?;
+
+    Ok(id)
+}
+
+pub fn get_escrow<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<Option<Escrow>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_ESCROWS, storage);
+    TypedStore::attach(&store).may_load(&id.to_be_bytes())
+}
+
+pub fn remove_escrow<S: Storage>(storage: &mut S, id: u64) {
+    let mut store = VersionedStorage::new(PREFIX_ESCROWS, storage);
+    store.remove(&id.to_be_bytes());
+}
+
+pub fn try_create_escrow<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    payee: &HumanAddr,
+    arbiter: &HumanAddr,
+    amount: &Uint128,
+    expiry: u64,
+) -> StdResult<HandleResponse> {
+    let payer_address_raw = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    if expiry <= env.block.time {
+        return Err(generic_err("Expiry must be in the future"));
+    }
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(payer_address_raw)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            account_balance, amount_raw
+        ))
+    })?;
+    Balances::from_storage(&mut deps.storage).set_balance(payer_address_raw, account_balance)?;
+
+    let id = store_escrow(
+        &mut deps.storage,
+        &Escrow {
+            payer: payer_address_raw.clone(),
+            payee: deps.api.canonical_address(payee)?,
+            arbiter: deps.api.canonical_address(arbiter)?,
+            amount: amount_raw,
+            expiry,
+        },
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "create_escrow"),
+            log("payer", deps.api.human_address(payer_address_raw)?.as_str()),
+            log("id", id),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Pays the escrowed funds to the payee. Only the arbiter can do this, and only before expiry
+pub fn try_release_escrow<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let escrow = get_escrow(&deps.storage, id)?
+        .ok_or_else(|| generic_err(format!("No escrow with id {}", id)))?;
+
+    if escrow.arbiter != env.message.sender {
+        return Err(unauthorized());
+    }
+    if env.block.time >= escrow.expiry {
+        return Err(generic_err("Escrow has expired and can only be refunded"));
+    }
+    check_not_frozen(&deps.storage, &escrow.payee)?;
+    check_account_compliance(&deps.storage, &escrow.payee)?;
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(&escrow.payee)?
+        .checked_add(escrow.amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    Balances::from_storage(&mut deps.storage).set_balance(&escrow.payee, account_balance)?;
+
+    remove_escrow(&mut deps.storage, id);
+
+    store_transfer(&mut deps.storage, &escrow.payer, &escrow.payee, &Uint128(escrow.amount), env.block.time)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "release_escrow"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Returns the escrowed funds to the payer. The arbiter can do this at any time, after expiry
+/// anyone can
+pub fn try_refund_escrow<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let escrow = get_escrow(&deps.storage, id)?
+        .ok_or_else(|| generic_err(format!("No escrow with id {}", id)))?;
+
+    if escrow.arbiter != env.message.sender && env.block.time < escrow.expiry {
+        return Err(unauthorized());
+    }
+
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage).balance(&escrow.payer)?
+        .checked_add(escrow.amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    Balances::from_storage(&mut deps.storage).set_balance(&escrow.payer, account_balance)?;
+
+    remove_escrow(&mut deps.storage, id);
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "refund_escrow"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
@@ -0,0 +1,242 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, unauthorized, Api, Binary, CanonicalAddr, Env, Extern,
+    HandleResponse, HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::{assert_admin, credit, debit};
+use crate::denom_ledger::ledger_transfer;
+use crate::state::{
+    store_tagged_transfer, Balances, Config, ReadonlyBalances, ReadonlyConfig,
+    ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_FEES: &[u8] = b"fees";
+pub const KEY_FEES: &[u8] = b"rates";
+
+/// Fees are in basis points, and can't be more than 10%
+pub const MAX_FEE_BPS: u16 = 1000;
+const BPS_SCALE: u128 = 10_000;
+
+/// The references fees are tagged with in the history, when they're charged and when the
+/// admin withdraws them
+pub const FEE_REFERENCE: &str = "fee";
+pub const FEE_WITHDRAWAL_REFERENCE: &str = "fee_withdrawal";
+/// The reference of what the contract held itself when it was moved to a new treasury
+pub const TREASURY_SWEEP_REFERENCE: &str = "treasury_sweep";
+
+/// Taken out of deposits and withdrawals, in basis points. Fees are credited as tokens to the
+/// treasury, which is the contract itself until the admin sets one
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct FeesResponse {
+    pub deposit_fee_bps: u16,
+    pub redeem_fee_bps: u16,
+}
+
+/// The treasury and its balance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectedFeesResponse {
+    pub treasury: HumanAddr,
+    pub collected: Uint128,
+}
+
+pub fn get_fees<S: ReadonlyStorage>(storage: &S) -> StdResult<FeesResponse> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_FEES, storage);
+    Ok(TypedStore::attach(&store).may_load(KEY_FEES)?.unwrap_or_default())
+}
+
+fn set_fees<S: Storage>(storage: &mut S, fees: &FeesResponse) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_FEES, storage);
+    TypedStoreMut::attach(&mut store).store(KEY_FEES, fees)
+}
+
+/// `fee_bps` of `amount`, rounded down
+fn fee_of(amount: u128, fee_bps: u16) -> u128 {
+    let fee_bps = fee_bps as u128;
+    // can't overflow, unlike `amount * fee_bps`
+    amount / BPS_SCALE * fee_bps + amount % BPS_SCALE * fee_bps / BPS_SCALE
+}
+
+/// Moves `fee_bps` of `amount` out of `account`'s balance into the treasury's, and returns the
+/// fee. Goes around compliance checks, the treasury is the admin's choice. The treasury itself
+/// doesn't pay fees
+pub fn charge_fee<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    amount: u128,
+    fee_bps: u16,
+    time: u64,
+) -> StdResult<u128> {
+    charge_fee_in_units(storage, account, amount, fee_bps, 1, time)
+}
+
+/// Same as `charge_fee`, but if there is a fee it also takes what would leave the rest of
+/// `amount` short of a multiple of `unit`
+pub fn charge_fee_in_units<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    amount: u128,
+    fee_bps: u16,
+    unit: u128,
+    time: u64,
+) -> StdResult<u128> {
+    let mut fee = fee_of(amount, fee_bps);
+    if fee_bps > 0 {
+        fee += (amount - fee) % unit;
+    }
+    let treasury = ReadonlyConfig::from_storage(storage).treasury()?;
+    if fee == 0 || *account == treasury {
+        return Ok(0);
+    }
+    move_fees(storage, account, &treasury, fee, time, FEE_REFERENCE)?;
+    Ok(fee)
+}
+
+fn move_fees<S: Storage>(
+    storage: &mut S,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
+    amount: u128,
+    time: u64,
+    reference: &str,
+) -> StdResult<()> {
+    ledger_transfer(storage, from, to, amount)?;
+
+    let mut balances = Balances::from_storage(storage);
+    let from_balance = debit(balances.balance(from)?, amount)?;
+    balances.set_balance(from, from_balance)?;
+    let to_balance = credit(balances.balance(to)?, amount)?;
+    balances.set_balance(to, to_balance)?;
+
+    store_tagged_transfer(storage, from, to, &Uint128(amount), time, Some(reference))
+}
+
+/// Admin only. Fees apply to deposits and withdrawals made from now on
+pub fn try_set_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    deposit_fee_bps: u16,
+    redeem_fee_bps: u16,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if deposit_fee_bps > MAX_FEE_BPS || redeem_fee_bps > MAX_FEE_BPS {
+        return Err(generic_err(format!(
+            "Fees can be at most {} basis points",
+            MAX_FEE_BPS
+        )));
+    }
+
+    let fees = FeesResponse {
+        deposit_fee_bps,
+        redeem_fee_bps,
+    };
+    set_fees(&mut deps.storage, &fees)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_fees"),
+            log("deposit_fee_bps", &deposit_fee_bps.to_string()),
+            log("redeem_fee_bps", &redeem_fee_bps.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Admin only. Fees from now on are credited to `treasury`, and whatever the contract holds
+/// itself, fees it collected as its own treasury or tokens sent to it, is swept there
+pub fn try_set_treasury<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    treasury: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let treasury_raw = deps.api.canonical_address(treasury)?;
+    Config::from_storage(&mut deps.storage).set_treasury(&treasury_raw);
+
+    let contract = &env.contract.address;
+    let swept = ReadonlyBalances::from_storage(&deps.storage).balance(contract)?;
+    if swept > 0 && treasury_raw != *contract {
+        move_fees(
+            &mut deps.storage,
+            contract,
+            &treasury_raw,
+            swept,
+            env.block.time,
+            TREASURY_SWEEP_REFERENCE,
+        )?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_treasury"),
+            log("treasury", treasury.as_str()),
+            log("swept", &swept.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Admin only, sends `amount` of the tokens the contract holds itself to `recipient`, or all of
+/// them if `amount` is not set. That's where fees are while no treasury is set
+pub fn try_withdraw_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let contract = &env.contract.address;
+    let amount = match amount {
+        Some(amount) => amount.u128(),
+        None => ReadonlyBalances::from_storage(&deps.storage).balance(contract)?,
+    };
+    let recipient_raw = deps.api.canonical_address(recipient)?;
+    move_fees(
+        &mut deps.storage,
+        contract,
+        &recipient_raw,
+        amount,
+        env.block.time,
+        FEE_WITHDRAWAL_REFERENCE,
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "withdraw_fees"),
+            log("recipient", recipient.as_str()),
+            log("amount", &amount.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_fees<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    to_binary(&get_fees(&deps.storage)?)
+}
+
+/// Only for the admin and the treasury, whose viewing key was already checked
+pub fn query_collected_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+) -> StdResult<Binary> {
+    let config = ReadonlyConfig::from_storage(&deps.storage);
+    let address = deps.api.canonical_address(address)?;
+    let treasury = config.treasury()?;
+    if address != config.admin()? && address != treasury {
+        return Err(unauthorized());
+    }
+    to_binary(&CollectedFeesResponse {
+        treasury: deps.api.human_address(&treasury)?,
+        collected: Uint128(ReadonlyBalances::from_storage(&deps.storage).balance(&treasury)?),
+    })
+}
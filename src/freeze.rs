@@ -0,0 +1,104 @@
+//! Account freezes, for issuers with legal obligations to stop an account from moving tokens.
+//! Only tokens created with `freezing` in their init message allow them, and that can't be
+//! changed later. A frozen account can't receive transfers or get funds out in any way, but the
+//! admin can still claw back its tokens if clawbacks are enabled
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage,
+};
+
+use crate::contract::assert_admin;
+use crate::state::{ReadonlyVersionedStorage, VersionedStorage};
+
+pub const PREFIX_FREEZE: &[u8] = b"freeze";
+pub const KEY_FREEZE_ENABLED: &[u8] = b"enabled";
+pub const PREFIX_FROZEN_ACCOUNTS: &[u8] = b"frozen_accounts";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FreezeStatusResponse {
+    /// Whether the admin can freeze accounts at all
+    pub enabled: bool,
+    pub frozen: bool,
+}
+
+pub fn init_freeze<S: Storage>(storage: &mut S) {
+    let mut store = VersionedStorage::new(PREFIX_FREEZE, storage);
+    store.set(KEY_FREEZE_ENABLED, &[1]);
+}
+
+fn is_enabled<S: ReadonlyStorage>(storage: &S) -> bool {
+    let store = ReadonlyVersionedStorage::new(PREFIX_FREEZE, storage);
+    store.get(KEY_FREEZE_ENABLED).is_some()
+}
+
+fn is_frozen<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> bool {
+    let store = ReadonlyVersionedStorage::new(PREFIX_FROZEN_ACCOUNTS, storage);
+    store.get(account.as_slice()).is_some()
+}
+
+/// Fails if `from` or `to` is frozen
+pub fn check_frozen<S: ReadonlyStorage>(
+    storage: &S,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
+) -> StdResult<()> {
+    if is_frozen(storage, from) || is_frozen(storage, to) {
+        return Err(generic_err("Transfer rejected, the account is frozen"));
+    }
+    Ok(())
+}
+
+/// Fails if `account` is frozen, for every message that takes funds out of it
+pub fn check_not_frozen<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> StdResult<()> {
+    if is_frozen(storage, account) {
+        return Err(generic_err("The account is frozen"));
+    }
+    Ok(())
+}
+
+/// Admin only, needs freezes to be enabled at init
+pub fn try_set_frozen<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+    frozen: bool,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if !is_enabled(&deps.storage) {
+        return Err(generic_err("Freezes weren't enabled when the token was created"));
+    }
+
+    let account_raw = deps.api.canonical_address(account)?;
+    let mut store = VersionedStorage::new(PREFIX_FROZEN_ACCOUNTS, &mut deps.storage);
+    if frozen {
+        store.set(account_raw.as_slice(), &[1]);
+    } else {
+        store.remove(account_raw.as_slice());
+    }
+
+    let action = if frozen {
+        "freeze_account"
+    } else {
+        "unfreeze_account"
+    };
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", action), log("account", account.as_str())],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_freeze_status<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(address)?;
+    to_binary(&FreezeStatusResponse {
+        enabled: is_enabled(&deps.storage),
+        frozen: is_frozen(&deps.storage, &address),
+    })
+}
@@ -0,0 +1,78 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    log, to_binary, unauthorized, Api, Binary, Env, Extern, HandleResponse, HumanAddr, Querier,
+    ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::{assert_admin, validate_permit};
+use crate::permit::{Permission, Permit};
+use crate::rand::sha_256;
+use crate::state::{ReadonlyBalances, ReadonlyVersionedStorage, VersionedStorage};
+use crate::utils::{ct_slice_compare, tagged_hash, GOVERNANCE_KEY_TAG};
+
+pub const PREFIX_GOVERNANCE_KEYS: &[u8] = b"governance_keys";
+
+/// An account's balance, as seen by governance contracts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VotingWeightResponse {
+    pub weight: Uint128,
+}
+
+/// Whitelists `contract` to query voting weights with `key`, or removes it from the whitelist.
+/// Only the hash of the key is stored
+pub fn try_set_governance_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: &HumanAddr,
+    key: Option<String>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let contract_raw = deps.api.canonical_address(contract)?;
+    let mut store = VersionedStorage::new(PREFIX_GOVERNANCE_KEYS, &mut deps.storage);
+    match key {
+        Some(key) => store.set(contract_raw.as_slice(), &tagged_hash(GOVERNANCE_KEY_TAG, key.as_bytes())),
+        None => store.remove(contract_raw.as_slice()),
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_governance_key"),
+            log("contract", contract.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Returns the balance of the account that signed `permit`. Only whitelisted governance
+/// contracts know a key that passes this check, so nobody else can use a voter's permit
+pub fn query_voting_weight<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: &Permit,
+    governance_contract: &HumanAddr,
+    governance_key: &str,
+) -> StdResult<Binary> {
+    let contract_raw = deps.api.canonical_address(governance_contract)?;
+    let store = ReadonlyVersionedStorage::new(PREFIX_GOVERNANCE_KEYS, &deps.storage);
+    let expected_hash = store.get(contract_raw.as_slice()).unwrap_or_default();
+    // compare even if the contract isn't whitelisted, so both failures take the same time
+    let key_hash = tagged_hash(GOVERNANCE_KEY_TAG, governance_key.as_bytes());
+    // keys set before they were hashed with a tag
+    let legacy_hash = sha_256(governance_key.as_bytes());
+    let key_matches = ct_slice_compare(&key_hash, &expected_hash)
+        | ct_slice_compare(&legacy_hash, &expected_hash);
+    if !key_matches || expected_hash.is_empty() {
+        return Err(unauthorized());
+    }
+
+    let voter = validate_permit(deps, permit, Permission::Balance)?;
+    let weight = ReadonlyBalances::from_storage(&deps.storage).balance(&voter)?;
+
+    to_binary(&VotingWeightResponse {
+        weight: Uint128(weight),
+    })
+}
@@ -0,0 +1,146 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, CanonicalAddr, CosmosMsg, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128, WasmMsg,
+};
+
+use crate::contract::assert_admin;
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_HOOKS: &[u8] = b"hooks";
+pub const KEY_TRANSFER_HOOKS: &[u8] = b"transfer_hooks";
+
+/// Every hook adds a message to each transfer, so their number is capped to keep transfers
+/// affordable
+pub const MAX_TRANSFER_HOOKS: usize = 8;
+
+/// A contract that gets an `OnTransfer` callback for every transfer of at least `threshold`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransferHook {
+    pub contract: HumanAddr,
+    pub code_hash: String,
+    pub threshold: u128,
+}
+
+/// The handle message shape hook contracts should include in their own `HandleMsg`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferHookHandleMsg {
+    OnTransfer {
+        from: HumanAddr,
+        to: HumanAddr,
+        amount: Uint128,
+    },
+}
+
+fn get_transfer_hooks<S: ReadonlyStorage>(storage: &S) -> StdResult<Vec<TransferHook>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_HOOKS, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(KEY_TRANSFER_HOOKS)?
+        .unwrap_or_default())
+}
+
+fn set_transfer_hooks<S: Storage>(storage: &mut S, hooks: &[TransferHook]) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_HOOKS, storage);
+    TypedStoreMut::attach(&mut store).store(KEY_TRANSFER_HOOKS, &hooks.to_vec())
+}
+
+/// The callbacks for a transfer of `amount` from `from` to `to`. Every handler that moves tokens
+/// between accounts adds these to its response
+pub fn transfer_hook_messages<S: ReadonlyStorage, A: Api>(
+    storage: &S,
+    api: &A,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
+    amount: u128,
+) -> StdResult<Vec<CosmosMsg>> {
+    let hooks = get_transfer_hooks(storage)?;
+    if hooks.iter().all(|hook| amount < hook.threshold) {
+        return Ok(vec![]);
+    }
+
+    let msg = to_binary(&TransferHookHandleMsg::OnTransfer {
+        from: api.human_address(from)?,
+        to: api.human_address(to)?,
+        amount: Uint128(amount),
+    })?;
+
+    Ok(hooks
+        .into_iter()
+        .filter(|hook| amount >= hook.threshold)
+        .map(|hook| {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: hook.contract,
+                callback_code_hash: hook.code_hash,
+                msg: msg.clone(),
+                send: vec![],
+            })
+        })
+        .collect())
+}
+
+/// Adds `contract` as a transfer hook, or updates it if it already is one
+pub fn try_register_transfer_hook<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: HumanAddr,
+    code_hash: String,
+    threshold: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    // fail early on addresses the callbacks couldn't be delivered to
+    deps.api.canonical_address(&contract)?;
+
+    let mut hooks = get_transfer_hooks(&deps.storage)?;
+    hooks.retain(|hook| hook.contract != contract);
+    if hooks.len() >= MAX_TRANSFER_HOOKS {
+        return Err(generic_err(format!(
+            "Can't register more than {} transfer hooks",
+            MAX_TRANSFER_HOOKS
+        )));
+    }
+    hooks.push(TransferHook {
+        contract: contract.clone(),
+        code_hash,
+        threshold: threshold.map_or(0, |threshold| threshold.u128()),
+    });
+    set_transfer_hooks(&mut deps.storage, &hooks)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "register_transfer_hook"),
+            log("contract", contract.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_remove_transfer_hook<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let mut hooks = get_transfer_hooks(&deps.storage)?;
+    let count = hooks.len();
+    hooks.retain(|hook| hook.contract != contract);
+    if hooks.len() == count {
+        return Err(generic_err(format!("{} is not a transfer hook", contract)));
+    }
+    set_transfer_hooks(&mut deps.storage, &hooks)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "remove_transfer_hook"),
+            log("contract", contract.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
@@ -0,0 +1,111 @@
+//! Accounts can refuse transfers they didn't ask for, e.g. dust sent to see whether an address
+//! is in use. Blocking covers transfers pushed into the account: `Transfer`, `BatchTransfer`,
+//! `Send`, `TransferFrom`, `SendFrom`, signed transfers, co-signed proposals, and locked or
+//! vesting transfers. Tokens the account has to claim or asked for, like claimable transfers or
+//! paid payment requests, still arrive
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage,
+};
+
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_INCOMING_BLOCKS: &[u8] = b"incoming_blocks";
+
+/// Whether an account blocks incoming transfers, and the senders it still accepts them from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IncomingTransfersResponse {
+    pub blocked: bool,
+    pub allowed_senders: Vec<HumanAddr>,
+}
+
+fn get_allowed_senders<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<Vec<CanonicalAddr>>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_INCOMING_BLOCKS, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+fn set_allowed_senders<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    senders: Option<&Vec<CanonicalAddr>>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_INCOMING_BLOCKS, storage);
+    match senders {
+        Some(senders) => TypedStoreMut::attach(&mut store).store(account.as_slice(), senders),
+        None => {
+            TypedStoreMut::<Vec<CanonicalAddr>, _>::attach(&mut store).remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Rejects a transfer to `recipient` if it blocks incoming transfers and `sender` isn't one it
+/// allowed. Moving tokens between an account and itself is always fine
+pub fn check_incoming<S: ReadonlyStorage>(
+    storage: &S,
+    sender: &CanonicalAddr,
+    recipient: &CanonicalAddr,
+) -> StdResult<()> {
+    if sender == recipient {
+        return Ok(());
+    }
+    match get_allowed_senders(storage, recipient)? {
+        Some(senders) if !senders.contains(sender) => Err(generic_err(
+            "The recipient doesn't accept transfers from this sender",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Blocks transfers to the sender except from `allowed_senders`, or lifts the block if `block`
+/// is false
+pub fn try_block_incoming_transfers<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    block: bool,
+    allowed_senders: Vec<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    if block {
+        let senders_raw = allowed_senders
+            .iter()
+            .map(|sender| deps.api.canonical_address(sender))
+            .collect::<StdResult<Vec<_>>>()?;
+        set_allowed_senders(&mut deps.storage, &env.message.sender, Some(&senders_raw))?;
+    } else {
+        set_allowed_senders(&mut deps.storage, &env.message.sender, None)?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "block_incoming_transfers"),
+            log("blocked", block),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_incoming_transfers<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let account = deps.api.canonical_address(account)?;
+    let senders = get_allowed_senders(&deps.storage, &account)?;
+    let allowed_senders = senders
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|sender| deps.api.human_address(sender))
+        .collect::<StdResult<Vec<_>>>()?;
+    to_binary(&IncomingTransfersResponse {
+        blocked: senders.is_some(),
+        allowed_senders,
+    })
+}
@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::perform_transfer;
+use crate::hooks::transfer_hook_messages;
+use crate::limits::{has_transfer_limit, LIMIT_WINDOW};
+use crate::multisig::get_transfer_policy;
+use crate::state::{
+    store_transfer, ReadonlyBalances, ReadonlyVersionedStorage, TypedStore,
+    TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_BENEFICIARIES: &[u8] = b"beneficiaries";
+pub const PREFIX_PENDING_BENEFICIARIES: &[u8] = b"pending_beneficiaries";
+
+/// Shortest inactivity period a beneficiary can be set with
+pub const MIN_INACTIVITY_PERIOD: u64 = 30 * LIMIT_WINDOW;
+
+/// `beneficiary` can take the owner's balance once the owner sent no message for
+/// `inactivity_period` seconds after `last_active`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Beneficiary {
+    pub beneficiary: CanonicalAddr,
+    pub inactivity_period: u64,
+    pub last_active: u64,
+}
+
+/// A beneficiary set on an account with a transfer policy or limit, which only takes over
+/// from `effective_at` on so a stolen key can't make itself the heir right away
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingBeneficiary {
+    pub beneficiary: Beneficiary,
+    pub effective_at: u64,
+}
+
+fn get_beneficiary<S: ReadonlyStorage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+) -> StdResult<Option<Beneficiary>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_BENEFICIARIES, storage);
+    TypedStore::attach(&store).may_load(owner.as_slice())
+}
+
+fn set_beneficiary<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    beneficiary: Option<&Beneficiary>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_BENEFICIARIES, storage);
+    match beneficiary {
+        Some(beneficiary) => TypedStoreMut::attach(&mut store).store(owner.as_slice(), beneficiary),
+        None => {
+            TypedStoreMut::<Beneficiary, _>::attach(&mut store).remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+fn get_pending_beneficiary<S: ReadonlyStorage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+) -> StdResult<Option<PendingBeneficiary>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PENDING_BENEFICIARIES, storage);
+    TypedStore::attach(&store).may_load(owner.as_slice())
+}
+
+fn set_pending_beneficiary<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    pending: Option<&PendingBeneficiary>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_PENDING_BENEFICIARIES, storage);
+    match pending {
+        Some(pending) => TypedStoreMut::attach(&mut store).store(owner.as_slice(), pending),
+        None => {
+            TypedStoreMut::<PendingBeneficiary, _>::attach(&mut store).remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Returns `owner`'s beneficiary as of `now`, after replacing it with a pending one that is due
+fn current_beneficiary<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    now: u64,
+) -> StdResult<Option<Beneficiary>> {
+    match get_pending_beneficiary(storage, owner)? {
+        Some(pending) if pending.effective_at <= now => {
+            set_pending_beneficiary(storage, owner, None)?;
+            set_beneficiary(storage, owner, Some(&pending.beneficiary))?;
+            Ok(Some(pending.beneficiary))
+        }
+        _ => get_beneficiary(storage, owner),
+    }
+}
+
+/// Restarts the inactivity period of `owner`, if they set a beneficiary. Runs before each of
+/// the owner's transactions
+pub fn record_activity<S: Storage>(storage: &mut S, owner: &CanonicalAddr, now: u64) -> StdResult<()> {
+    match current_beneficiary(storage, owner, now)? {
+        Some(mut beneficiary) => {
+            beneficiary.last_active = now;
+            set_beneficiary(storage, owner, Some(&beneficiary))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Sets who inherits the sender's balance, or removes the beneficiary if `address` is not set.
+/// If the sender has a transfer policy or limit, a new beneficiary only takes effect after
+/// `LIMIT_WINDOW`
+pub fn try_set_beneficiary<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: Option<HumanAddr>,
+    inactivity_period: u64,
+) -> StdResult<HandleResponse> {
+    let owner = &env.message.sender;
+    let now = env.block.time;
+    let mut logs = vec![log("action", "set_beneficiary")];
+
+    set_pending_beneficiary(&mut deps.storage, owner, None)?;
+    match address {
+        Some(address) => {
+            if inactivity_period < MIN_INACTIVITY_PERIOD {
+                return Err(generic_err(format!(
+                    "Inactivity period must be at least {} seconds",
+                    MIN_INACTIVITY_PERIOD
+                )));
+            }
+            let beneficiary = Beneficiary {
+                beneficiary: deps.api.canonical_address(&address)?,
+                inactivity_period,
+                last_active: now,
+            };
+
+            let guarded = get_transfer_policy(&deps.storage, owner)?.is_some()
+                || has_transfer_limit(&deps.storage, owner, now)?;
+            if guarded {
+                // the inactivity period only starts once the beneficiary takes effect
+                let effective_at = now.saturating_add(LIMIT_WINDOW);
+                let pending = PendingBeneficiary {
+                    beneficiary: Beneficiary {
+                        last_active: effective_at,
+                        ..beneficiary
+                    },
+                    effective_at,
+                };
+                set_pending_beneficiary(&mut deps.storage, owner, Some(&pending))?;
+                logs.push(log("effective_at", pending.effective_at));
+            } else {
+                set_beneficiary(&mut deps.storage, owner, Some(&beneficiary))?;
+            }
+        }
+        None => set_beneficiary(&mut deps.storage, owner, None)?,
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: logs,
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Moves `owner`'s balance to the sender, if they are the beneficiary and the owner has been
+/// inactive for long enough. Counts as the owner moving their balance out, so the owner's
+/// transfer policy and limit apply
+pub fn try_claim_inheritance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let owner_raw = deps.api.canonical_address(owner)?;
+    let beneficiary = current_beneficiary(&mut deps.storage, &owner_raw, env.block.time)?
+        .ok_or_else(|| generic_err("The account has no beneficiary"))?;
+
+    if beneficiary.beneficiary != env.message.sender {
+        return Err(unauthorized());
+    }
+    let claimable_at = beneficiary
+        .last_active
+        .saturating_add(beneficiary.inactivity_period);
+    if env.block.time < claimable_at {
+        return Err(generic_err("The owner has been active too recently"));
+    }
+
+    set_beneficiary(&mut deps.storage, &owner_raw, None)?;
+
+    let balance = ReadonlyBalances::from_storage(&deps.storage).balance(&owner_raw)?;
+    let mut messages = vec![];
+    if balance > 0 {
+        perform_transfer(&mut deps.storage, &owner_raw, &beneficiary.beneficiary, balance)?;
+
+        store_transfer(
+            &mut deps.storage,
+            &owner_raw,
+            &beneficiary.beneficiary,
+            &Uint128(balance),
+            env.block.time,
+        )?;
+
+        messages = transfer_hook_messages(&deps.storage, &deps.api, &owner_raw, &beneficiary.beneficiary, balance)?;
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "claim_inheritance"),
+            log("owner", owner.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
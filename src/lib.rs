@@ -1,9 +1,45 @@
-mod rand;
 mod viewing_key;
 mod utils;
+pub mod airdrop;
+pub mod attestation;
+pub mod bridge;
+pub mod channels;
+pub mod clawback;
+pub mod compliance;
 pub mod contract;
+pub mod cw20;
+pub mod denom_ledger;
+pub mod display;
+pub mod escrow;
+pub mod fees;
+pub mod freeze;
+pub mod governance;
+pub mod hooks;
+pub mod incoming;
+pub mod inheritance;
+pub mod limits;
+pub mod marketing;
 pub mod msg;
+pub mod multisig;
+pub mod payment_requests;
+pub mod permit;
+pub mod pull;
+pub mod rand;
+pub mod receiver;
+pub mod recovery;
+pub mod redeem_limit;
+pub mod routing;
+pub mod send_whitelist;
+pub mod signed;
+pub mod snip20;
+pub mod staking;
 pub mod state;
+pub mod streams;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod testing;
+pub mod timelock;
+pub mod vesting;
+pub mod wrapped;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm {
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, Api, CanonicalAddr, Env, Extern, HandleResponse, Querier, ReadonlyStorage,
+    StdResult, Storage, Uint128,
+};
+
+use crate::msg::HandleMsg;
+use crate::multisig::outflow;
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_TRANSFER_LIMITS: &[u8] = b"transfer_limits";
+
+/// Length of the rolling window limits apply to, and how long loosening a limit takes
+pub const LIMIT_WINDOW: u64 = 24 * 60 * 60;
+
+/// An account's cap on how much can leave it within any `LIMIT_WINDOW`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransferLimit {
+    pub amount_per_day: u128,
+    /// A looser limit waiting to take effect, so a stolen key can't lift the limit right away
+    pub pending: Option<PendingLimit>,
+    /// What left the account during the last `LIMIT_WINDOW`, oldest first
+    pub spent: Vec<Spend>,
+}
+
+/// `None` removes the limit
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingLimit {
+    pub amount_per_day: Option<u128>,
+    pub effective_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Spend {
+    pub time: u64,
+    pub amount: u128,
+}
+
+fn get_limit<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<TransferLimit>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_TRANSFER_LIMITS, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+fn set_limit<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    limit: Option<&TransferLimit>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_TRANSFER_LIMITS, storage);
+    match limit {
+        Some(limit) => TypedStoreMut::attach(&mut store).store(account.as_slice(), limit),
+        None => {
+            TypedStoreMut::<TransferLimit, _>::attach(&mut store).remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Returns the limit as of `now`, with a due pending change applied and spending that left the
+/// window dropped. `None` if a pending change lifted it
+fn refresh(mut limit: TransferLimit, now: u64) -> Option<TransferLimit> {
+    if let Some(pending) = limit.pending.clone() {
+        if pending.effective_at <= now {
+            limit.amount_per_day = pending.amount_per_day?;
+            limit.pending = None;
+        }
+    }
+
+    limit
+        .spent
+        .retain(|spend| spend.time.saturating_add(LIMIT_WINDOW) > now);
+    Some(limit)
+}
+
+/// Whether `account` has a limit as of `now`
+pub fn has_transfer_limit<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+    now: u64,
+) -> StdResult<bool> {
+    let limit = get_limit(storage, account)?.and_then(|limit| refresh(limit, now));
+    Ok(limit.is_some())
+}
+
+/// Records what `msg` moves out of an account with a limit, and rejects it if that exceeds
+/// the limit
+pub fn enforce_transfer_limit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    msg: &HandleMsg,
+) -> StdResult<()> {
+    let (owner, amount) = match outflow(deps, &env.message.sender, msg)? {
+        Some(outflow) => outflow,
+        None => return Ok(()),
+    };
+    let now = env.block.time;
+
+    let limit = match get_limit(&deps.storage, &owner)? {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let mut limit = match refresh(limit, now) {
+        Some(limit) => limit,
+        None => return set_limit(&mut deps.storage, &owner, None),
+    };
+
+    let spent = limit
+        .spent
+        .iter()
+        .try_fold(amount, |sum, spend| sum.checked_add(spend.amount))
+        .ok_or_else(|| generic_err("Spending overflow"))?;
+    if spent > limit.amount_per_day {
+        return Err(generic_err(format!(
+            "Amount exceeds the account's daily limit of {}",
+            limit.amount_per_day
+        )));
+    }
+
+    limit.spent.push(Spend { time: now, amount });
+    set_limit(&mut deps.storage, &owner, Some(&limit))
+}
+
+/// Sets a daily limit on what can leave the sender's account. A stricter limit applies right
+/// away, a looser one (or removing it with `None`) only after `LIMIT_WINDOW`
+pub fn try_set_transfer_limit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount_per_day: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    let account = &env.message.sender;
+    let now = env.block.time;
+    let amount_per_day = amount_per_day.map(|amount| amount.u128());
+
+    let current = get_limit(&deps.storage, account)?.and_then(|limit| refresh(limit, now));
+    let limit = match (current, amount_per_day) {
+        (None, None) => None,
+        (None, Some(amount_per_day)) => Some(TransferLimit {
+            amount_per_day,
+            pending: None,
+            spent: vec![],
+        }),
+        (Some(mut limit), Some(amount_per_day)) if amount_per_day <= limit.amount_per_day => {
+            limit.amount_per_day = amount_per_day;
+            limit.pending = None;
+            Some(limit)
+        }
+        (Some(mut limit), amount_per_day) => {
+            limit.pending = Some(PendingLimit {
+                amount_per_day,
+                effective_at: now.saturating_add(LIMIT_WINDOW),
+            });
+            Some(limit)
+        }
+    };
+    set_limit(&mut deps.storage, account, limit.as_ref())?;
+
+    let mut logs = vec![log("action", "set_transfer_limit")];
+    if let Some(pending) = limit.as_ref().and_then(|limit| limit.pending.as_ref()) {
+        logs.push(log("effective_at", pending.effective_at));
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: logs,
+        data: None,
+    };
+    Ok(res)
+}
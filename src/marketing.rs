@@ -0,0 +1,156 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, Env, Extern, HandleResponse, Querier,
+    ReadonlyStorage, StdResult, Storage,
+};
+
+use crate::contract::assert_admin;
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_MARKETING: &[u8] = b"marketing";
+pub const KEY_MARKETING_INFO: &[u8] = b"info";
+
+/// Max length of the description and the project URL, in bytes
+pub const MAX_MARKETING_TEXT_LENGTH: usize = 1024;
+/// Max size of an embedded logo, in bytes. Same as cw20's
+pub const MAX_LOGO_SIZE: usize = 5 * 1024;
+
+const PNG_HEADER: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Logo {
+    /// A link to the logo
+    Url(String),
+    /// The logo itself, stored in the contract
+    Embedded(EmbeddedLogo),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddedLogo {
+    Svg(Binary),
+    Png(Binary),
+}
+
+/// What wallets and explorers can show about the token, mirroring cw20's marketing extension
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct MarketingInfoResponse {
+    pub project: Option<String>,
+    pub description: Option<String>,
+    pub logo: Option<Logo>,
+}
+
+fn get_marketing_info<S: ReadonlyStorage>(storage: &S) -> StdResult<MarketingInfoResponse> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_MARKETING, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(KEY_MARKETING_INFO)?
+        .unwrap_or_default())
+}
+
+fn set_marketing_info<S: Storage>(storage: &mut S, info: &MarketingInfoResponse) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_MARKETING, storage);
+    TypedStoreMut::attach(&mut store).store(KEY_MARKETING_INFO, info)
+}
+
+/// `None` keeps the current value and an empty string removes it, like in cw20
+fn update_text(name: &str, current: &mut Option<String>, update: Option<String>) -> StdResult<()> {
+    match update {
+        None => {}
+        Some(text) if text.is_empty() => *current = None,
+        Some(text) if text.len() > MAX_MARKETING_TEXT_LENGTH => {
+            return Err(generic_err(format!(
+                "{} can be at most {} bytes",
+                name, MAX_MARKETING_TEXT_LENGTH
+            )));
+        }
+        Some(text) => *current = Some(text),
+    }
+    Ok(())
+}
+
+fn check_logo(logo: &Logo) -> StdResult<()> {
+    match logo {
+        Logo::Url(url) if url.len() > MAX_MARKETING_TEXT_LENGTH => Err(generic_err(format!(
+            "Logo URL can be at most {} bytes",
+            MAX_MARKETING_TEXT_LENGTH
+        ))),
+        Logo::Url(_) => Ok(()),
+        Logo::Embedded(embedded) => {
+            let (image, looks_right) = match embedded {
+                EmbeddedLogo::Png(image) => (image, image.as_slice().starts_with(&PNG_HEADER)),
+                EmbeddedLogo::Svg(image) => (
+                    image,
+                    image
+                        .as_slice()
+                        .iter()
+                        .find(|byte| !byte.is_ascii_whitespace())
+                        == Some(&b'<'),
+                ),
+            };
+            if image.len() > MAX_LOGO_SIZE {
+                return Err(generic_err(format!(
+                    "Embedded logo can be at most {} bytes",
+                    MAX_LOGO_SIZE
+                )));
+            }
+            if !looks_right {
+                return Err(generic_err("Embedded logo is not in the format it claims"));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Admin only
+pub fn try_update_marketing<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    project: Option<String>,
+    description: Option<String>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let mut info = get_marketing_info(&deps.storage)?;
+    update_text("Project", &mut info.project, project)?;
+    update_text("Description", &mut info.description, description)?;
+    set_marketing_info(&mut deps.storage, &info)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "update_marketing")],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Admin only, replaces the logo or removes it if `logo` is not set
+pub fn try_upload_logo<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    logo: Option<Logo>,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if let Some(logo) = &logo {
+        check_logo(logo)?;
+    }
+
+    let mut info = get_marketing_info(&deps.storage)?;
+    info.logo = logo;
+    set_marketing_info(&mut deps.storage, &info)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "upload_logo")],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_marketing_info<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    to_binary(&get_marketing_info(&deps.storage)?)
+}
@@ -1,9 +1,25 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, Serializer};
 
-use cosmwasm_std::{HumanAddr, Uint128};
+use cosmwasm_std::{generic_err, Binary, CosmosMsg, HumanAddr, StdResult, Uint128, WasmMsg};
+use crate::compliance::{ComplianceMode, ComplianceStatus};
+use crate::cw20::{Cw20Auth, Cw20QueryMsg};
+use crate::marketing::Logo;
+use crate::permit::{Permit, PermitSignature};
+use crate::signed::SignedTransfer;
+use crate::staking::StakingInitConfig;
+use crate::state::{AccountStats, Tx, TxDirection};
 use crate::viewing_key::ViewingKey;
 
+/// One transfer of a `BatchTransfer`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransferAction {
+    pub recipient: HumanAddr,
+    pub amount: Uint128,
+    /// Kept in both histories, e.g. what a payroll line item is for
+    pub memo: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct InitialBalance {
     pub address: HumanAddr,
@@ -16,6 +32,47 @@ pub struct InitMsg {
     pub symbol: String,
     pub decimals: u8,
     pub initial_balances: Vec<InitialBalance>,
+    /// Maximum number of transfers kept in each account's history, the oldest ones are deleted
+    /// first. Unlimited if not set
+    pub history_limit: Option<u32>,
+    /// Executed once the token is created, e.g. so the factory that instantiated it can
+    /// register it
+    pub callback: Option<Callback>,
+    /// Delegate deposits instead of holding them, see `StakingInitConfig`
+    pub staking: Option<StakingInitConfig>,
+    /// Can change the token's settings. Defaults to the address that instantiated the contract
+    pub admin: Option<HumanAddr>,
+    /// Native denoms that can be deposited for tokens 1:1. The first one is what `Withdraw`
+    /// pays out by default. Only uscrt, the default, is supported in staking mode
+    pub supported_denoms: Option<Vec<String>>,
+    /// Screen every transfer against admin-managed lists. Can't be turned on later
+    pub compliance: Option<ComplianceMode>,
+    /// Lets the admin move tokens out of any account with `Clawback`. Can't be changed later
+    pub clawback: Option<bool>,
+    /// Lets the admin freeze accounts with `FreezeAccount`. Can't be changed later
+    pub freezing: Option<bool>,
+    /// Secret seed for the token's randomness, e.g. the viewing keys `CreateViewingKey` makes.
+    /// Derived from the init env if not set, which validators can predict
+    pub prng_seed: Option<Binary>,
+}
+
+/// A message to execute on another contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Callback {
+    pub contract_addr: HumanAddr,
+    pub code_hash: String,
+    pub msg: Binary,
+}
+
+impl Callback {
+    pub fn into_cosmos_msg(self) -> CosmosMsg {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: self.contract_addr,
+            callback_code_hash: self.code_hash,
+            msg: self.msg,
+            send: vec![],
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
@@ -25,74 +82,740 @@ pub enum HandleMsg {
     // Native coin interactions
     Withdraw {
         amount: Uint128,
+        /// One of the supported denoms, defaults to the first one
+        denom: Option<String>,
+    },
+    /// Withdraws to `recipient` instead of the sender
+    RedeemAndSend {
+        recipient: HumanAddr,
+        amount: Uint128,
+        denom: Option<String>,
     },
     Deposit { },
+    /// Deposits the coins sent along and transfers `amount` of the minted tokens to `recipient`
+    WrapAndTransfer {
+        recipient: HumanAddr,
+        amount: Uint128,
+    },
+    /// Like `WrapAndTransfer`, with the `Receive` callback of a `Send`
+    WrapAndSend {
+        recipient: HumanAddr,
+        recipient_code_hash: Option<String>,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// Redeems the whole balance, each part in the denom it came from, and deletes the sender's
+    /// balance, viewing key, allowances and history
+    CloseAccount {
+        /// Who gets the native coins, defaults to the sender
+        redeem_to: Option<HumanAddr>,
+        /// Spenders of allowances given before the contract listed them, which it can't find
+        /// on its own
+        spenders: Option<Vec<HumanAddr>>,
+    },
 
     // ERC-20 stuff
     Approve {
         spender: HumanAddr,
         amount: Uint128,
+        /// Fails unless the allowance is currently this, so the spender can't front-run a change
+        /// by spending the old allowance first
+        expected_current: Option<Uint128>,
+        /// Calls the spender back with the new allowance, see `Snip20AllowanceMsg`. The spender
+        /// must have registered with `RegisterReceive`
+        notify: Option<bool>,
     },
     Transfer {
         recipient: HumanAddr,
         amount: Uint128,
     },
+    /// Several transfers in one message, each with its own memo
+    BatchTransfer {
+        actions: Vec<TransferAction>,
+    },
+    Send {
+        recipient: HumanAddr,
+        /// Delivers the `Receive` callback with this code hash, for contracts that didn't
+        /// `RegisterReceive`. Takes precedence over the registered one
+        recipient_code_hash: Option<String>,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// A `Send` to a router, which passes the tokens on from its callback. What it reports it
+    /// didn't pass on goes back to the sender once the callback is done. `code_hash` is this
+    /// token's
+    TransferAndCall {
+        recipient: HumanAddr,
+        recipient_code_hash: Option<String>,
+        amount: Uint128,
+        msg: Option<Binary>,
+        code_hash: String,
+    },
+    /// Sent by the recipient of a `TransferAndCall` during its callback, for what it didn't pass
+    /// on
+    ReportUnspent {
+        amount: Uint128,
+    },
+    /// Sent by the token to itself after a `TransferAndCall`'s callback
+    SettleCall {
+        id: u64,
+    },
+    RegisterReceive {
+        code_hash: String,
+    },
     TransferFrom {
         owner: HumanAddr,
         recipient: HumanAddr,
         amount: Uint128,
     },
+    /// `TransferFrom` with a `Send`'s callback. The recipient's `Snip20ReceiveMsg` carries the
+    /// spender as `sender` and the owner as `from`, so it can credit the owner
+    SendFrom {
+        owner: HumanAddr,
+        recipient: HumanAddr,
+        recipient_code_hash: Option<String>,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
     Burn {
         amount: Uint128,
     },
+    /// Deprecated, use the `balance` query. Only returns the balance in the encrypted `data`
     Balance { },
+    /// Deprecated, use the `allowance` permit query. Only returns the allowance in the
+    /// encrypted `data`
     Allowance {
         spender: HumanAddr,
     },
 
+    // Claimable transfers
+    TransferPending {
+        recipient: HumanAddr,
+        amount: Uint128,
+        expiry: u64,
+    },
+    ClaimTransfer {
+        id: u64,
+    },
+    CancelTransfer {
+        id: u64,
+    },
+
+    // Hash-locked claims
+    CreateClaim {
+        hash: Binary,
+        amount: Uint128,
+        expiry: u64,
+    },
+    RedeemClaim {
+        preimage: String,
+    },
+
+    // Vesting
+    /// `cliff` and `duration` are in seconds, counted from this block
+    TransferVesting {
+        recipient: HumanAddr,
+        amount: Uint128,
+        cliff: u64,
+        duration: u64,
+    },
+    ClaimVested {},
+
+    // Timelocks
+    /// The recipient can spend the tokens from block time `unlock_at` (in seconds) on
+    TransferLocked {
+        recipient: HumanAddr,
+        amount: Uint128,
+        unlock_at: u64,
+    },
+
+    // Escrow
+    CreateEscrow {
+        payee: HumanAddr,
+        arbiter: HumanAddr,
+        amount: Uint128,
+        expiry: u64,
+    },
+    ReleaseEscrow {
+        id: u64,
+    },
+    RefundEscrow {
+        id: u64,
+    },
+
+    // Payment channels
+    OpenChannel {
+        recipient: HumanAddr,
+        amount: Uint128,
+        expiry: u64,
+    },
+    /// Sent by the recipient with the payer's latest signed balance proof, `amount` being the
+    /// total paid through the channel
+    SettleChannel {
+        id: u64,
+        amount: Uint128,
+        signature: Binary,
+        pub_key: Binary,
+    },
+    CloseChannel {
+        id: u64,
+    },
+
+    // Streams
+    /// `amount` accrues to the recipient linearly between block times `start` and `end`
+    CreateStream {
+        recipient: HumanAddr,
+        amount: Uint128,
+        start: u64,
+        end: u64,
+    },
+    WithdrawFromStream {
+        id: u64,
+    },
+    CancelStream {
+        id: u64,
+    },
+
+    // Payment requests
+    /// Creates an invoice payable to the sender. Paying it tags the transfer with `reference`
+    CreatePaymentRequest {
+        amount: Uint128,
+        reference: String,
+        expiry: u64,
+    },
+    PayRequest {
+        id: u64,
+    },
+
+    // Pull payments
+    /// Lets `contract` pull up to `max_amount` from the sender's balance once every `frequency`
+    /// seconds. Replaces any earlier authorization of `contract`
+    AuthorizePull {
+        contract: HumanAddr,
+        max_amount: Uint128,
+        frequency: u64,
+    },
+    RevokePull {
+        contract: HumanAddr,
+    },
+    /// Sent by an authorized contract to move `amount` from `owner` to itself
+    Pull {
+        owner: HumanAddr,
+        amount: Uint128,
+    },
+
+    // Staking mode
+    ClaimUnbonded {},
+    CollectRewards {},
+
+    // Holder rewards
+    DistributeRewards {},
+    ClaimRewards {},
+
+    // Airdrops
+    /// Admin only, `amount` of the admin's tokens pay for the claims
+    RegisterAirdrop {
+        merkle_root: Binary,
+        amount: Uint128,
+    },
+    /// `index` is the position of the sender's leaf in the tree
+    ClaimAirdrop {
+        index: u32,
+        proof: Vec<Binary>,
+        amount: Uint128,
+    },
+
+    /// Admin only, pays every recipient from the airdrop pool after adding `amount` of the
+    /// admin's tokens to it
+    Airdrop {
+        recipients: Vec<InitialBalance>,
+        amount: Option<Uint128>,
+    },
+
+    // Co-signed transfers
+    /// Once set, moving more than `threshold` out of the sender's account needs `co_signer`
+    SetTransferPolicy {
+        co_signer: HumanAddr,
+        threshold: Uint128,
+    },
+    /// Sent by the co-signer to lift `account`'s policy
+    ReleaseTransferPolicy {
+        account: HumanAddr,
+    },
+    /// Holds `amount` back from the sender's balance until the co-signer approves it
+    ProposeTransfer {
+        recipient: HumanAddr,
+        amount: Uint128,
+    },
+    ApproveTransfer {
+        id: u64,
+    },
+    /// Sent by the proposer to get the held amount back
+    CancelProposedTransfer {
+        id: u64,
+    },
+
+    // Social recovery
+    /// `quorum` of `guardians` can move the sender's balance and reset their viewing key,
+    /// `delay` seconds after agreeing, at least a week. An empty list removes the guardians.
+    /// Replacing or removing guardians takes effect after the current guardians' delay
+    SetGuardians {
+        guardians: Vec<HumanAddr>,
+        quorum: u32,
+        delay: u64,
+    },
+    ApproveRecovery {
+        account: HumanAddr,
+        recovery_address: HumanAddr,
+        viewing_key: Option<String>,
+    },
+    CancelRecovery {},
+    /// Subject to the account's transfer policy and limit, like any transfer out of it
+    ExecuteRecovery {
+        account: HumanAddr,
+    },
+
+    // Transfer limits
+    /// Caps what can leave the sender's account in any 24 hours. Lowering the limit applies
+    /// right away, raising or removing it (with `None`) after 24 hours
+    SetTransferLimit {
+        amount_per_day: Option<Uint128>,
+    },
+
+    // Send whitelist
+    /// Only lets the sender `Send` to `contracts`, or to any contract again if `contracts` is
+    /// not set
+    SetSendWhitelist {
+        contracts: Option<Vec<HumanAddr>>,
+    },
+
+    // Incoming transfers
+    /// Makes transfers to the sender fail unless they come from `allowed_senders`, or accepts
+    /// transfers from anyone again if `block` is false
+    BlockIncomingTransfers {
+        block: bool,
+        allowed_senders: Option<Vec<HumanAddr>>,
+    },
+
+    // Inheritance
+    /// `address` can claim the sender's balance once the sender sent no message for
+    /// `inactivity_period` seconds, at least 30 days. Not setting `address` removes the
+    /// beneficiary. With a transfer policy or limit, a new beneficiary takes effect after 24 hours
+    SetBeneficiary {
+        address: Option<HumanAddr>,
+        inactivity_period: u64,
+    },
+    /// Subject to the owner's transfer policy and limit, like any transfer out of the account
+    ClaimInheritance {
+        owner: HumanAddr,
+    },
+
+    // Bridge
+    /// Admin only, sets the address that can send `BridgeMint`, or removes it if not set
+    SetBridge {
+        address: Option<HumanAddr>,
+    },
+    /// Mints tokens locked on the other chain in transaction `tx_hash`, once per hash
+    BridgeMint {
+        recipient: HumanAddr,
+        amount: Uint128,
+        tx_hash: String,
+    },
+    /// Burns the sender's tokens to have them released to `external_recipient` on the other
+    /// chain
+    BridgeBurn {
+        amount: Uint128,
+        external_recipient: String,
+    },
+
+    // Wrapped SNIP-20s
+    /// Admin only, lets holders of the SNIP-20 at `contract` wrap it by sending it to this
+    /// contract. `own_code_hash` is this contract's code hash, which the token calls back with
+    RegisterWrappedToken {
+        contract: HumanAddr,
+        code_hash: String,
+        decimals: u8,
+        own_code_hash: String,
+    },
+    /// Sent by a registered SNIP-20 when its tokens are sent here, mints their value to `from`
+    Receive {
+        sender: HumanAddr,
+        from: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// Burns `amount` and sends its value in the wrapped SNIP-20 at `token` to the sender
+    UnwrapToken {
+        token: HumanAddr,
+        amount: Uint128,
+    },
+
+    // Transfer hooks
+    /// Admin only, `contract` gets an `on_transfer` callback for every transfer of at least
+    /// `threshold`, or every transfer if not set
+    RegisterTransferHook {
+        contract: HumanAddr,
+        code_hash: String,
+        threshold: Option<Uint128>,
+    },
+    RemoveTransferHook {
+        contract: HumanAddr,
+    },
+
+    // Proof of funds
+    /// Attests that the sender holds at least `min_amount` now. The attestation's id is in
+    /// `data`, see `AttestFundsResponse`
+    AttestFunds {
+        min_amount: Uint128,
+    },
+
+    // Compliance
+    /// Admin only, needs compliance lists to be enabled at init
+    SetComplianceStatus {
+        addresses: Vec<HumanAddr>,
+        status: ComplianceStatus,
+    },
+
+    // Freezes
+    /// Admin only, needs freezes to be enabled at init. The account can't send or receive
+    /// transfers until it's unfrozen
+    FreezeAccount {
+        account: HumanAddr,
+    },
+    UnfreezeAccount {
+        account: HumanAddr,
+    },
+
+    // Clawback
+    /// Admin only, needs clawbacks to be enabled at init. Moves `amount` from `owner` to
+    /// `recipient`. `reason` is public, see `QueryMsg::Clawbacks`
+    Clawback {
+        owner: HumanAddr,
+        recipient: HumanAddr,
+        amount: Uint128,
+        reason: String,
+    },
+
+    // Signed transfers
+    /// Makes a transfer the owner signed offline. Anyone can send it, so someone else can pay
+    /// the gas. `nonce` has to be the owner's next one
+    ExecuteSigned {
+        transfer: SignedTransfer,
+        nonce: u64,
+        signature: PermitSignature,
+    },
+    /// Sets an allowance the owner signed offline, e.g. right before the spender uses it in the
+    /// same transaction. Uses up the owner's next nonce
+    PermitAllowance {
+        owner: HumanAddr,
+        spender: HumanAddr,
+        amount: Uint128,
+        /// Block time, in seconds
+        deadline: u64,
+        signature: PermitSignature,
+    },
+
+    // Admin
+    /// Irreversibly stops deposits, transfers and minting, so that holders can only withdraw
+    PermanentRedeemOnly {},
+    /// Caps the total withdrawn during each `period` seconds, or removes the cap if
+    /// `max_amount` is not set
+    SetRedeemLimit {
+        max_amount: Option<Uint128>,
+        period: u64,
+    },
+    /// Sets the fees taken out of deposits and withdrawals, in basis points
+    SetFees {
+        deposit_fee_bps: u16,
+        redeem_fee_bps: u16,
+    },
+    /// Credits fees to `treasury` from now on, and sweeps what the contract holds itself there
+    SetTreasury {
+        treasury: HumanAddr,
+    },
+    /// Sends `amount` of the tokens the contract holds itself to `recipient`, or all of them if
+    /// `amount` is not set. Fees are there while no treasury is set
+    WithdrawFees {
+        recipient: HumanAddr,
+        amount: Option<Uint128>,
+    },
+    /// Accepts `new_denom` instead of `denom` for deposits and withdrawals, for when the chain
+    /// renames a denom. What was deposited in `denom` can be withdrawn in `new_denom`
+    RenameDenom {
+        denom: String,
+        new_denom: String,
+    },
+    ChangeAdmin {
+        address: HumanAddr,
+    },
+    /// Sets what `MarketingInfo` shows. Fields that aren't set stay as they are, empty strings
+    /// remove them
+    UpdateMarketing {
+        project: Option<String>,
+        description: Option<String>,
+    },
+    /// Replaces the logo, or removes it if `logo` is not set
+    UploadLogo {
+        logo: Option<Logo>,
+    },
+    /// Lets `contract` query voting weights with `key`, or revokes that if `key` is not set
+    SetGovernanceKey {
+        contract: HumanAddr,
+        key: Option<String>,
+    },
+
     // Privacy stuff
     SetViewingKey { key: String },
+    PruneHistory { keep_last: u32 },
     CreateViewingKey { entropy: String }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    Balance { address: HumanAddr, key: String },
-    Transfers { address: HumanAddr, key: String },
+    /// With `denom`, only the part of the balance that came from that native denom
+    Balance {
+        address: HumanAddr,
+        key: String,
+        denom: Option<String>,
+    },
+    Transfers {
+        address: HumanAddr,
+        key: String,
+        page: Option<u32>,
+        page_size: Option<u32>,
+        /// Only return transfers the address sent, or only those it received
+        direction: Option<TxDirection>,
+        /// Only return transfers from or to this address
+        counterparty: Option<HumanAddr>,
+        /// Only return transfers at or after this block time
+        start_time: Option<u64>,
+        /// Only return transfers before this block time
+        end_time: Option<u64>,
+    },
+    TransferCount { address: HumanAddr, key: String },
+    AccountStats { address: HumanAddr, key: String },
+    AccountTotals { address: HumanAddr, key: String },
+    VestingSchedules { address: HumanAddr, key: String },
+    LockedBalance { address: HumanAddr, key: String },
+    Unbondings { address: HumanAddr, key: String },
+    Rewards { address: HumanAddr, key: String },
+    /// The spenders the address gave a nonzero allowance to, and what's left of each
+    ActiveSpenders { address: HumanAddr, key: String },
+    /// The contracts the address can `Send` to, if it restricted them
+    SendWhitelist { address: HumanAddr, key: String },
+    /// Whether the address blocks incoming transfers, and who it accepts them from
+    IncomingTransfers { address: HumanAddr, key: String },
+    /// The treasury and its balance, only for the admin and the treasury
+    CollectedFees { address: HumanAddr, key: String },
+    /// Checks the viewing key once and answers all `queries`, which must be for `address`
+    Batch {
+        address: HumanAddr,
+        key: String,
+        queries: Vec<QueryMsg>,
+    },
+    /// Name, symbol, decimals and total supply
+    TokenInfo {},
+    /// Whether the token works normally or is being wound down
+    ContractStatus {},
+    /// The uscrt the contract holds, against the supply it backs
+    ReserveBacking {},
+    ExchangeRate {},
+    /// The deposit and withdrawal fees, in basis points
+    Fees {},
+    /// The project URL, description and logo set by the admin
+    MarketingInfo {},
+    /// A cw20 query, answered in cw20's shape. `auth` is needed for everything but `token_info`
+    Cw20 {
+        query: Cw20QueryMsg,
+        auth: Option<Cw20Auth>,
+    },
+    ComplianceStatus { address: HumanAddr },
+    /// Whether the address is frozen
+    FreezeStatus { address: HumanAddr },
+    /// Whether the admin can claw back tokens, and the amounts and reasons of past clawbacks
+    Clawbacks {
+        page: Option<u32>,
+        page_size: Option<u32>,
+    },
+    /// The nonce the address's next signed message has to carry
+    Nonce { address: HumanAddr },
+    /// A proof of funds made with `AttestFunds`
+    Attestation { id: String },
+    /// Balances of several accounts at once, one permit signed by each
+    PermitBalances { permits: Vec<Permit> },
+    /// Answers `query` for the account that signed `permit`. The permit needs the `balance`,
+    /// `history` or `allowance` permission, depending on the query
+    WithPermit {
+        permit: Permit,
+        query: QueryWithPermit,
+    },
+    /// Only for whitelisted governance contracts, which pass their key along with a voter's
+    /// permit
+    VotingWeight {
+        permit: Permit,
+        governance_contract: HumanAddr,
+        governance_key: String,
+    },
     Test {},
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    Balance {},
+    Transfers {
+        page: Option<u32>,
+        page_size: Option<u32>,
+    },
+    TransferCount {},
+    /// Only for the owner or the spender of the allowance
+    Allowance {
+        owner: HumanAddr,
+        spender: HumanAddr,
+    },
+}
+
 impl QueryMsg {
-    pub fn get_validation_params(&self) -> (&HumanAddr, ViewingKey) {
-        match self {
-            Self::Balance { address, key} => (address, ViewingKey(key.clone())),
-            Self::Transfers { address, key} => (address, ViewingKey(key.clone())),
-            _ => (panic!("lol"))
-        }
+    /// The address a query is for and the viewing key it authenticates with. Fails for the
+    /// queries that don't take a key
+    pub fn get_validation_params(&self) -> StdResult<(&HumanAddr, ViewingKey)> {
+        let params = match self {
+            Self::Balance { address, key, .. } => (address, ViewingKey(key.clone())),
+            Self::Transfers { address, key, .. } => (address, ViewingKey(key.clone())),
+            Self::TransferCount { address, key } => (address, ViewingKey(key.clone())),
+            Self::AccountStats { address, key } => (address, ViewingKey(key.clone())),
+            Self::AccountTotals { address, key } => (address, ViewingKey(key.clone())),
+            Self::VestingSchedules { address, key } => (address, ViewingKey(key.clone())),
+            Self::LockedBalance { address, key } => (address, ViewingKey(key.clone())),
+            Self::Unbondings { address, key } => (address, ViewingKey(key.clone())),
+            Self::Rewards { address, key } => (address, ViewingKey(key.clone())),
+            Self::ActiveSpenders { address, key } => (address, ViewingKey(key.clone())),
+            Self::SendWhitelist { address, key } => (address, ViewingKey(key.clone())),
+            Self::IncomingTransfers { address, key } => (address, ViewingKey(key.clone())),
+            Self::CollectedFees { address, key } => (address, ViewingKey(key.clone())),
+            Self::Batch { address, key, .. } => (address, ViewingKey(key.clone())),
+            _ => return Err(generic_err("This query doesn't take a viewing key")),
+        };
+        Ok(params)
     }
 }
 
-#[derive(Clone, PartialEq)]
+/// What the deprecated `HandleMsg::Balance` returns in `data`. `token` is the symbol
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct BalanceResponse {
     pub balance: Uint128,
     pub token: String,
 }
 
-// impl Serialize for BalanceResponse {
-//     fn serialize<S>(&self, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error> where
-//         S: Serializer {
-//         unimplemented!()
-//     }
-// }
+/// A page of transfers, newest first
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct TransfersResponse {
+    pub txs: Vec<Tx>,
+}
+
+/// How many transfers an account's history holds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransferCountResponse {
+    pub count: u32,
+}
+
+/// Activity counters of an account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountStatsResponse {
+    pub stats: AccountStats,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountBalance {
+    pub address: HumanAddr,
+    pub balance: Uint128,
+}
+
+/// The balances of the accounts that signed the permits, in the same order
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitBalancesResponse {
+    pub balances: Vec<AccountBalance>,
+}
+
+/// The answers to a `Batch` query, in the order of its queries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchResponse {
+    pub answers: Vec<Binary>,
+}
 
+/// Lifetime transfer totals of an account, along with its current balance. Transfers made
+/// before totals were tracked aren't counted
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountTotalsResponse {
+    pub received: Uint128,
+    pub sent: Uint128,
+    pub balance: Uint128,
+}
+
+/// Rewards that can be claimed with `ClaimRewards`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardsResponse {
+    pub rewards: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Normal,
+    /// See `HandleMsg::PermanentRedeemOnly`
+    RedeemOnly,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractStatusResponse {
+    pub status: ContractStatus,
+}
+
+/// What backs the supply, for every supported denom. Together the reserves cover
+/// `total_supply_native` 1:1. In staking mode the uscrt is delegated, see `ExchangeRate` instead
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReserveBackingResponse {
+    pub denoms: Vec<DenomBacking>,
+    /// The total supply in native units, rounded down for tokens with more than 6 decimals
+    pub total_supply_native: Uint128,
+}
+
+/// `balance` is the contract's bank balance of `denom` and `reserve` what it holds for
+/// withdrawals of it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomBacking {
+    pub denom: String,
+    pub balance: Uint128,
+    pub reserve: Uint128,
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct AllowanceResponse {
     pub allowance: Uint128,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpenderAllowance {
+    pub spender: HumanAddr,
+    pub allowance: Uint128,
+}
+
+/// Allowances that last changed before spenders were tracked are missing until they change
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ActiveSpendersResponse {
+    pub spenders: Vec<SpenderAllowance>,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct CreateViewingKeyResponse {
     pub key: String,
 }
+
+/// Answers a query instead of its own answer when the viewing key doesn't match. Always the
+/// same size, so it doesn't reveal whether the account has a key at all
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryAnswer {
+    ViewingKeyError { msg: String },
+}
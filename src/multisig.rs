@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::{credit, debit, perform_transfer};
+use crate::hooks::transfer_hook_messages;
+use crate::incoming::check_incoming;
+use crate::msg::HandleMsg;
+use crate::payment_requests::get_payment_request;
+use crate::permit::pub_key_to_address;
+use crate::state::{
+    store_transfer, Balances, ReadonlyBalances, ReadonlyVersionedStorage, TypedStore,
+    TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_TRANSFER_POLICIES: &[u8] = b"transfer_policies";
+pub const PREFIX_PROPOSED_TRANSFERS: &[u8] = b"proposed_transfers";
+pub const KEY_PROPOSED_COUNT: &[u8] = b"proposed_count";
+
+/// Moving more than `threshold` out of the account needs `co_signer`'s approval
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransferPolicy {
+    pub co_signer: CanonicalAddr,
+    pub threshold: u128,
+}
+
+/// A transfer above the owner's threshold, waiting for the co-signer. The amount is held back
+/// from the owner's balance until it is approved or cancelled
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProposedTransfer {
+    pub owner: CanonicalAddr,
+    pub recipient: CanonicalAddr,
+    pub amount: u128,
+}
+
+pub fn get_transfer_policy<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<TransferPolicy>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_TRANSFER_POLICIES, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+/// Returns whose funds `msg` moves out and how much, for messages sent by `sender`
+pub fn outflow<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    sender: &CanonicalAddr,
+    msg: &HandleMsg,
+) -> StdResult<Option<(CanonicalAddr, u128)>> {
+    let amount = match msg {
+        HandleMsg::Withdraw { amount, .. }
+        | HandleMsg::RedeemAndSend { amount, .. }
+        | HandleMsg::Transfer { amount, .. }
+        | HandleMsg::Send { amount, .. }
+        | HandleMsg::TransferAndCall { amount, .. }
+        | HandleMsg::WrapAndTransfer { amount, .. }
+        | HandleMsg::WrapAndSend { amount, .. }
+        | HandleMsg::Burn { amount }
+        | HandleMsg::TransferPending { amount, .. }
+        | HandleMsg::CreateClaim { amount, .. }
+        | HandleMsg::TransferVesting { amount, .. }
+        | HandleMsg::TransferLocked { amount, .. }
+        | HandleMsg::CreateEscrow { amount, .. }
+        | HandleMsg::OpenChannel { amount, .. }
+        | HandleMsg::CreateStream { amount, .. }
+        | HandleMsg::BridgeBurn { amount, .. }
+        | HandleMsg::UnwrapToken { amount, .. } => amount,
+        HandleMsg::TransferFrom { owner, amount, .. }
+        | HandleMsg::SendFrom { owner, amount, .. }
+        | HandleMsg::Pull { owner, amount } => {
+            return Ok(Some((deps.api.canonical_address(owner)?, amount.u128())));
+        }
+        HandleMsg::ExecuteSigned {
+            transfer,
+            signature,
+            ..
+        } => {
+            let owner = pub_key_to_address(signature.pub_key.value.as_slice());
+            return Ok(Some((owner, transfer.amount.u128())));
+        }
+        HandleMsg::BatchTransfer { actions } => {
+            let total = actions
+                .iter()
+                .try_fold(0u128, |sum, action| sum.checked_add(action.amount.u128()))
+                .ok_or_else(|| generic_err("Batch total overflow"))?;
+            return Ok(Some((sender.clone(), total)));
+        }
+        HandleMsg::CloseAccount { .. } => {
+            let balance = ReadonlyBalances::from_storage(&deps.storage).balance(sender)?;
+            return Ok(Some((sender.clone(), balance)));
+        }
+        HandleMsg::ClaimInheritance { owner: account }
+        | HandleMsg::ExecuteRecovery { account } => {
+            let owner = deps.api.canonical_address(account)?;
+            let balance = ReadonlyBalances::from_storage(&deps.storage).balance(&owner)?;
+            return Ok(Some((owner, balance)));
+        }
+        HandleMsg::PayRequest { id } => {
+            let request = get_payment_request(&deps.storage, *id)?;
+            return Ok(Some((sender.clone(), request.amount)));
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some((sender.clone(), amount.u128())))
+}
+
+/// Rejects `msg` if it moves more out of an account than the account's policy allows without
+/// a co-signer
+pub fn check_transfer_policy<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    msg: &HandleMsg,
+) -> StdResult<()> {
+    let (owner, amount) = match outflow(deps, &env.message.sender, msg)? {
+        Some(outflow) => outflow,
+        None => return Ok(()),
+    };
+
+    match get_transfer_policy(&deps.storage, &owner)? {
+        Some(policy) if amount > policy.threshold => Err(generic_err(
+            "Amount is above the account's threshold, use ProposeTransfer",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the sender's policy. Once a policy is set, only the co-signer can lift it
+pub fn try_set_transfer_policy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    co_signer: &HumanAddr,
+    threshold: Uint128,
+) -> StdResult<HandleResponse> {
+    let owner = &env.message.sender;
+    if get_transfer_policy(&deps.storage, owner)?.is_some() {
+        return Err(generic_err(
+            "A transfer policy is already set, the co-signer has to release it first",
+        ));
+    }
+
+    let policy = TransferPolicy {
+        co_signer: deps.api.canonical_address(co_signer)?,
+        threshold: threshold.u128(),
+    };
+    let mut store = VersionedStorage::new(PREFIX_TRANSFER_POLICIES, &mut deps.storage);
+    TypedStoreMut::attach(&mut store).store(owner.as_slice(), &policy)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_transfer_policy")],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Removes `account`'s policy. Only its co-signer can do this
+pub fn try_release_transfer_policy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let account_raw = deps.api.canonical_address(account)?;
+    let policy = get_transfer_policy(&deps.storage, &account_raw)?
+        .ok_or_else(|| generic_err("The account has no transfer policy"))?;
+    if policy.co_signer != env.message.sender {
+        return Err(unauthorized());
+    }
+
+    let mut store = VersionedStorage::new(PREFIX_TRANSFER_POLICIES, &mut deps.storage);
+    TypedStoreMut::<TransferPolicy, _>::attach(&mut store).remove(account_raw.as_slice());
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "release_transfer_policy")],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn get_proposed_transfer<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<ProposedTransfer> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PROPOSED_TRANSFERS, storage);
+    TypedStore::attach(&store)
+        .may_load(&id.to_be_bytes())?
+        .ok_or_else(|| generic_err(format!("No proposed transfer with id {}", id)))
+}
+
+fn remove_proposed_transfer<S: Storage>(storage: &mut S, id: u64) {
+    let mut store = VersionedStorage::new(PREFIX_PROPOSED_TRANSFERS, storage);
+    store.remove(&id.to_be_bytes());
+}
+
+/// Holds `amount` back from the sender's balance until their co-signer approves sending it to
+/// `recipient`. Only accounts with a policy can propose transfers
+pub fn try_propose_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let owner = &env.message.sender;
+    if get_transfer_policy(&deps.storage, owner)?.is_none() {
+        return Err(generic_err("The account has no transfer policy, transfer directly"));
+    }
+
+    let proposal = ProposedTransfer {
+        owner: owner.clone(),
+        recipient: deps.api.canonical_address(recipient)?,
+        amount: amount.u128(),
+    };
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let balance = debit(balances.balance(owner)?, proposal.amount)?;
+    balances.set_balance(owner, balance)?;
+
+    let mut store = VersionedStorage::new(PREFIX_PROPOSED_TRANSFERS, &mut deps.storage);
+    let id = TypedStore::<u64, _>::attach(&store)
+        .may_load(KEY_PROPOSED_COUNT)?
+        .unwrap_or(0)
+        + 1;
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), &proposal)?;
+    TypedStoreMut::attach(&mut store).store(KEY_PROPOSED_COUNT, &id)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "propose_transfer"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Executes proposal `id`. Only the co-signer of the proposing account can do this
+pub fn try_approve_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let proposal = get_proposed_transfer(&deps.storage, id)?;
+
+    let policy = get_transfer_policy(&deps.storage, &proposal.owner)?;
+    match policy {
+        Some(policy) if policy.co_signer == env.message.sender => {}
+        _ => return Err(unauthorized()),
+    }
+
+    remove_proposed_transfer(&mut deps.storage, id);
+    // give the held amount back first, so the transfer goes through the usual checks
+    release_held(&mut deps.storage, &proposal)?;
+
+    check_incoming(&deps.storage, &proposal.owner, &proposal.recipient)?;
+    perform_transfer(&mut deps.storage, &proposal.owner, &proposal.recipient, proposal.amount)?;
+
+    store_transfer(
+        &mut deps.storage,
+        &proposal.owner,
+        &proposal.recipient,
+        &Uint128(proposal.amount),
+        env.block.time,
+    )?;
+
+    let messages = transfer_hook_messages(
+        &deps.storage,
+        &deps.api,
+        &proposal.owner,
+        &proposal.recipient,
+        proposal.amount,
+    )?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![log("action", "approve_transfer"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Returns the held amount of proposal `id` to the owner. Only the owner can do this
+pub fn try_cancel_proposed_transfer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let proposal = get_proposed_transfer(&deps.storage, id)?;
+    if proposal.owner != env.message.sender {
+        return Err(unauthorized());
+    }
+
+    remove_proposed_transfer(&mut deps.storage, id);
+    release_held(&mut deps.storage, &proposal)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "cancel_proposed_transfer"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn release_held<S: Storage>(storage: &mut S, proposal: &ProposedTransfer) -> StdResult<()> {
+    let mut balances = Balances::from_storage(storage);
+    let balance = credit(balances.balance(&proposal.owner)?, proposal.amount)?;
+    balances.set_balance(&proposal.owner, balance)
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, Api, CanonicalAddr, Env, Extern, HandleResponse, Querier, ReadonlyStorage,
+    StdResult, Storage, Uint128,
+};
+
+use crate::contract::perform_transfer;
+use crate::hooks::transfer_hook_messages;
+use crate::state::{
+    store_tagged_transfer, ReadonlyVersionedStorage, TypedStore, TypedStoreMut,
+    VersionedStorage,
+};
+
+pub const PREFIX_PAYMENT_REQUESTS: &[u8] = b"payment_requests";
+pub const KEY_PAYMENT_REQUEST_COUNT: &[u8] = b"payment_request_count";
+
+/// Max length of a payment request's reference, in bytes
+pub const MAX_REFERENCE_LENGTH: usize = 64;
+
+/// An invoice the merchant shares off-chain. Whoever pays it first, before `expiry`, settles it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PaymentRequest {
+    pub merchant: CanonicalAddr,
+    pub amount: u128,
+    pub reference: String,
+    pub expiry: u64,
+}
+
+pub fn get_payment_request<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<PaymentRequest> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PAYMENT_REQUESTS, storage);
+    TypedStore::attach(&store)
+        .may_load(&id.to_be_bytes())?
+        .ok_or_else(|| generic_err(format!("No payment request with id {}", id)))
+}
+
+pub fn try_create_payment_request<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+    reference: String,
+    expiry: u64,
+) -> StdResult<HandleResponse> {
+    if expiry <= env.block.time {
+        return Err(generic_err("Expiry must be in the future"));
+    }
+    if reference.len() > MAX_REFERENCE_LENGTH {
+        return Err(generic_err(format!(
+            "Reference can't be longer than {} bytes",
+            MAX_REFERENCE_LENGTH
+        )));
+    }
+
+    let request = PaymentRequest {
+        merchant: env.message.sender,
+        amount: amount.u128(),
+        reference,
+        expiry,
+    };
+
+    let mut store = VersionedStorage::new(PREFIX_PAYMENT_REQUESTS, &mut deps.storage);
+    let id = TypedStore::<u64, _>::attach(&store)
+        .may_load(KEY_PAYMENT_REQUEST_COUNT)?
+        .unwrap_or(0)
+        + 1;
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), &request)?;
+    TypedStoreMut::attach(&mut store).store(KEY_PAYMENT_REQUEST_COUNT, &id)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "create_payment_request"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Pays request `id` from the sender's balance. The transfer shows up in both histories tagged
+/// with the request's reference
+pub fn try_pay_request<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let request = get_payment_request(&deps.storage, id)?;
+    if env.block.time >= request.expiry {
+        return Err(generic_err("Payment request has expired"));
+    }
+
+    let mut store = VersionedStorage::new(PREFIX_PAYMENT_REQUESTS, &mut deps.storage);
+    store.remove(&id.to_be_bytes());
+
+    let payer = &env.message.sender;
+    perform_transfer(&mut deps.storage, payer, &request.merchant, request.amount)?;
+
+    store_tagged_transfer(
+        &mut deps.storage,
+        payer,
+        &request.merchant,
+        &Uint128(request.amount),
+        env.block.time,
+        Some(&request.reference),
+    )?;
+
+    let messages = transfer_hook_messages(
+        &deps.storage,
+        &deps.api,
+        payer,
+        &request.merchant,
+        request.amount,
+    )?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![log("action", "pay_request"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
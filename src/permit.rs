@@ -0,0 +1,173 @@
+use ripemd160::{Digest, Ripemd160};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{generic_err, to_binary, Binary, CanonicalAddr, HumanAddr, StdResult};
+
+use crate::rand::sha_256;
+
+/// A query permit: the owner of `signature.pub_key` signed `params` with their wallet, which
+/// lets whoever holds the permit query on their behalf without a viewing key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Permit {
+    pub params: PermitParams,
+    pub signature: PermitSignature,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitParams {
+    pub allowed_tokens: Vec<HumanAddr>,
+    pub permit_name: String,
+    pub chain_id: String,
+    /// What the permit lets its holder see
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Balance,
+    History,
+    Allowance,
+    /// Everything the other permissions allow
+    Owner,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitSignature {
+    pub pub_key: PubKey,
+    pub signature: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PubKey {
+    /// Always "tendermint/PubKeySecp256k1"
+    pub r#type: String,
+    /// 33 byte compressed secp256k1 key
+    pub value: Binary,
+}
+
+// The document wallets actually sign, an amino `StdSignDoc` wrapping the params. Fields are
+// declared in alphabetical order because amino JSON is signed with sorted keys
+
+#[derive(Serialize)]
+struct SignDoc<'a, T: Serialize> {
+    account_number: &'static str,
+    chain_id: &'a str,
+    fee: Fee,
+    memo: &'static str,
+    msgs: [SignedMsg<'a, T>; 1],
+    sequence: &'static str,
+}
+
+#[derive(Serialize)]
+struct Fee {
+    amount: [FeeAmount; 1],
+    gas: &'static str,
+}
+
+#[derive(Serialize)]
+struct FeeAmount {
+    amount: &'static str,
+    denom: &'static str,
+}
+
+#[derive(Serialize)]
+struct SignedMsg<'a, T: Serialize> {
+    r#type: &'static str,
+    value: &'a T,
+}
+
+#[derive(Serialize)]
+struct SignedParams<'a> {
+    allowed_tokens: &'a [HumanAddr],
+    permissions: &'a [Permission],
+    permit_name: &'a str,
+}
+
+impl Permit {
+    /// Checks that the permit is for `this_contract` and grants `permission`, and the signature.
+    /// Returns the address of the account that signed the permit
+    pub fn validate(
+        &self,
+        this_contract: &HumanAddr,
+        permission: Permission,
+    ) -> StdResult<CanonicalAddr> {
+        if !self.params.allowed_tokens.contains(this_contract) {
+            return Err(generic_err("The permit isn't valid for this token"));
+        }
+        let permissions = &self.params.permissions;
+        if !permissions.contains(&permission) && !permissions.contains(&Permission::Owner) {
+            return Err(generic_err(format!(
+                "The permit doesn't grant the {:?} permission",
+                permission
+            )));
+        }
+
+        let params = SignedParams {
+            allowed_tokens: &self.params.allowed_tokens,
+            permissions,
+            permit_name: &self.params.permit_name,
+        };
+        let signed_bytes = amino_sign_bytes(&self.params.chain_id, "query_permit", &params)?;
+
+        self.signature.verify(signed_bytes.as_slice())
+    }
+}
+
+impl PermitSignature {
+    /// Checks that this signs `message` and returns the address of the account that signed it
+    pub fn verify(&self, message: &[u8]) -> StdResult<CanonicalAddr> {
+        let pub_key = self.pub_key.value.as_slice();
+        if !verify_signature(message, self.signature.as_slice(), pub_key)? {
+            return Err(generic_err("Failed to verify signature"));
+        }
+        Ok(pub_key_to_address(pub_key))
+    }
+}
+
+/// The bytes a wallet signs for an offline amino message of type `msg_type` with `value`, the
+/// way Keplr's `signAmino` does. `value`'s fields have to be in alphabetical order
+pub fn amino_sign_bytes<T: Serialize>(
+    chain_id: &str,
+    msg_type: &'static str,
+    value: &T,
+) -> StdResult<Binary> {
+    to_binary(&SignDoc {
+        account_number: "0",
+        chain_id,
+        fee: Fee {
+            amount: [FeeAmount {
+                amount: "0",
+                denom: "uscrt",
+            }],
+            gas: "1",
+        },
+        memo: "",
+        msgs: [SignedMsg {
+            r#type: msg_type,
+            value,
+        }],
+        sequence: "0",
+    })
+}
+
+/// Verifies a 64 byte compact secp256k1 `signature` over the SHA-256 hash of `message`
+pub fn verify_signature(message: &[u8], signature: &[u8], pub_key: &[u8]) -> StdResult<bool> {
+    let message = secp256k1::Message::parse(&sha_256(message));
+    let signature = secp256k1::Signature::parse_slice(signature)
+        .map_err(|_| generic_err("Malformed signature"))?;
+    let pub_key = secp256k1::PublicKey::parse_slice(
+        pub_key,
+        Some(secp256k1::PublicKeyFormat::Compressed),
+    )
+    .map_err(|_| generic_err("Malformed public key"))?;
+
+    Ok(secp256k1::verify(&message, &signature, &pub_key))
+}
+
+/// Cosmos addresses are the RIPEMD-160 hash of the SHA-256 hash of the public key
+pub fn pub_key_to_address(pub_key: &[u8]) -> CanonicalAddr {
+    let hash = Ripemd160::digest(&sha_256(pub_key));
+    CanonicalAddr::from(hash.to_vec())
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::perform_transfer;
+use crate::hooks::transfer_hook_messages;
+use crate::state::{
+    store_tagged_transfer, ReadonlyVersionedStorage, TypedStore, TypedStoreMut,
+    VersionedStorage,
+};
+
+pub const PREFIX_PULL_AUTHORIZATIONS: &[u8] = b"pull_authorizations";
+
+/// The reference pulls are tagged with in both histories
+pub const PULL_REFERENCE: &str = "pull";
+
+/// Lets a contract take up to `max_amount` from the owner's balance, at most once every
+/// `frequency` seconds
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PullAuthorization {
+    pub max_amount: u128,
+    pub frequency: u64,
+    pub last_pull: Option<u64>,
+}
+
+fn get_authorization<S: ReadonlyStorage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+    contract: &CanonicalAddr,
+) -> StdResult<Option<PullAuthorization>> {
+    let store = ReadonlyVersionedStorage::multilevel(
+        &[PREFIX_PULL_AUTHORIZATIONS, owner.as_slice()],
+        storage,
+    );
+    TypedStore::attach(&store).may_load(contract.as_slice())
+}
+
+fn set_authorization<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    contract: &CanonicalAddr,
+    authorization: Option<&PullAuthorization>,
+) -> StdResult<()> {
+    let mut store =
+        VersionedStorage::multilevel(&[PREFIX_PULL_AUTHORIZATIONS, owner.as_slice()], storage);
+    match authorization {
+        Some(authorization) => {
+            TypedStoreMut::attach(&mut store).store(contract.as_slice(), authorization)
+        }
+        None => {
+            TypedStoreMut::<PullAuthorization, _>::attach(&mut store).remove(contract.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Replaces `contract`'s authorization to pull from the sender. The first pull is allowed right
+/// away
+pub fn try_authorize_pull<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: &HumanAddr,
+    max_amount: Uint128,
+    frequency: u64,
+) -> StdResult<HandleResponse> {
+    let contract_raw = deps.api.canonical_address(contract)?;
+    let authorization = PullAuthorization {
+        max_amount: max_amount.u128(),
+        frequency,
+        last_pull: None,
+    };
+    set_authorization(&mut deps.storage, &env.message.sender, &contract_raw, Some(&authorization))?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "authorize_pull"),
+            log("contract", contract.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_revoke_pull<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let contract_raw = deps.api.canonical_address(contract)?;
+    set_authorization(&mut deps.storage, &env.message.sender, &contract_raw, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "revoke_pull"),
+            log("contract", contract.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Moves `amount` from `owner` to the sending contract, within the limits `owner` authorized
+pub fn try_pull<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let owner_raw = deps.api.canonical_address(owner)?;
+    let contract = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    let mut authorization = get_authorization(&deps.storage, &owner_raw, contract)?
+        .ok_or_else(unauthorized)?;
+    if amount_raw > authorization.max_amount {
+        return Err(generic_err(format!(
+            "Can pull at most {} at a time",
+            authorization.max_amount
+        )));
+    }
+    if let Some(last_pull) = authorization.last_pull {
+        let next_pull = last_pull.saturating_add(authorization.frequency);
+        if env.block.time < next_pull {
+            return Err(generic_err(format!("Next pull is allowed at {}", next_pull)));
+        }
+    }
+    authorization.last_pull = Some(env.block.time);
+    set_authorization(&mut deps.storage, &owner_raw, contract, Some(&authorization))?;
+
+    perform_transfer(&mut deps.storage, &owner_raw, contract, amount_raw)?;
+
+    store_tagged_transfer(
+        &mut deps.storage,
+        &owner_raw,
+        contract,
+        &amount,
+        env.block.time,
+        Some(PULL_REFERENCE),
+    )?;
+
+    let messages = transfer_hook_messages(&deps.storage, &deps.api, &owner_raw, contract, amount_raw)?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![log("action", "pull"), log("owner", owner.as_str())],
+        data: None,
+    };
+    Ok(res)
+}
@@ -1,9 +1,23 @@
-
+//! The token's one source of randomness. A contract has no private entropy of its own, so every
+//! draw is seeded from a secret seed kept in storage, which only the enclave can read, mixed with
+//! the message's env and whatever entropy the sender passed. The seed is replaced after each use,
+//! so draws never repeat and each one depends on all the entropy that came before it. Every
+//! message also folds its env into an entropy pool that draws mix in, so even a token whose
+//! initial seed was guessable gets harder to predict the more it's used
 use rand_chacha::ChaChaRng;
 use rand_core::{RngCore, SeedableRng};
 
 use sha2::{Digest, Sha256};
 
+use cosmwasm_std::{Env, ReadonlyStorage, Storage};
+
+use crate::state::{ReadonlyVersionedStorage, VersionedStorage};
+use crate::utils::{tagged_hash, ENTROPY_POOL_TAG, PRNG_SEED_TAG};
+
+pub const PREFIX_PRNG: &[u8] = b"prng";
+pub const KEY_PRNG_SEED: &[u8] = b"seed";
+pub const KEY_ENTROPY_POOL: &[u8] = b"pool";
+
 pub fn sha_256(data: &[u8]) -> [u8; 32] {
     let mut hasher = Sha256::new();
     hasher.update(data);
@@ -14,44 +28,76 @@ pub fn sha_256(data: &[u8]) -> [u8; 32] {
     result
 }
 
-
+/// A ChaCha stream seeded with the tagged hash of `seed` and `entropy`
 pub struct Prng {
-    seed: Vec<u8>,
-    entropy: Vec<u8>,
-    pos: u128
+    rng: ChaChaRng,
 }
 
 impl Prng {
-
     pub fn new(seed: &[u8], entropy: &[u8]) -> Self {
-        return Self {
-            seed: seed.to_vec(),
-            entropy: entropy.to_vec(),
-            pos: 0
+        let rng_seed = tagged_hash(PRNG_SEED_TAG, &[seed, entropy].concat());
+        Self {
+            rng: ChaChaRng::from_seed(rng_seed),
         }
     }
 
-    pub fn rand_slice(&mut self) -> [u32; 8] {
-        let mut hasher = Sha256::new();
+    /// The next 32 bytes of the stream
+    pub fn rand_bytes(&mut self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        self.rng.fill_bytes(&mut bytes);
+        bytes
+    }
+}
+
+fn get_seed<S: ReadonlyStorage>(storage: &S) -> Vec<u8> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PRNG, storage);
+    // tokens instantiated before the seed was stored start from the env alone
+    store.get(KEY_PRNG_SEED).unwrap_or_default()
+}
+
+fn set_seed<S: Storage>(storage: &mut S, seed: &[u8]) {
+    let mut store = VersionedStorage::new(PREFIX_PRNG, storage);
+    store.set(KEY_PRNG_SEED, seed);
+}
 
-        // write input message
-        hasher.update(&self.seed);
-        hasher.update(&self.entropy);
-        let hash = hasher.finalize();
+fn get_pool<S: ReadonlyStorage>(storage: &S) -> Vec<u8> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PRNG, storage);
+    store.get(KEY_ENTROPY_POOL).unwrap_or_default()
+}
+
+/// Folds the message's env into the entropy pool, on every handle
+pub fn update_entropy_pool<S: Storage>(storage: &mut S, env: &Env) {
+    let pool = tagged_hash(ENTROPY_POOL_TAG, &[get_pool(storage), env_entropy(env)].concat());
+    let mut store = VersionedStorage::new(PREFIX_PRNG, storage);
+    store.set(KEY_ENTROPY_POOL, &pool);
+}
 
-        let mut result = [0u8; 32];
-        result.copy_from_slice(hash.as_slice());
+/// Stores the first seed, from the one passed at init or, failing that, from the init env
+pub fn init_prng<S: Storage>(storage: &mut S, env: &Env, seed: Option<&[u8]>) {
+    let seed = match seed {
+        Some(seed) => tagged_hash(PRNG_SEED_TAG, seed),
+        None => Prng::new(&env_entropy(env), env.contract.address.as_slice()).rand_bytes(),
+    };
+    set_seed(storage, &seed);
+}
 
-        let mut rng: ChaChaRng = ChaChaRng::from_seed(result);
+/// The generator every handler that needs randomness draws from, seeded from the stored seed,
+/// the entropy pool, the message's env and `entropy`
+pub fn contract_rng<S: Storage>(storage: &mut S, env: &Env, entropy: &[u8]) -> Prng {
+    let mut rng_entropy = get_pool(storage);
+    rng_entropy.extend_from_slice(&env_entropy(env));
+    rng_entropy.extend_from_slice(entropy);
 
-        rng.set_word_pos(self.pos.into());
-        self.pos += 8;
+    let mut rng = Prng::new(&get_seed(storage), &rng_entropy);
+    set_seed(storage, &rng.rand_bytes());
 
-        let mut output = [0u32; 8];
-        for i in output.iter_mut() {
-            *i = rng.next_u32();
-        }
+    rng
+}
 
-        output
-    }
-}
\ No newline at end of file
+fn env_entropy(env: &Env) -> Vec<u8> {
+    let mut entropy: Vec<u8> = vec![];
+    entropy.extend_from_slice(&env.block.height.to_be_bytes());
+    entropy.extend_from_slice(&env.block.time.to_be_bytes());
+    entropy.extend_from_slice(env.message.sender.as_slice());
+    entropy
+}
@@ -0,0 +1,93 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{to_binary, Binary, CosmosMsg, HumanAddr, StdResult, Uint128, WasmMsg};
+
+/// The callback a contract receives when tokens are sent to it with `Send`, after it registered
+/// its code hash with `RegisterReceive`
+///
+/// `sender` is the address that executed the `Send`, `from` is the address whose balance the
+/// tokens came out of
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Snip20ReceiveMsg {
+    pub sender: HumanAddr,
+    pub from: HumanAddr,
+    pub amount: Uint128,
+    pub msg: Option<Binary>,
+}
+
+impl Snip20ReceiveMsg {
+    pub fn new(sender: HumanAddr, from: HumanAddr, amount: Uint128, msg: Option<Binary>) -> Self {
+        Self {
+            sender,
+            from,
+            amount,
+            msg,
+        }
+    }
+
+    /// Serializes the message as `{"receive": {...}}`, which is what receiving contracts expect
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = ReceiverHandleMsg::Receive(self);
+        to_binary(&msg)
+    }
+
+    /// Creates the `WasmMsg` that delivers this callback to `contract_addr`
+    pub fn into_cosmos_msg(
+        self,
+        callback_code_hash: String,
+        contract_addr: HumanAddr,
+    ) -> StdResult<CosmosMsg> {
+        let msg = self.into_binary()?;
+        Ok(execute_msg(msg, callback_code_hash, contract_addr))
+    }
+}
+
+/// The callback a contract receives when an `Approve` with `notify` set its allowance, after it
+/// registered its code hash with `RegisterReceive`. `amount` is the new allowance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Snip20AllowanceMsg {
+    pub owner: HumanAddr,
+    pub amount: Uint128,
+}
+
+impl Snip20AllowanceMsg {
+    pub fn new(owner: HumanAddr, amount: Uint128) -> Self {
+        Self { owner, amount }
+    }
+
+    /// Serializes the message as `{"allowance_received": {...}}`
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = ReceiverHandleMsg::AllowanceReceived(self);
+        to_binary(&msg)
+    }
+
+    /// Creates the `WasmMsg` that delivers this callback to `contract_addr`
+    pub fn into_cosmos_msg(
+        self,
+        callback_code_hash: String,
+        contract_addr: HumanAddr,
+    ) -> StdResult<CosmosMsg> {
+        let msg = self.into_binary()?;
+        Ok(execute_msg(msg, callback_code_hash, contract_addr))
+    }
+}
+
+fn execute_msg(msg: Binary, callback_code_hash: String, contract_addr: HumanAddr) -> CosmosMsg {
+    let execute = WasmMsg::Execute {
+        contract_addr,
+        callback_code_hash,
+        msg,
+        send: vec![],
+    };
+    CosmosMsg::Wasm(execute)
+}
+
+/// The handle message shape receiving contracts should include in their own `HandleMsg`.
+/// `AllowanceReceived` is only needed by spenders that ask to be notified of allowances
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverHandleMsg {
+    Receive(Snip20ReceiveMsg),
+    AllowanceReceived(Snip20AllowanceMsg),
+}
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::perform_transfer;
+use crate::hooks::transfer_hook_messages;
+use crate::limits::LIMIT_WINDOW;
+use crate::state::{
+    store_transfer, write_viewing_key, ReadonlyBalances,
+    ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage,
+};
+use crate::viewing_key::ViewingKey;
+
+pub const PREFIX_GUARDIANS: &[u8] = b"guardians";
+pub const PREFIX_RECOVERIES: &[u8] = b"recoveries";
+pub const PREFIX_PENDING_GUARDIANS: &[u8] = b"pending_guardians";
+
+/// Shortest delay guardians can be set with, so the owner has time to cancel a recovery
+pub const MIN_RECOVERY_DELAY: u64 = 7 * LIMIT_WINDOW;
+
+/// `quorum` of `guardians` can recover the account, `delay` seconds after they agreed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Guardians {
+    pub guardians: Vec<CanonicalAddr>,
+    pub quorum: u32,
+    pub delay: u64,
+}
+
+/// A recovery guardians are voting on. Once `quorum` guardians approved, it can be executed
+/// from `executable_at` on, until then the owner can still cancel it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Recovery {
+    pub recovery_address: CanonicalAddr,
+    pub viewing_key: Option<String>,
+    pub approvals: Vec<CanonicalAddr>,
+    pub executable_at: Option<u64>,
+}
+
+/// A change to an account's guardians, which takes effect after the current guardians' delay
+/// so a stolen key can't swap them out before they can act. `None` removes them
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingGuardians {
+    pub guardians: Option<Guardians>,
+    pub effective_at: u64,
+}
+
+fn get_guardians<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<Guardians>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_GUARDIANS, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+fn set_guardians<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    guardians: Option<&Guardians>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_GUARDIANS, storage);
+    match guardians {
+        Some(guardians) => TypedStoreMut::attach(&mut store).store(account.as_slice(), guardians),
+        None => {
+            TypedStoreMut::<Guardians, _>::attach(&mut store).remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+fn get_pending_guardians<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<PendingGuardians>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PENDING_GUARDIANS, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+fn set_pending_guardians<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    pending: Option<&PendingGuardians>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_PENDING_GUARDIANS, storage);
+    match pending {
+        Some(pending) => TypedStoreMut::attach(&mut store).store(account.as_slice(), pending),
+        None => {
+            TypedStoreMut::<PendingGuardians, _>::attach(&mut store).remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Returns `account`'s guardians as of `now`, after applying a pending change that is due
+fn current_guardians<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    now: u64,
+) -> StdResult<Option<Guardians>> {
+    match get_pending_guardians(storage, account)? {
+        Some(pending) if pending.effective_at <= now => {
+            set_pending_guardians(storage, account, None)?;
+            set_guardians(storage, account, pending.guardians.as_ref())?;
+            Ok(pending.guardians)
+        }
+        _ => get_guardians(storage, account),
+    }
+}
+
+fn get_recovery<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<Recovery>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_RECOVERIES, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+fn set_recovery<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    recovery: Option<&Recovery>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_RECOVERIES, storage);
+    match recovery {
+        Some(recovery) => TypedStoreMut::attach(&mut store).store(account.as_slice(), recovery),
+        None => {
+            TypedStoreMut::<Recovery, _>::attach(&mut store).remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Replaces the sender's guardians. An empty list removes them. If the sender already has
+/// guardians, the change takes effect after their delay. Cancels any recovery in progress
+pub fn try_set_guardians<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    guardians: Vec<HumanAddr>,
+    quorum: u32,
+    delay: u64,
+) -> StdResult<HandleResponse> {
+    let owner = &env.message.sender;
+    let now = env.block.time;
+
+    let config = if guardians.is_empty() {
+        None
+    } else {
+        if quorum == 0 || quorum as usize > guardians.len() {
+            return Err(generic_err(
+                "Quorum must be between 1 and the number of guardians",
+            ));
+        }
+        if delay < MIN_RECOVERY_DELAY {
+            return Err(generic_err(format!(
+                "Delay must be at least {} seconds",
+                MIN_RECOVERY_DELAY
+            )));
+        }
+        let guardians = guardians
+            .iter()
+            .map(|guardian| deps.api.canonical_address(guardian))
+            .collect::<StdResult<Vec<_>>>()?;
+        Some(Guardians {
+            guardians,
+            quorum,
+            delay,
+        })
+    };
+
+    let mut logs = vec![log("action", "set_guardians")];
+    match current_guardians(&mut deps.storage, owner, now)? {
+        Some(current) => {
+            let pending = PendingGuardians {
+                guardians: config,
+                effective_at: now.saturating_add(current.delay),
+            };
+            set_pending_guardians(&mut deps.storage, owner, Some(&pending))?;
+            logs.push(log("effective_at", pending.effective_at));
+        }
+        None => {
+            set_pending_guardians(&mut deps.storage, owner, None)?;
+            set_guardians(&mut deps.storage, owner, config.as_ref())?;
+        }
+    }
+
+    set_recovery(&mut deps.storage, owner, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: logs,
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Adds the sending guardian's approval to recovering `account` to `recovery_address`. A
+/// guardian proposing different terms starts the vote over
+pub fn try_approve_recovery<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+    recovery_address: &HumanAddr,
+    viewing_key: Option<String>,
+) -> StdResult<HandleResponse> {
+    let account_raw = deps.api.canonical_address(account)?;
+    let recovery_address_raw = deps.api.canonical_address(recovery_address)?;
+    let guardian = &env.message.sender;
+
+    let config = current_guardians(&mut deps.storage, &account_raw, env.block.time)?
+        .ok_or_else(|| generic_err("The account has no guardians"))?;
+    if !config.guardians.contains(guardian) {
+        return Err(unauthorized());
+    }
+
+    let mut recovery = match get_recovery(&deps.storage, &account_raw)? {
+        Some(recovery)
+            if recovery.recovery_address == recovery_address_raw
+                && recovery.viewing_key == viewing_key =>
+        {
+            recovery
+        }
+        _ => Recovery {
+            recovery_address: recovery_address_raw,
+            viewing_key,
+            approvals: vec![],
+            executable_at: None,
+        },
+    };
+    if !recovery.approvals.contains(guardian) {
+        recovery.approvals.push(guardian.clone());
+    }
+    if recovery.executable_at.is_none() && recovery.approvals.len() >= config.quorum as usize {
+        recovery.executable_at = Some(env.block.time.saturating_add(config.delay));
+    }
+    set_recovery(&mut deps.storage, &account_raw, Some(&recovery))?;
+
+    let mut logs = vec![
+        log("action", "approve_recovery"),
+        log("account", account.as_str()),
+    ];
+    if let Some(executable_at) = recovery.executable_at {
+        logs.push(log("executable_at", executable_at));
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: logs,
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Lets the owner stop a recovery they didn't ask for
+pub fn try_cancel_recovery<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let owner = &env.message.sender;
+    if get_recovery(&deps.storage, owner)?.is_none() {
+        return Err(generic_err("No recovery in progress"));
+    }
+    set_recovery(&mut deps.storage, owner, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "cancel_recovery")],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Moves `account`'s balance to the recovery address and replaces its viewing key, once the
+/// guardians agreed and the delay has passed. Anyone can send this. Counts as the account
+/// moving its balance out, so its transfer policy and limit apply
+pub fn try_execute_recovery<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let account_raw = deps.api.canonical_address(account)?;
+    let recovery = get_recovery(&deps.storage, &account_raw)?
+        .ok_or_else(|| generic_err("No recovery in progress"))?;
+
+    match recovery.executable_at {
+        Some(executable_at) if executable_at <= env.block.time => {}
+        Some(_) => return Err(generic_err("The recovery delay hasn't passed yet")),
+        None => return Err(generic_err("The guardians haven't reached quorum")),
+    }
+
+    set_recovery(&mut deps.storage, &account_raw, None)?;
+
+    let balance = ReadonlyBalances::from_storage(&deps.storage).balance(&account_raw)?;
+    let mut messages = vec![];
+    if balance > 0 {
+        perform_transfer(&mut deps.storage, &account_raw, &recovery.recovery_address, balance)?;
+
+        store_transfer(
+            &mut deps.storage,
+            &account_raw,
+            &recovery.recovery_address,
+            &Uint128(balance),
+            env.block.time,
+        )?;
+
+        messages = transfer_hook_messages(&deps.storage, &deps.api, &account_raw, &recovery.recovery_address, balance)?;
+    }
+
+    if let Some(key) = recovery.viewing_key {
+        write_viewing_key(&mut deps.storage, &account_raw, &ViewingKey(key));
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "execute_recovery"),
+            log("account", account.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
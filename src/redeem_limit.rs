@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, Api, Env, Extern, HandleResponse, Querier, ReadonlyStorage, StdResult,
+    Storage, Uint128,
+};
+
+use crate::contract::assert_admin;
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_REDEEM_LIMIT: &[u8] = b"redeem_limit";
+pub const KEY_REDEEM_LIMIT: &[u8] = b"limit";
+
+/// At most `max_amount` tokens can be withdrawn in total during each `period` seconds. Limits
+/// the damage if tokens are ever minted without backing
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RedeemLimit {
+    pub max_amount: u128,
+    pub period: u64,
+    pub window_start: u64,
+    pub redeemed: u128,
+}
+
+fn get_redeem_limit<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<RedeemLimit>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_REDEEM_LIMIT, storage);
+    TypedStore::attach(&store).may_load(KEY_REDEEM_LIMIT)
+}
+
+fn set_redeem_limit<S: Storage>(storage: &mut S, limit: Option<&RedeemLimit>) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_REDEEM_LIMIT, storage);
+    match limit {
+        Some(limit) => TypedStoreMut::attach(&mut store).store(KEY_REDEEM_LIMIT, limit),
+        None => {
+            store.remove(KEY_REDEEM_LIMIT);
+            Ok(())
+        }
+    }
+}
+
+/// Counts `amount` against the current window, failing if that goes over the limit
+pub fn record_redeem<S: Storage>(storage: &mut S, amount: u128, now: u64) -> StdResult<()> {
+    let mut limit = match get_redeem_limit(storage)? {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+
+    if now >= limit.window_start.saturating_add(limit.period) {
+        // windows are aligned to multiples of the period
+        limit.window_start = now - now % limit.period;
+        limit.redeemed = 0;
+    }
+
+    let redeemed = limit
+        .redeemed
+        .checked_add(amount)
+        .filter(|redeemed| *redeemed <= limit.max_amount)
+        .ok_or_else(|| {
+            generic_err(format!(
+                "Withdrawals are limited to {} every {} seconds, try again at {}",
+                limit.max_amount,
+                limit.period,
+                limit.window_start.saturating_add(limit.period)
+            ))
+        })?;
+    limit.redeemed = redeemed;
+
+    set_redeem_limit(storage, Some(&limit))
+}
+
+/// Admin only. Sets the withdrawal limit, or removes it if `max_amount` is not set. Withdrawals
+/// already made in the current period count against the new limit
+pub fn try_set_redeem_limit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    max_amount: Option<Uint128>,
+    period: u64,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+
+    let limit = match max_amount {
+        Some(max_amount) => {
+            if period == 0 {
+                return Err(generic_err("Period must be at least one second"));
+            }
+            let now = env.block.time;
+            let window_start = now - now % period;
+            let redeemed = match get_redeem_limit(&deps.storage)? {
+                Some(old) if old.window_start.saturating_add(old.period) > now => old.redeemed,
+                _ => 0,
+            };
+            Some(RedeemLimit {
+                max_amount: max_amount.u128(),
+                period,
+                window_start,
+                redeemed,
+            })
+        }
+        None => None,
+    };
+    set_redeem_limit(&mut deps.storage, limit.as_ref())?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_redeem_limit")],
+        data: None,
+    };
+    Ok(res)
+}
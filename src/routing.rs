@@ -0,0 +1,204 @@
+//! `TransferAndCall`: a `Send` whose recipient, typically a router, passes the tokens on from
+//! its `Receive` callback, e.g. to the next hop of a swap. The recipient reports what it didn't
+//! pass on with `ReportUnspent`. Once the callback and everything it triggered ran, the token
+//! calls itself back and returns that to the sender, so no intermediate balance is left behind.
+//! Only the recipient knows what it used, its balance may change for other reasons meanwhile.
+//! If any hop fails the whole transaction is reverted, and the sender keeps everything
+
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, unauthorized, Api, Binary, CanonicalAddr, CosmosMsg, Env, Extern,
+    HandleResponse, HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128, WasmMsg,
+};
+
+use crate::contract::{perform_transfer, try_send};
+use crate::msg::HandleMsg;
+use crate::state::{
+    get_receiver_hash, store_tagged_transfer, ReadonlyVersionedStorage, TypedStore,
+    TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_CALLS: &[u8] = b"calls";
+pub const KEY_CALL_COUNT: &[u8] = b"call_count";
+pub const PREFIX_CALLS_BY_RECIPIENT: &[u8] = b"calls_by_recipient";
+
+/// The reference of what a recipient reported unspent, when it goes back to the sender
+pub const CALL_REFUND_REFERENCE: &str = "call_refund";
+
+/// A `TransferAndCall` waiting for its callback to finish
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Call {
+    pub sender: CanonicalAddr,
+    pub recipient: CanonicalAddr,
+    pub amount: u128,
+    /// What the recipient reported it didn't pass on, nothing until it reports
+    pub unspent: u128,
+}
+
+fn store_call<S: Storage>(storage: &mut S, call: &Call) -> StdResult<u64> {
+    let mut store = VersionedStorage::new(PREFIX_CALLS, storage);
+
+    let id = TypedStore::<u64, _>::attach(&store).may_load(KEY_CALL_COUNT)?.unwrap_or(0) + 1;
+
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), call)?;
+    TypedStoreMut::attach(&mut store).store(KEY_CALL_COUNT, &id)?;
+
+    let mut by_recipient = VersionedStorage::new(PREFIX_CALLS_BY_RECIPIENT, storage);
+    TypedStoreMut::attach(&mut by_recipient).store(call.recipient.as_slice(), &id)?;
+
+    Ok(id)
+}
+
+fn get_call<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<Option<Call>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_CALLS, storage);
+    TypedStore::attach(&store).may_load(&id.to_be_bytes())
+}
+
+fn set_call<S: Storage>(storage: &mut S, id: u64, call: &Call) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_CALLS, storage);
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), call)
+}
+
+/// The id of the call `recipient` is in, if any. A recipient is in at most one call at a time
+fn get_recipient_call<S: ReadonlyStorage>(
+    storage: &S,
+    recipient: &CanonicalAddr,
+) -> StdResult<Option<u64>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_CALLS_BY_RECIPIENT, storage);
+    TypedStore::attach(&store).may_load(recipient.as_slice())
+}
+
+fn remove_call<S: Storage>(storage: &mut S, id: u64, call: &Call) {
+    let mut store = VersionedStorage::new(PREFIX_CALLS, storage);
+    store.remove(&id.to_be_bytes());
+
+    let mut by_recipient = VersionedStorage::new(PREFIX_CALLS_BY_RECIPIENT, storage);
+    by_recipient.remove(call.recipient.as_slice());
+}
+
+/// Sends `amount` to `recipient` like `Send`, and settles the call after the recipient's
+/// callback. `code_hash` is this token's, which the token needs to call itself
+pub fn try_transfer_and_call<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    recipient_code_hash: Option<String>,
+    amount: &Uint128,
+    msg: Option<Binary>,
+    code_hash: String,
+) -> StdResult<HandleResponse> {
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    if recipient_code_hash.is_none()
+        && get_receiver_hash(&deps.storage, &recipient_address_raw).is_none()
+    {
+        return Err(generic_err(
+            "The recipient didn't register to receive callbacks, pass its code hash",
+        ));
+    }
+
+    // otherwise a report couldn't tell which call it's for
+    if get_recipient_call(&deps.storage, &recipient_address_raw)?.is_some() {
+        return Err(generic_err("The recipient is already in a call, wait for it to settle"));
+    }
+
+    let send = try_send(deps, env.clone(), recipient, recipient_code_hash, amount, msg)?;
+
+    let id = store_call(
+        &mut deps.storage,
+        &Call {
+            sender: env.message.sender.clone(),
+            recipient: recipient_address_raw,
+            amount: amount.u128(),
+            unspent: 0,
+        },
+    )?;
+
+    // runs after the callback and whatever messages it returned
+    let mut messages = send.messages;
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: deps.api.human_address(&env.contract.address)?,
+        callback_code_hash: code_hash,
+        msg: to_binary(&HandleMsg::SettleCall { id })?,
+        send: vec![],
+    }));
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "transfer_and_call"),
+            log("sender", deps.api.human_address(&env.message.sender)?.as_str()),
+            log("recipient", recipient.as_str()),
+            log("id", id),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Sent by the recipient of a call from its callback, or a message the callback started, for
+/// the part of `amount` it didn't pass on. Reporting again replaces the earlier report
+pub fn try_report_unspent<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let id = get_recipient_call(&deps.storage, &env.message.sender)?
+        .ok_or_else(|| generic_err("The sender isn't the recipient of a call"))?;
+    let mut call = get_call(&deps.storage, id)?
+        .ok_or_else(|| generic_err(format!("No call with id {}", id)))?;
+    if amount.u128() > call.amount {
+        return Err(generic_err(format!(
+            "Can't report more than the call's amount of {}",
+            call.amount
+        )));
+    }
+
+    call.unspent = amount.u128();
+    set_call(&mut deps.storage, id, &call)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "report_unspent"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Only the token itself sends this. Returns what the recipient reported unspent to the sender.
+/// Fails, and with it the whole transaction, if the recipient no longer has it
+pub fn try_settle_call<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    if env.message.sender != env.contract.address {
+        return Err(unauthorized());
+    }
+    let call = get_call(&deps.storage, id)?
+        .ok_or_else(|| generic_err(format!("No call with id {}", id)))?;
+    remove_call(&mut deps.storage, id, &call);
+
+    if call.unspent > 0 {
+        perform_transfer(&mut deps.storage, &call.recipient, &call.sender, call.unspent)?;
+        store_tagged_transfer(
+            &mut deps.storage,
+            &call.recipient,
+            &call.sender,
+            &Uint128(call.unspent),
+            env.block.time,
+            Some(CALL_REFUND_REFERENCE),
+        )?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "settle_call"),
+            log("id", id),
+            log("refunded", call.unspent),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
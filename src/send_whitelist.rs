@@ -0,0 +1,102 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage,
+};
+
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_SEND_WHITELISTS: &[u8] = b"send_whitelists";
+
+/// The contracts an account's `Send`s can go to. `None` if the account didn't restrict them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SendWhitelistResponse {
+    pub contracts: Option<Vec<HumanAddr>>,
+}
+
+fn get_whitelist<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Option<Vec<CanonicalAddr>>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_SEND_WHITELISTS, storage);
+    TypedStore::attach(&store).may_load(account.as_slice())
+}
+
+fn set_whitelist<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    contracts: Option<&Vec<CanonicalAddr>>,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_SEND_WHITELISTS, storage);
+    match contracts {
+        Some(contracts) => TypedStoreMut::attach(&mut store).store(account.as_slice(), contracts),
+        None => {
+            TypedStoreMut::<Vec<CanonicalAddr>, _>::attach(&mut store).remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Rejects a `Send` from `sender` to a contract it didn't whitelist, if it keeps a whitelist
+pub fn check_send_whitelist<S: ReadonlyStorage>(
+    storage: &S,
+    sender: &CanonicalAddr,
+    recipient: &CanonicalAddr,
+) -> StdResult<()> {
+    match get_whitelist(storage, sender)? {
+        Some(contracts) if !contracts.contains(recipient) => Err(generic_err(
+            "The recipient isn't on the sender's send whitelist",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Limits the sender's `Send`s to `contracts`, or lifts the limit if `contracts` is not set. An
+/// empty list blocks every `Send`
+pub fn try_set_send_whitelist<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contracts: Option<Vec<HumanAddr>>,
+) -> StdResult<HandleResponse> {
+    let contracts_raw = match &contracts {
+        Some(contracts) => Some(
+            contracts
+                .iter()
+                .map(|contract| deps.api.canonical_address(contract))
+                .collect::<StdResult<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+    set_whitelist(&mut deps.storage, &env.message.sender, contracts_raw.as_ref())?;
+
+    let mut logs = vec![log("action", "set_send_whitelist")];
+    if let Some(contracts) = &contracts {
+        logs.push(log("contracts", contracts.len()));
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: logs,
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_send_whitelist<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let account = deps.api.canonical_address(account)?;
+    let contracts = match get_whitelist(&deps.storage, &account)? {
+        Some(contracts) => Some(
+            contracts
+                .iter()
+                .map(|contract| deps.api.human_address(contract))
+                .collect::<StdResult<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+    to_binary(&SendWhitelistResponse { contracts })
+}
@@ -0,0 +1,213 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::perform_transfer;
+use crate::hooks::transfer_hook_messages;
+use crate::incoming::check_incoming;
+use crate::inheritance::record_activity;
+use crate::permit::{amino_sign_bytes, PermitSignature};
+use crate::state::{
+    store_transfer, update_allowance, ReadonlyVersionedStorage, TypedStore, TypedStoreMut,
+    VersionedStorage,
+};
+use crate::timelock::release_unlocked;
+
+pub const PREFIX_SIGNED_NONCES: &[u8] = b"signed_nonces";
+
+/// A transfer the owner signed offline, so that someone else can submit it and pay the gas
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SignedTransfer {
+    pub recipient: HumanAddr,
+    pub amount: Uint128,
+}
+
+/// The nonce the account's next signed transfer or allowance permit has to carry
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NonceResponse {
+    pub nonce: u64,
+}
+
+// What the owner signs, as the value of an amino message of type "execute_signed". Fields are
+// in alphabetical order because amino JSON is signed with sorted keys, and the nonce is a string
+// because amino encodes 64 bit integers as strings
+#[derive(Serialize)]
+struct SignedTransferParams<'a> {
+    amount: Uint128,
+    contract: &'a HumanAddr,
+    nonce: String,
+    recipient: &'a HumanAddr,
+}
+
+// Signed as the value of an amino message of type "permit_allowance". The nonce isn't sent
+// along with the permit, it's always the owner's next one
+#[derive(Serialize)]
+struct AllowancePermitParams<'a> {
+    amount: Uint128,
+    contract: &'a HumanAddr,
+    deadline: String,
+    nonce: String,
+    owner: &'a HumanAddr,
+    spender: &'a HumanAddr,
+}
+
+/// The nonce `owner`'s next signed message has to carry
+pub fn get_nonce<S: ReadonlyStorage>(storage: &S, owner: &CanonicalAddr) -> StdResult<u64> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_SIGNED_NONCES, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(owner.as_slice())?
+        .unwrap_or(0))
+}
+
+/// Fails unless `nonce` is `owner`'s next one, and moves on to the one after. A signed message
+/// can't be replayed, and messages signed in advance execute in order
+fn use_nonce<S: Storage>(storage: &mut S, owner: &CanonicalAddr, nonce: u64) -> StdResult<()> {
+    let expected = get_nonce(storage, owner)?;
+    if nonce != expected {
+        return Err(generic_err(format!(
+            "Wrong nonce: expected={}, got={}",
+            expected, nonce
+        )));
+    }
+    let next = nonce
+        .checked_add(1)
+        .ok_or_else(|| generic_err("Nonce overflow"))?;
+
+    let mut store = VersionedStorage::new(PREFIX_SIGNED_NONCES, storage);
+    TypedStoreMut::attach(&mut store).store(owner.as_slice(), &next)
+}
+
+/// Makes the transfer the owner signed, whoever sends it. The owner is the account of the key
+/// that signed, and the signature covers this token's address and the chain, so it can't be
+/// replayed elsewhere
+pub fn try_execute_signed<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    transfer: SignedTransfer,
+    nonce: u64,
+    signature: PermitSignature,
+) -> StdResult<HandleResponse> {
+    let contract = deps.api.human_address(&env.contract.address)?;
+    let params = SignedTransferParams {
+        amount: transfer.amount,
+        contract: &contract,
+        nonce: nonce.to_string(),
+        recipient: &transfer.recipient,
+    };
+    let signed_bytes = amino_sign_bytes(&env.block.chain_id, "execute_signed", &params)?;
+    let owner_address_raw = signature.verify(signed_bytes.as_slice())?;
+
+    use_nonce(&mut deps.storage, &owner_address_raw, nonce)?;
+    // `handle` only does this for the sender, which here is whoever relays the message
+    release_unlocked(&mut deps.storage, &owner_address_raw, env.block.time)?;
+    record_activity(&mut deps.storage, &owner_address_raw, env.block.time)?;
+
+    let recipient_address_raw = deps.api.canonical_address(&transfer.recipient)?;
+    let amount_raw = transfer.amount.u128();
+    check_incoming(&deps.storage, &owner_address_raw, &recipient_address_raw)?;
+    perform_transfer(
+        &mut deps.storage,
+        &owner_address_raw,
+        &recipient_address_raw,
+        amount_raw,
+    )?;
+
+    store_transfer(
+        &mut deps.storage,
+        &owner_address_raw,
+        &recipient_address_raw,
+        &transfer.amount,
+        env.block.time,
+    )?;
+
+    let messages = transfer_hook_messages(
+        &deps.storage,
+        &deps.api,
+        &owner_address_raw,
+        &recipient_address_raw,
+        amount_raw,
+    )?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "execute_signed"),
+            log(
+                "owner",
+                deps.api.human_address(&owner_address_raw)?.as_str(),
+            ),
+            log("recipient", transfer.recipient.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Sets the allowance `owner` signed for `spender` offline, so the spender can set it and use
+/// it in one transaction. Fails after `deadline` (block time, in seconds), and uses up the
+/// owner's next nonce so the permit can't be replayed once the allowance was spent
+pub fn try_permit_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    spender: &HumanAddr,
+    amount: Uint128,
+    deadline: u64,
+    signature: PermitSignature,
+) -> StdResult<HandleResponse> {
+    if env.block.time > deadline {
+        return Err(generic_err("Allowance permit expired"));
+    }
+
+    let owner_address_raw = deps.api.canonical_address(owner)?;
+    let nonce = get_nonce(&deps.storage, &owner_address_raw)?;
+    let contract = deps.api.human_address(&env.contract.address)?;
+    let params = AllowancePermitParams {
+        amount,
+        contract: &contract,
+        deadline: deadline.to_string(),
+        nonce: nonce.to_string(),
+        owner,
+        spender,
+    };
+    let signed_bytes = amino_sign_bytes(&env.block.chain_id, "permit_allowance", &params)?;
+    if signature.verify(signed_bytes.as_slice())? != owner_address_raw {
+        return Err(generic_err("Allowance permit wasn't signed by the owner"));
+    }
+    use_nonce(&mut deps.storage, &owner_address_raw, nonce)?;
+
+    let spender_address_raw = deps.api.canonical_address(spender)?;
+    update_allowance(
+        &mut deps.storage,
+        &owner_address_raw,
+        &spender_address_raw,
+        |_| Ok(amount.u128()),
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "permit_allowance"),
+            log("owner", owner.as_str()),
+            log("spender", spender.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Public, so whoever relays signed messages can look up the nonce to sign with. It only tells
+/// how many signed messages an account made here
+pub fn query_nonce<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(address)?;
+    to_binary(&NonceResponse {
+        nonce: get_nonce(&deps.storage, &address)?,
+    })
+}
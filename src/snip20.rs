@@ -0,0 +1,82 @@
+use cosmwasm_std::{to_binary, Binary, CosmosMsg, HumanAddr, StdResult, Uint128, WasmMsg};
+
+use crate::msg::HandleMsg;
+use crate::utils::space_pad;
+
+/// Block size used to pad messages when the caller doesn't have a preference
+pub const DEFAULT_BLOCK_SIZE: usize = 256;
+
+/// Creates a `Transfer` message for the token at `contract_addr`
+pub fn transfer_msg(
+    recipient: HumanAddr,
+    amount: Uint128,
+    block_size: usize,
+    callback_code_hash: String,
+    contract_addr: HumanAddr,
+) -> StdResult<CosmosMsg> {
+    let msg = HandleMsg::Transfer { recipient, amount };
+    to_cosmos_msg(&msg, block_size, callback_code_hash, contract_addr)
+}
+
+/// Creates a `Send` message for the token at `contract_addr`. With `recipient_code_hash` the
+/// recipient gets its callback even if it didn't register with the token
+pub fn send_msg(
+    recipient: HumanAddr,
+    recipient_code_hash: Option<String>,
+    amount: Uint128,
+    msg: Option<Binary>,
+    block_size: usize,
+    callback_code_hash: String,
+    contract_addr: HumanAddr,
+) -> StdResult<CosmosMsg> {
+    let msg = HandleMsg::Send {
+        recipient,
+        recipient_code_hash,
+        amount,
+        msg,
+    };
+    to_cosmos_msg(&msg, block_size, callback_code_hash, contract_addr)
+}
+
+/// Creates a `RegisterReceive` message, so the token at `contract_addr` knows which code hash to
+/// use when calling back the sending contract
+pub fn register_receive_msg(
+    your_contracts_code_hash: String,
+    block_size: usize,
+    callback_code_hash: String,
+    contract_addr: HumanAddr,
+) -> StdResult<CosmosMsg> {
+    let msg = HandleMsg::RegisterReceive {
+        code_hash: your_contracts_code_hash,
+    };
+    to_cosmos_msg(&msg, block_size, callback_code_hash, contract_addr)
+}
+
+/// Creates a `SetViewingKey` message for the token at `contract_addr`
+pub fn set_viewing_key_msg(
+    key: String,
+    block_size: usize,
+    callback_code_hash: String,
+    contract_addr: HumanAddr,
+) -> StdResult<CosmosMsg> {
+    let msg = HandleMsg::SetViewingKey { key };
+    to_cosmos_msg(&msg, block_size, callback_code_hash, contract_addr)
+}
+
+fn to_cosmos_msg(
+    msg: &HandleMsg,
+    block_size: usize,
+    callback_code_hash: String,
+    contract_addr: HumanAddr,
+) -> StdResult<CosmosMsg> {
+    let mut msg = to_binary(msg)?;
+    space_pad(&mut msg.0, block_size);
+
+    let execute = WasmMsg::Execute {
+        contract_addr,
+        callback_code_hash,
+        msg,
+        send: vec![],
+    };
+    Ok(CosmosMsg::Wasm(execute))
+}
@@ -0,0 +1,458 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{to_binary, Api, Binary, Extern, HumanAddr, Querier, StdResult, Storage, Uint128};
+#[cfg(feature = "staking")]
+use cosmwasm_std::{
+    generic_err, log, BankMsg, CanonicalAddr, Coin, CosmosMsg, Env, HandleResponse,
+    ReadonlyStorage, StakingMsg,
+};
+
+use crate::state::ReadonlyConfig;
+#[cfg(feature = "staking")]
+use crate::state::{ReadonlyVersionedStorage, TypedStore, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_STAKING: &[u8] = b"staking";
+pub const KEY_STAKING_STATE: &[u8] = b"state";
+pub const PREFIX_UNBONDING: &[u8] = b"unbonding";
+
+#[cfg(feature = "staking")]
+const STAKING_DENOM: &str = "uscrt";
+
+/// Turns the token into a staking derivative: deposits are delegated to `validators`, and
+/// withdrawals are paid out `unbonding_period` seconds after they were requested
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakingInitConfig {
+    pub validators: Vec<HumanAddr>,
+    pub unbonding_period: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Delegation {
+    pub validator: HumanAddr,
+    pub amount: u128,
+}
+
+/// `bonded` is what all tokens are worth together, it grows as rewards are restaked.
+/// `unbonding` is owed to withdrawals that haven't been claimed yet
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StakingState {
+    pub delegations: Vec<Delegation>,
+    pub unbonding_period: u64,
+    pub bonded: u128,
+    pub unbonding: u128,
+}
+
+#[cfg(feature = "staking")]
+impl StakingState {
+    /// How many tokens a deposit of `uscrt` is worth
+    fn tokens_for_uscrt(&self, uscrt: u128, total_supply: u128) -> StdResult<u128> {
+        if total_supply == 0 || self.bonded == 0 {
+            return Ok(uscrt);
+        }
+        uscrt
+            .checked_mul(total_supply)
+            .map(|product| product / self.bonded)
+            .ok_or_else(|| generic_err("Exchange rate overflow"))
+    }
+
+    /// How much uscrt `tokens` can be withdrawn for
+    fn uscrt_for_tokens(&self, tokens: u128, total_supply: u128) -> StdResult<u128> {
+        if total_supply == 0 {
+            return Ok(0);
+        }
+        tokens
+            .checked_mul(self.bonded)
+            .map(|product| product / total_supply)
+            .ok_or_else(|| generic_err("Exchange rate overflow"))
+    }
+
+    /// Delegates `uscrt` to the validator that has the least delegated so far
+    fn delegate(&mut self, uscrt: u128) -> StdResult<Vec<CosmosMsg>> {
+        if uscrt == 0 {
+            return Ok(vec![]);
+        }
+
+        let delegation = self
+            .delegations
+            .iter_mut()
+            .min_by_key(|delegation| delegation.amount)
+            .ok_or_else(|| generic_err("No validators configured"))?;
+        delegation.amount = delegation
+            .amount
+            .checked_add(uscrt)
+            .ok_or_else(|| generic_err("Delegation overflow"))?;
+        self.bonded = self
+            .bonded
+            .checked_add(uscrt)
+            .ok_or_else(|| generic_err("Bonded amount overflow"))?;
+
+        Ok(vec![CosmosMsg::Staking(StakingMsg::Delegate {
+            validator: delegation.validator.clone(),
+            amount: Coin {
+                denom: STAKING_DENOM.to_string(),
+                amount: Uint128(uscrt),
+            },
+        })])
+    }
+
+    /// Undelegates `uscrt`, taking it from the validators that have the most delegated first
+    fn undelegate(&mut self, uscrt: u128) -> StdResult<Vec<CosmosMsg>> {
+        let mut messages = vec![];
+        let mut remaining = uscrt;
+        while remaining > 0 {
+            let delegation = self
+                .delegations
+                .iter_mut()
+                .max_by_key(|delegation| delegation.amount)
+                .filter(|delegation| delegation.amount > 0)
+                .ok_or_else(|| generic_err("Not enough delegated to unbond"))?;
+
+            let amount = remaining.min(delegation.amount);
+            delegation.amount -= amount;
+            remaining -= amount;
+
+            messages.push(CosmosMsg::Staking(StakingMsg::Undelegate {
+                validator: delegation.validator.clone(),
+                amount: Coin {
+                    denom: STAKING_DENOM.to_string(),
+                    amount: Uint128(amount),
+                },
+            }));
+        }
+
+        self.bonded = self
+            .bonded
+            .checked_sub(uscrt)
+            .ok_or_else(|| generic_err("Unbonding more than is bonded"))?;
+        self.unbonding = self
+            .unbonding
+            .checked_add(uscrt)
+            .ok_or_else(|| generic_err("Unbonding amount overflow"))?;
+
+        Ok(messages)
+    }
+}
+
+/// A withdrawal that can be claimed from `release_at` (block time, in seconds) on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Unbonding {
+    pub amount: u128,
+    pub release_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingEntry {
+    pub amount: Uint128,
+    pub release_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingsResponse {
+    pub unbondings: Vec<UnbondingEntry>,
+}
+
+/// `tokens` tokens can currently be withdrawn for `uscrt`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateResponse {
+    pub uscrt: Uint128,
+    pub tokens: Uint128,
+}
+
+#[cfg(feature = "staking")]
+pub fn init_staking<S: Storage>(storage: &mut S, config: StakingInitConfig) -> StdResult<()> {
+    if config.validators.is_empty() {
+        return Err(generic_err("Staking mode needs at least one validator"));
+    }
+
+    let state = StakingState {
+        delegations: config
+            .validators
+            .into_iter()
+            .map(|validator| Delegation {
+                validator,
+                amount: 0,
+            })
+            .collect(),
+        unbonding_period: config.unbonding_period,
+        bonded: 0,
+        unbonding: 0,
+    };
+    set_staking_state(storage, &state)
+}
+
+/// Returns `None` if the token isn't in staking mode
+#[cfg(feature = "staking")]
+pub fn get_staking_state<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<StakingState>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_STAKING, storage);
+    TypedStore::attach(&store).may_load(KEY_STAKING_STATE)
+}
+
+#[cfg(feature = "staking")]
+fn set_staking_state<S: Storage>(storage: &mut S, state: &StakingState) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_STAKING, storage);
+    TypedStoreMut::attach(&mut store).store(KEY_STAKING_STATE, state)
+}
+
+#[cfg(feature = "staking")]
+fn get_unbondings<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Vec<Unbonding>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_UNBONDING, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(account.as_slice())?
+        .unwrap_or_default())
+}
+
+#[cfg(feature = "staking")]
+fn set_unbondings<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    unbondings: &[Unbonding],
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_UNBONDING, storage);
+    if unbondings.is_empty() {
+        TypedStoreMut::<Vec<Unbonding>, _>::attach(&mut store).remove(account.as_slice());
+        Ok(())
+    } else {
+        TypedStoreMut::attach(&mut store).store(account.as_slice(), &unbondings.to_vec())
+    }
+}
+
+/// Delegates a deposit of `uscrt`. Returns how many tokens to mint for it and the delegation
+/// messages, or `None` if the token isn't in staking mode
+#[cfg(feature = "staking")]
+pub fn bond<S: Storage>(
+    storage: &mut S,
+    uscrt: u128,
+    total_supply: u128,
+) -> StdResult<Option<(u128, Vec<CosmosMsg>)>> {
+    let mut state = match get_staking_state(storage)? {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+
+    let tokens = state.tokens_for_uscrt(uscrt, total_supply)?;
+    let messages = state.delegate(uscrt)?;
+    set_staking_state(storage, &state)?;
+
+    Ok(Some((tokens, messages)))
+}
+
+/// Undelegates what `tokens` are worth and queues it for `owner`. `total_supply` must still
+/// include the withdrawn tokens. Returns the undelegation messages, or `None` if the token isn't
+/// in staking mode
+#[cfg(feature = "staking")]
+pub fn unbond<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    tokens: u128,
+    total_supply: u128,
+    now: u64,
+) -> StdResult<Option<Vec<CosmosMsg>>> {
+    let mut state = match get_staking_state(storage)? {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+
+    let uscrt = state.uscrt_for_tokens(tokens, total_supply)?;
+    if uscrt == 0 {
+        return Err(generic_err("Withdrawn amount is worth less than 1uscrt"));
+    }
+    let messages = state.undelegate(uscrt)?;
+    set_staking_state(storage, &state)?;
+
+    let mut unbondings = get_unbondings(storage, owner)?;
+    unbondings.push(Unbonding {
+        amount: uscrt,
+        release_at: now.saturating_add(state.unbonding_period),
+    });
+    set_unbondings(storage, owner, &unbondings)?;
+
+    Ok(Some(messages))
+}
+
+/// Pays out the sender's withdrawals whose unbonding period is over
+#[cfg(feature = "staking")]
+pub fn try_claim_unbonded<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let mut state = get_staking_state(&deps.storage)?
+        .ok_or_else(|| generic_err("Staking mode is not enabled"))?;
+
+    let owner_address_raw = &env.message.sender;
+    let (released, pending): (Vec<_>, Vec<_>) = get_unbondings(&deps.storage, owner_address_raw)?
+        .into_iter()
+        .partition(|unbonding| unbonding.release_at <= env.block.time);
+    let amount = released
+        .iter()
+        .try_fold(0u128, |sum, unbonding| sum.checked_add(unbonding.amount))
+        .ok_or_else(|| generic_err("Unbonded amount overflow"))?;
+    if amount == 0 {
+        return Err(generic_err("Nothing has finished unbonding yet"));
+    }
+    set_unbondings(&mut deps.storage, owner_address_raw, &pending)?;
+
+    state.unbonding = state
+        .unbonding
+        .checked_sub(amount)
+        .ok_or_else(|| generic_err("Claiming more than is unbonding"))?;
+    set_staking_state(&mut deps.storage, &state)?;
+
+    let owner = deps.api.human_address(owner_address_raw)?;
+    let res = HandleResponse {
+        messages: vec![CosmosMsg::Bank(BankMsg::Send {
+            from_address: deps.api.human_address(&env.contract.address)?,
+            to_address: owner.clone(),
+            amount: vec![Coin {
+                denom: STAKING_DENOM.to_string(),
+                amount: Uint128(amount),
+            }],
+        })],
+        log: vec![
+            log("action", "claim_unbonded"),
+            log("account", owner.as_str()),
+            log("amount", &amount.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Restakes the rewards collected so far and withdraws the ones accrued since from every
+/// validator, so they get restaked the next time. Anyone can call this
+#[cfg(feature = "staking")]
+pub fn try_collect_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let mut state = get_staking_state(&deps.storage)?
+        .ok_or_else(|| generic_err("Staking mode is not enabled"))?;
+
+    let contract_addr = deps.api.human_address(&env.contract.address)?;
+    let liquid = deps
+        .querier
+        .query_balance(contract_addr, STAKING_DENOM)?
+        .amount
+        .u128();
+    // whatever isn't owed to withdrawals is rewards. Unbonding funds that haven't arrived yet
+    // make this an underestimate, the difference is restaked on a later call
+    let rewards = liquid.saturating_sub(state.unbonding);
+
+    let mut messages = state.delegate(rewards)?;
+    set_staking_state(&mut deps.storage, &state)?;
+
+    for delegation in state.delegations.iter().filter(|delegation| delegation.amount > 0) {
+        messages.push(CosmosMsg::Staking(StakingMsg::Withdraw {
+            validator: delegation.validator.clone(),
+            recipient: None,
+        }));
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "collect_rewards"),
+            log("restaked", &rewards.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_exchange_rate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let total_supply = ReadonlyConfig::from_storage(&deps.storage).total_supply()?;
+    let uscrt = match get_staking_state(&deps.storage)? {
+        Some(state) => state.bonded,
+        None => total_supply,
+    };
+
+    to_binary(&ExchangeRateResponse {
+        uscrt: Uint128(uscrt),
+        tokens: Uint128(total_supply),
+    })
+}
+
+#[cfg(feature = "staking")]
+pub fn query_unbondings<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+
+    let unbondings = get_unbondings(&deps.storage, &address)?
+        .into_iter()
+        .map(|unbonding| UnbondingEntry {
+            amount: Uint128(unbonding.amount),
+            release_at: unbonding.release_at,
+        })
+        .collect();
+
+    to_binary(&UnbondingsResponse { unbondings })
+}
+
+/// Builds without the `staking` feature can't be created in staking mode, so deposits and
+/// withdrawals always go through the reserves
+#[cfg(not(feature = "staking"))]
+mod disabled {
+    use cosmwasm_std::{
+        generic_err, to_binary, Api, Binary, CanonicalAddr, CosmosMsg, Env, Extern,
+        HandleResponse, HumanAddr, Querier, ReadonlyStorage, StdResult, Storage,
+    };
+
+    use super::{StakingInitConfig, StakingState, UnbondingsResponse};
+
+    pub fn init_staking<S: Storage>(_storage: &mut S, _config: StakingInitConfig) -> StdResult<()> {
+        Err(generic_err("Staking mode is not supported by this build of the token"))
+    }
+
+    pub fn get_staking_state<S: ReadonlyStorage>(_storage: &S) -> StdResult<Option<StakingState>> {
+        Ok(None)
+    }
+
+    pub fn bond<S: Storage>(
+        _storage: &mut S,
+        _uscrt: u128,
+        _total_supply: u128,
+    ) -> StdResult<Option<(u128, Vec<CosmosMsg>)>> {
+        Ok(None)
+    }
+
+    pub fn unbond<S: Storage>(
+        _storage: &mut S,
+        _owner: &CanonicalAddr,
+        _tokens: u128,
+        _total_supply: u128,
+        _now: u64,
+    ) -> StdResult<Option<Vec<CosmosMsg>>> {
+        Ok(None)
+    }
+
+    pub fn try_claim_unbonded<S: Storage, A: Api, Q: Querier>(
+        _deps: &mut Extern<S, A, Q>,
+        _env: Env,
+    ) -> StdResult<HandleResponse> {
+        Err(generic_err("Staking mode is not supported by this build of the token"))
+    }
+
+    pub fn try_collect_rewards<S: Storage, A: Api, Q: Querier>(
+        _deps: &mut Extern<S, A, Q>,
+        _env: Env,
+    ) -> StdResult<HandleResponse> {
+        Err(generic_err("Staking mode is not supported by this build of the token"))
+    }
+
+    pub fn query_unbondings<S: Storage, A: Api, Q: Querier>(
+        _deps: &Extern<S, A, Q>,
+        _account: &HumanAddr,
+    ) -> StdResult<Binary> {
+        to_binary(&UnbondingsResponse { unbondings: vec![] })
+    }
+}
+
+#[cfg(not(feature = "staking"))]
+pub use disabled::*;
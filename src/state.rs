@@ -1,22 +1,237 @@
 use bincode2;
-use serde::{Deserialize, Serialize};
-use cosmwasm_std::{Api, CanonicalAddr, Storage, StdResult, StdError, HumanAddr, Coin, Uint128, ReadonlyStorage};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use cosmwasm_std::{Api, CanonicalAddr, Storage, StdResult, StdError, HumanAddr, Coin, Uint128, ReadonlyStorage, generic_err};
+#[cfg(feature = "debug-print")]
+use cosmwasm_std::debug_print;
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+#[cfg(feature = "debug-print")]
+use crate::rand::sha_256;
 use crate::utils::ConstLenStr;
+use crate::viewing_key::ViewingKey;
 use std::path::Display;
 use core::fmt;
 use serde::export::Formatter;
+use std::convert::TryInto;
+use std::marker::PhantomData;
 
 pub static CONFIG_KEY: &[u8] = b"config";
+pub const PREFIX_BALANCES: &[u8] = b"balances";
+pub const PREFIX_ALLOWANCES: &[u8] = b"allowances";
+/// Balances and allowances are read and written by every transfer, so they are stored directly
+/// under these one byte prefixes instead of in namespaces. Namespaced keys always start with a
+/// zero byte, so these can't collide with them
+pub const KEY_PREFIX_BALANCE: &[u8] = b"b";
+pub const KEY_PREFIX_ALLOWANCE: &[u8] = b"a";
+pub const PREFIX_VIEW_KEY: &[u8] = b"viewingkey";
+pub const KEY_CONSTANTS: &[u8] = b"constants";
+pub const KEY_SYMBOL: &[u8] = b"symbol";
+pub const KEY_TOTAL_SUPPLY: &[u8] = b"total_supply";
+#[cfg(feature = "debug-supply")]
+pub const KEY_BALANCE_DELTA: &[u8] = b"balance_delta";
 pub const PREFIX_TXS: &[u8] = b"transfers";
+pub const PREFIX_PENDING: &[u8] = b"pending";
+pub const KEY_PENDING_COUNT: &[u8] = b"pending_count";
+pub const PREFIX_CLAIMS: &[u8] = b"claims";
+pub const PREFIX_RECEIVERS: &[u8] = b"receivers";
+pub const KEY_HISTORY_LIMIT: &[u8] = b"history_limit";
+pub const KEY_ADMIN: &[u8] = b"admin";
+pub const KEY_CONTRACT_ADDRESS: &[u8] = b"contract_address";
+pub const KEY_TREASURY: &[u8] = b"treasury";
+pub const KEY_SUPPORTED_DENOMS: &[u8] = b"supported_denoms";
+pub const KEY_REDEEM_ONLY: &[u8] = b"redeem_only";
+pub const PREFIX_RESERVES: &[u8] = b"reserves";
+pub const PREFIX_SENT_TXS: &[u8] = b"sent_transfers";
+pub const PREFIX_RECEIVED_TXS: &[u8] = b"received_transfers";
+pub const PREFIX_ACCOUNT_STATS: &[u8] = b"account_stats";
+pub const PREFIX_ACCOUNT_TOTALS: &[u8] = b"account_totals";
+pub const KEY_REWARD_PER_TOKEN: &[u8] = b"reward_per_token";
+pub const PREFIX_REWARDS: &[u8] = b"rewards";
+pub const PREFIX_TX_REFERENCES: &[u8] = b"transfer_references";
+pub const PREFIX_TX_TIMES: &[u8] = b"transfer_times";
+pub const PREFIX_TX_MEMOS: &[u8] = b"transfer_memos";
+pub const PREFIX_TX_SPENDERS: &[u8] = b"transfer_spenders";
+pub const PREFIX_TX_HEIGHTS: &[u8] = b"transfer_heights";
+pub const PREFIX_TX_DENOMS: &[u8] = b"transfer_denoms";
+pub const PREFIX_ALLOWANCE_SPENDERS: &[u8] = b"allowance_spenders";
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// The references burns, claimed rewards, deposits and redeems are tagged with in the history
+pub const BURN_REFERENCE: &str = "burn";
+pub const REWARDS_REFERENCE: &str = "rewards";
+pub const DEPOSIT_REFERENCE: &str = "deposit";
+pub const REDEEM_REFERENCE: &str = "redeem";
+
+/// The only denom instances created before multi-denom support could wrap
+pub const DEFAULT_DENOM: &str = "uscrt";
+
+/// `reward_per_token` is scaled up by this so that small distributions over a large supply
+/// don't round down to nothing
+pub const REWARD_SCALE: u128 = 1_000_000_000_000;
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct Constants {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// A transfer as returned by the `Transfers` query
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub struct Tx {
     pub sender: HumanAddr,
     pub receiver: HumanAddr,
-    pub coins: Coin
+    pub coins: Coin,
+    /// Set when the transfer paid a payment request, to the other chain's transaction hash or
+    /// recipient for bridge transfers, and to "pull" for pull payments
+    pub reference: Option<String>,
+    /// Block time of the transfer, not set for transfers recorded before times were
+    pub timestamp: Option<u64>,
+    /// What the sender wrote about the transfer, for batch transfers
+    pub memo: Option<String>,
+    /// Who moved the funds, for transfers made with an allowance
+    pub spender: Option<HumanAddr>,
+    /// Whether the account whose history this is sent or received the transfer
+    pub direction: TxDirection,
+    /// Block height of the transfer, only recorded for burns and mints, which include deposits
+    /// and redeems
+    pub block_height: Option<u64>,
+    /// The native denom deposited or redeemed
+    pub denom: Option<String>,
+}
+
+/// A transfer as it was stored in an account's history before records became compact. What was
+/// added to transfers before that is stored next to them, keyed by their position
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StoredTx {
+    sender: HumanAddr,
+    receiver: HumanAddr,
+    coins: Coin,
+}
+
+impl StoredTx {
+    fn involves(&self, address: &HumanAddr) -> bool {
+        self.sender == *address || self.receiver == *address
+    }
+
+    /// A transfer to oneself is listed twice in the history, both times as sent
+    fn direction_for(&self, address: &HumanAddr) -> TxDirection {
+        if self.sender == *address {
+            TxDirection::Sent
+        } else {
+            TxDirection::Received
+        }
+    }
+
+    fn into_tx(self, reference: Option<String>, timestamp: Option<u64>, direction: TxDirection) -> Tx {
+        Tx {
+            sender: self.sender,
+            receiver: self.receiver,
+            coins: self.coins,
+            reference,
+            timestamp,
+            memo: None,
+            spender: None,
+            direction,
+            block_height: None,
+            denom: None,
+        }
+    }
+}
+
+/// A transfer as it is stored in an account's history: canonical addresses and raw integers,
+/// only made human readable at query time. The symbol isn't stored, it is the token's. Every
+/// field has a fixed width, so all records take the same space and their size tells nothing
+/// about the addresses in them
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CompactTx {
+    sender: [u8; ADDRESS_WIDTH],
+    sender_len: u8,
+    receiver: [u8; ADDRESS_WIDTH],
+    receiver_len: u8,
+    amount: u128,
+    time: u64,
+}
+
+/// The length of canonical addresses on Secret Network. Compact transfers store addresses
+/// zero-padded to it, next to their actual length
+const ADDRESS_WIDTH: usize = 20;
+
+fn fixed_width_address(address: &CanonicalAddr) -> StdResult<([u8; ADDRESS_WIDTH], u8)> {
+    let bytes = address.as_slice();
+    if bytes.len() > ADDRESS_WIDTH {
+        return Err(generic_err(format!(
+            "Addresses longer than {} bytes can't be recorded",
+            ADDRESS_WIDTH
+        )));
+    }
+    let mut fixed = [0u8; ADDRESS_WIDTH];
+    fixed[..bytes.len()].copy_from_slice(bytes);
+    Ok((fixed, bytes.len() as u8))
+}
+
+impl CompactTx {
+    fn new(
+        sender: &CanonicalAddr,
+        receiver: &CanonicalAddr,
+        amount: &Uint128,
+        time: u64,
+    ) -> StdResult<Self> {
+        let (sender, sender_len) = fixed_width_address(sender)?;
+        let (receiver, receiver_len) = fixed_width_address(receiver)?;
+        Ok(Self {
+            sender,
+            sender_len,
+            receiver,
+            receiver_len,
+            amount: amount.u128(),
+            time,
+        })
+    }
+
+    fn sender(&self) -> &[u8] {
+        &self.sender[..self.sender_len as usize]
+    }
+
+    fn receiver(&self) -> &[u8] {
+        &self.receiver[..self.receiver_len as usize]
+    }
+
+    fn involves(&self, address: &CanonicalAddr) -> bool {
+        self.sender() == address.as_slice() || self.receiver() == address.as_slice()
+    }
+
+    /// Same as `StoredTx::direction_for`
+    fn direction_for(&self, address: &CanonicalAddr) -> TxDirection {
+        if self.sender() == address.as_slice() {
+            TxDirection::Sent
+        } else {
+            TxDirection::Received
+        }
+    }
+
+    fn into_tx<A: Api>(self, api: &A, symbol: &str, reference: Option<String>, direction: TxDirection) -> StdResult<Tx> {
+        Ok(Tx {
+            sender: api.human_address(&CanonicalAddr::from(self.sender()))?,
+            receiver: api.human_address(&CanonicalAddr::from(self.receiver()))?,
+            coins: Coin {
+                denom: symbol.to_string(),
+                amount: Uint128(self.amount),
+            },
+            reference,
+            timestamp: Some(self.time),
+            memo: None,
+            spender: None,
+            direction,
+            block_height: None,
+            denom: None,
+        })
+    }
 }
 
+/// Index of the first compact transfer in a history, stored next to the transfers. Everything
+/// before it is a `StoredTx`
+const KEY_COMPACT_START: &[u8] = b"compact_start";
+
 /// This is here so we can create constant length transactions if we want to return this on-chain instead of a query
 impl Default for Tx {
     fn default() -> Self {
@@ -26,7 +241,14 @@ impl Default for Tx {
             coins: Coin {
                 denom: "EMPT".to_string(),
                 amount: Uint128::zero()
-            }
+            },
+            reference: None,
+            timestamp: None,
+            memo: None,
+            spender: None,
+            direction: TxDirection::Sent,
+            block_height: None,
+            denom: None,
         }
     }
 }
@@ -37,50 +259,1914 @@ impl Default for Tx {
 //     }
 // }
 
-pub fn store_transfer<A: Api, S: Storage>(api: &A, storage: &mut S, from_address: &CanonicalAddr, to_address: &CanonicalAddr, amount: &Uint128, symbol: String) {
+pub fn store_transfer<S: Storage>(storage: &mut S, from_address: &CanonicalAddr, to_address: &CanonicalAddr, amount: &Uint128, time: u64) -> StdResult<()> {
+    store_tagged_transfer(storage, from_address, to_address, amount, time, None)
+}
+
+/// Like `store_transfer`, and tags both history entries with `reference`
+pub fn store_tagged_transfer<S: Storage>(
+    storage: &mut S,
+    from_address: &CanonicalAddr,
+    to_address: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    reference: Option<&str>,
+) -> StdResult<()> {
+    let details = TxDetails {
+        reference,
+        ..TxDetails::default()
+    };
+    store_transfer_with_details(storage, from_address, to_address, amount, time, &details)
+}
+
+/// Like `store_tagged_transfer`, and attaches `memo` to both history entries
+pub fn store_tagged_transfer_with_memo<S: Storage>(
+    storage: &mut S,
+    from_address: &CanonicalAddr,
+    to_address: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    reference: &str,
+    memo: &str,
+) -> StdResult<()> {
+    let details = TxDetails {
+        reference: Some(reference),
+        memo: Some(memo),
+        ..TxDetails::default()
+    };
+    store_transfer_with_details(storage, from_address, to_address, amount, time, &details)
+}
+
+/// Like `store_transfer`, and attaches the sender's `memo` to both history entries
+pub fn store_transfer_with_memo<S: Storage>(
+    storage: &mut S,
+    from_address: &CanonicalAddr,
+    to_address: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    memo: Option<&str>,
+) -> StdResult<()> {
+    let details = TxDetails {
+        memo,
+        ..TxDetails::default()
+    };
+    store_transfer_with_details(storage, from_address, to_address, amount, time, &details)
+}
+
+/// Like `store_transfer`, and records in both history entries that `spender` made the transfer
+/// with an allowance from `from_address`
+pub fn store_transfer_from<S: Storage>(
+    storage: &mut S,
+    from_address: &CanonicalAddr,
+    to_address: &CanonicalAddr,
+    spender: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+) -> StdResult<()> {
+    let details = TxDetails {
+        spender: Some(spender),
+        ..TxDetails::default()
+    };
+    store_transfer_with_details(storage, from_address, to_address, amount, time, &details)
+}
+
+/// Records that `owner` burned `amount`, tagged with `reference`. Only `owner`'s history gets
+/// the entry, with this contract as the receiver
+pub fn store_burn<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    contract: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    height: u64,
+    reference: &str,
+) -> StdResult<()> {
+    let details = TxDetails {
+        reference: Some(reference),
+        block_height: Some(height),
+        ..TxDetails::default()
+    };
+    store_burn_with_details(storage, owner, contract, amount, time, &details)
+}
+
+/// Records a withdrawal in `denom` like a burn, tagged with `REDEEM_REFERENCE`
+pub fn store_redeem<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    contract: &CanonicalAddr,
+    amount: &Uint128,
+    denom: &str,
+    time: u64,
+    height: u64,
+) -> StdResult<()> {
+    let details = TxDetails {
+        reference: Some(REDEEM_REFERENCE),
+        block_height: Some(height),
+        denom: Some(denom),
+        ..TxDetails::default()
+    };
+    store_burn_with_details(storage, owner, contract, amount, time, &details)
+}
+
+fn store_burn_with_details<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    contract: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    details: &TxDetails,
+) -> StdResult<()> {
+    let tx = CompactTx::new(owner, contract, amount, time)?;
+    append_tx(storage, &tx, owner, TxDirection::Sent, details)
+}
+
+/// Records that `amount` was minted to `recipient`, with `minter` as the sender and tagged with
+/// `reference`. Only `recipient`'s history gets the entry, so it shows up like any other
+/// incoming transfer
+pub fn store_mint<S: Storage>(
+    storage: &mut S,
+    minter: &CanonicalAddr,
+    recipient: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    height: u64,
+    reference: &str,
+) -> StdResult<()> {
+    let details = TxDetails {
+        reference: Some(reference),
+        block_height: Some(height),
+        ..TxDetails::default()
+    };
+    store_mint_with_details(storage, minter, recipient, amount, time, &details)
+}
+
+/// Records a deposit of `denom` like a mint by this contract, tagged with `DEPOSIT_REFERENCE`
+pub fn store_deposit<S: Storage>(
+    storage: &mut S,
+    contract: &CanonicalAddr,
+    recipient: &CanonicalAddr,
+    amount: &Uint128,
+    denom: &str,
+    time: u64,
+    height: u64,
+) -> StdResult<()> {
+    let details = TxDetails {
+        reference: Some(DEPOSIT_REFERENCE),
+        block_height: Some(height),
+        denom: Some(denom),
+        ..TxDetails::default()
+    };
+    store_mint_with_details(storage, contract, recipient, amount, time, &details)
+}
+
+fn store_mint_with_details<S: Storage>(
+    storage: &mut S,
+    minter: &CanonicalAddr,
+    recipient: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    details: &TxDetails,
+) -> StdResult<()> {
+    let tx = CompactTx::new(minter, recipient, amount, time)?;
+    append_tx(storage, &tx, recipient, TxDirection::Received, details)
+}
+
+/// What is stored next to a transfer rather than in it, because few transfers have it
+#[derive(Default)]
+struct TxDetails<'a> {
+    reference: Option<&'a str>,
+    memo: Option<&'a str>,
+    spender: Option<&'a CanonicalAddr>,
+    block_height: Option<u64>,
+    denom: Option<&'a str>,
+}
+
+fn store_transfer_with_details<S: Storage>(
+    storage: &mut S,
+    from_address: &CanonicalAddr,
+    to_address: &CanonicalAddr,
+    amount: &Uint128,
+    time: u64,
+    details: &TxDetails,
+) -> StdResult<()> {
+    let tx = CompactTx::new(from_address, to_address, amount, time)?;
+
+    append_tx(storage, &tx, from_address, TxDirection::Sent, details)?;
+    append_tx(storage, &tx, to_address, TxDirection::Received, details)
+}
+
+/// Which side of a transfer an account was on
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TxDirection {
+    Sent,
+    Received,
+}
+
+impl TxDirection {
+    /// Prefix of the per-account index that lists the history positions of transfers in this
+    /// direction
+    fn index_prefix(self) -> &'static [u8] {
+        match self {
+            TxDirection::Sent => PREFIX_SENT_TXS,
+            TxDirection::Received => PREFIX_RECEIVED_TXS,
+        }
+    }
+}
+
+const ALL_DIRECTIONS: [TxDirection; 2] = [TxDirection::Sent, TxDirection::Received];
+
+fn append_tx<S: Storage>(
+    storage: &mut S,
+    tx: &CompactTx,
+    for_address: &CanonicalAddr,
+    direction: TxDirection,
+    details: &TxDetails,
+) -> StdResult<()> {
+    migrate_legacy_txs(storage, for_address)?;
+
+    let history_limit = ReadonlyConfig::from_storage(storage).history_limit()?;
+
+    let (position, first_removed, removed) = {
+        let mut store = VersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+        let first_compact = store.get(KEY_COMPACT_START).is_none();
+        let (position, first_removed, removed) = {
+            let mut store = AppendStoreMut::attach_or_create(&mut store)?;
+            let position = store.push(tx)?;
+
+            let first_removed = store.first_index();
+            let removed = match history_limit {
+                Some(keep_last) => store.truncate_front(keep_last),
+                None => 0,
+            };
+            (position, first_removed, removed)
+        };
+        if first_compact {
+            store.set(KEY_COMPACT_START, &position.to_be_bytes());
+        }
+        (position, first_removed, removed)
+    };
+
+    remove_tx_details(storage, for_address, first_removed..first_removed + removed)?;
+    let mut references = VersionedStorage::multilevel(&[PREFIX_TX_REFERENCES, for_address.as_slice()], storage);
+    if let Some(reference) = details.reference {
+        references.set(&position.to_be_bytes(), reference.as_bytes());
+    }
+    let mut memos = VersionedStorage::multilevel(&[PREFIX_TX_MEMOS, for_address.as_slice()], storage);
+    if let Some(memo) = details.memo {
+        memos.set(&position.to_be_bytes(), memo.as_bytes());
+    }
+    let mut spenders = VersionedStorage::multilevel(&[PREFIX_TX_SPENDERS, for_address.as_slice()], storage);
+    if let Some(spender) = details.spender {
+        spenders.set(&position.to_be_bytes(), spender.as_slice());
+    }
+    let mut heights = VersionedStorage::multilevel(&[PREFIX_TX_HEIGHTS, for_address.as_slice()], storage);
+    if let Some(height) = details.block_height {
+        heights.set(&position.to_be_bytes(), &height.to_be_bytes());
+    }
+    let mut denoms = VersionedStorage::multilevel(&[PREFIX_TX_DENOMS, for_address.as_slice()], storage);
+    if let Some(denom) = details.denom {
+        denoms.set(&position.to_be_bytes(), denom.as_bytes());
+    }
+
+    // the index only holds positions in the history above, so listing one direction doesn't
+    // have to read the transfers of the other
+    let mut index = VersionedStorage::multilevel(&[direction.index_prefix(), for_address.as_slice()], storage);
+    let mut index = AppendStoreMut::attach_or_create(&mut index)?;
+    index.push(&position)?;
 
-    let sender = api.human_address(from_address).unwrap();
-    let receiver = api.human_address(to_address).unwrap();
-    let coins = Coin {
-        denom: symbol,
-        amount: amount.clone(),
+    // an index never points at more transfers than the history keeps
+    if let Some(keep_last) = history_limit {
+        index.truncate_front(keep_last);
+    }
+
+    update_account_stats(storage, for_address, |stats| match direction {
+        TxDirection::Sent => stats.transfers_out = stats.transfers_out.saturating_add(1),
+        TxDirection::Received => stats.transfers_in = stats.transfers_in.saturating_add(1),
+    })?;
+
+    let amount = tx.amount;
+    update_account_totals(storage, for_address, |totals| match direction {
+        TxDirection::Sent => totals.sent = totals.sent.saturating_add(amount),
+        TxDirection::Received => totals.received = totals.received.saturating_add(amount),
+    })
+}
+
+/// Deletes all but the newest `keep_last` transfers from `for_address`'s history. Returns how
+/// many transfers were deleted
+pub fn prune_transfers<S: Storage>(storage: &mut S, for_address: &CanonicalAddr, keep_last: u32) -> StdResult<u32> {
+    migrate_legacy_txs(storage, for_address)?;
+
+    let (first_removed, removed) = {
+        let mut store = VersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+        let mut store = AppendStoreMut::<StoredTx, _>::attach_or_create(&mut store)?;
+        (store.first_index(), store.truncate_front(keep_last))
     };
 
-    let tx = Tx {
-        sender,
-        receiver,
-        coins
+    remove_tx_details(storage, for_address, first_removed..first_removed + removed)?;
+
+    for direction in ALL_DIRECTIONS.iter() {
+        let mut index = VersionedStorage::multilevel(&[direction.index_prefix(), for_address.as_slice()], storage);
+        let mut index = AppendStoreMut::<u32, _>::attach_or_create(&mut index)?;
+        index.truncate_front(keep_last);
+    }
+
+    Ok(removed)
+}
+
+/// Deletes what is stored next to the transfers at `positions`, once they are pruned
+fn remove_tx_details<S: Storage>(storage: &mut S, for_address: &CanonicalAddr, positions: std::ops::Range<u32>) -> StdResult<()> {
+    // compact transfers hold their own time
+    let timed_end = positions.end.min(compact_start(storage, for_address)?);
+
+    let mut references = VersionedStorage::multilevel(&[PREFIX_TX_REFERENCES, for_address.as_slice()], storage);
+    for position in positions.clone() {
+        references.remove(&position.to_be_bytes());
+    }
+    let mut memos = VersionedStorage::multilevel(&[PREFIX_TX_MEMOS, for_address.as_slice()], storage);
+    for position in positions.clone() {
+        memos.remove(&position.to_be_bytes());
+    }
+    let mut spenders = VersionedStorage::multilevel(&[PREFIX_TX_SPENDERS, for_address.as_slice()], storage);
+    for position in positions.clone() {
+        spenders.remove(&position.to_be_bytes());
+    }
+    let mut heights = VersionedStorage::multilevel(&[PREFIX_TX_HEIGHTS, for_address.as_slice()], storage);
+    for position in positions.clone() {
+        heights.remove(&position.to_be_bytes());
+    }
+    let mut denoms = VersionedStorage::multilevel(&[PREFIX_TX_DENOMS, for_address.as_slice()], storage);
+    for position in positions.clone() {
+        denoms.remove(&position.to_be_bytes());
+    }
+    let mut times = VersionedStorage::multilevel(&[PREFIX_TX_TIMES, for_address.as_slice()], storage);
+    for position in positions.start..timed_end {
+        times.remove(&position.to_be_bytes());
+    }
+    Ok(())
+}
+
+/// Index of the first compact transfer in `for_address`'s history, `u32::MAX` if there is none
+fn compact_start<S: ReadonlyStorage>(storage: &S, for_address: &CanonicalAddr) -> StdResult<u32> {
+    let store = ReadonlyVersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+    match store.get(KEY_COMPACT_START) {
+        Some(start_bytes) => bytes_to_u32(&start_bytes),
+        None => Ok(u32::MAX),
+    }
+}
+
+/// Histories used to be stored as a single serialized `Vec<Tx>` per address. Moves such a history
+/// into the append store the first time something is appended to it. The moved transfers are not
+/// added to the direction indexes, so they only show up when the history isn't filtered
+fn migrate_legacy_txs<S: Storage>(storage: &mut S, for_address: &CanonicalAddr) -> StdResult<()> {
+    let legacy_txs = match read_legacy_txs(storage, for_address)? {
+        Some(txs) => txs,
+        None => return Ok(()),
     };
 
-    let mut store = PrefixedStorage::new(PREFIX_TXS, storage);
+    {
+        let mut store = VersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+        let mut store = AppendStoreMut::attach_or_create(&mut store)?;
+        for tx in legacy_txs.iter() {
+            store.push(tx)?;
+        }
+    }
+
+    let mut legacy_store = VersionedStorage::new(PREFIX_TXS, storage);
+    legacy_store.remove(for_address.as_slice());
+
+    Ok(())
+}
+
+fn read_legacy_txs<S: ReadonlyStorage>(storage: &S, for_address: &CanonicalAddr) -> StdResult<Option<Vec<StoredTx>>> {
+    let legacy_store = ReadonlyVersionedStorage::new(PREFIX_TXS, storage);
 
-    append_tx(&mut store, &tx, from_address);
-    append_tx(&mut store, &tx, to_address);
+    match legacy_store.get(for_address.as_slice()) {
+        Some(tx_bytes) => bincode2::deserialize(&tx_bytes)
+            .map(Some)
+            .map_err(|_| generic_err("Corrupted transfer history")),
+        None => Ok(None),
+    }
 }
 
-fn append_tx<S: Storage>(store: &mut PrefixedStorage<S>, tx: &Tx, for_address: &CanonicalAddr) {
-    let mut new_txs: Vec<Tx> = vec![];
+/// Reads the length and newest entry of `for_address`'s history, its direction indexes, its
+/// activity counters and its totals and writes them back unchanged, so the access looks like recording a
+/// transfer in either direction
+pub fn touch_transfers<S: Storage>(storage: &mut S, for_address: &CanonicalAddr) -> StdResult<()> {
+    {
+        let mut store = VersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+        let mut store = AppendStoreMut::<StoredTx, _>::attach_or_create(&mut store)?;
+        store.touch()?;
+    }
+
+    for direction in ALL_DIRECTIONS.iter() {
+        let mut index = VersionedStorage::multilevel(&[direction.index_prefix(), for_address.as_slice()], storage);
+        let mut index = AppendStoreMut::<u32, _>::attach_or_create(&mut index)?;
+        index.touch()?;
+    }
+
+    update_account_stats(storage, for_address, |_| {})?;
+    update_account_totals(storage, for_address, |_| {})
+}
 
-    let txs = store.get(for_address.as_slice());
+/// How many transfers `for_address`'s history holds
+pub fn count_transfers<S: Storage>(storage: &S, for_address: &CanonicalAddr) -> StdResult<u32> {
+    if let Some(txs) = read_legacy_txs(storage, for_address)? {
+        return Ok(txs.len() as u32);
+    }
 
-    if let Some(txs_bytes) = txs {
-        new_txs = bincode2::deserialize(txs_bytes.as_slice()).unwrap();
+    let store = ReadonlyVersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+    match AppendStore::<StoredTx, _>::attach(&store) {
+        Some(store) => Ok(store?.len()),
+        None => Ok(0),
     }
+}
+
+/// A history entry in either format, stored ones with their time if it was recorded
+enum HistoryEntry {
+    Stored(StoredTx, Option<u64>),
+    Compact(CompactTx),
+}
 
-    new_txs.push(tx.clone());
+impl HistoryEntry {
+    fn time(&self) -> Option<u64> {
+        match self {
+            HistoryEntry::Stored(_, time) => *time,
+            HistoryEntry::Compact(tx) => Some(tx.time),
+        }
+    }
+}
 
-    let tx_bytes: Vec<u8> = bincode2::serialize(&new_txs).unwrap();
+/// Which transfers a history query returns. The default returns all of them
+#[derive(Default)]
+pub struct TxFilter<'a> {
+    /// Only transfers the account sent, or only those it received
+    pub direction: Option<TxDirection>,
+    /// Only transfers from or to this address
+    pub counterparty: Option<&'a HumanAddr>,
+    /// Only transfers at or after this block time
+    pub start_time: Option<u64>,
+    /// Only transfers before this block time
+    pub end_time: Option<u64>,
+}
 
-    store.set(for_address.as_slice(), &tx_bytes);
+impl<'a> TxFilter<'a> {
+    fn has_time_range(&self) -> bool {
+        self.start_time.is_some() || self.end_time.is_some()
+    }
 }
 
-pub fn get_transfers<S: Storage>(storage: &S, for_address: &CanonicalAddr) -> StdResult<Vec<Tx>> {
-    let store = ReadonlyPrefixedStorage::new(PREFIX_TXS, storage);
+/// Returns page number `page` of the transfers in `for_address`'s history that pass `filter`,
+/// newest first. Stops early once `max_reads` entries were looked at, so the page can come back
+/// short even though older transfers would pass the filter
+pub fn get_transfers<A: Api, S: Storage>(
+    api: &A,
+    storage: &S,
+    for_address: &CanonicalAddr,
+    filter: &TxFilter,
+    page: u32,
+    page_size: u32,
+    max_reads: u32,
+) -> StdResult<Vec<Tx>> {
+    let skip = page.saturating_mul(page_size) as usize;
+
+    if let Some(txs) = read_legacy_txs(storage, for_address)? {
+        // legacy histories have neither direction indexes nor timestamps
+        if filter.direction.is_some() || filter.has_time_range() {
+            return Ok(vec![]);
+        }
+        let for_address = api.human_address(for_address)?;
+        return Ok(txs
+            .into_iter()
+            .rev()
+            .take(max_reads as usize)
+            .filter(|tx| filter.counterparty.map_or(true, |counterparty| tx.involves(counterparty)))
+            .skip(skip)
+            .take(page_size as usize)
+            .map(|tx| {
+                let direction = tx.direction_for(&for_address);
+                tx.into_tx(None, None, direction)
+            })
+            .collect());
+    }
+
+    let txs_store = ReadonlyVersionedStorage::multilevel(&[PREFIX_TXS, for_address.as_slice()], storage);
+    let store = match AppendStore::<StoredTx, _>::attach(&txs_store) {
+        Some(store) => store?,
+        None => return Ok(vec![]),
+    };
+    let compact_store = match AppendStore::<CompactTx, _>::attach(&txs_store) {
+        Some(store) => store?,
+        None => return Ok(vec![]),
+    };
+    let compact_start = compact_start(storage, for_address)?;
+    let symbol = ReadonlyConfig::from_storage(storage).symbol()?;
+    let counterparty = match filter.counterparty {
+        Some(counterparty) => Some((counterparty, api.canonical_address(counterparty)?)),
+        None => None,
+    };
+    let references = ReadonlyVersionedStorage::multilevel(&[PREFIX_TX_REFERENCES, for_address.as_slice()], storage);
+    let times = ReadonlyVersionedStorage::multilevel(&[PREFIX_TX_TIMES, for_address.as_slice()], storage);
+    let read_time = |position: u32| -> StdResult<Option<u64>> {
+        match times.get(&position.to_be_bytes()) {
+            Some(bytes) => Ok(Some(u64::from_be_bytes(
+                bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| generic_err("Corrupted transfer time"))?,
+            ))),
+            None => Ok(None),
+        }
+    };
+    let read_reference = |position: u32| -> StdResult<Option<String>> {
+        match references.get(&position.to_be_bytes()) {
+            Some(bytes) => Ok(Some(
+                String::from_utf8(bytes).map_err(|_| generic_err("Corrupted transfer reference"))?,
+            )),
+            None => Ok(None),
+        }
+    };
+    let memos = ReadonlyVersionedStorage::multilevel(&[PREFIX_TX_MEMOS, for_address.as_slice()], storage);
+    let read_memo = |position: u32| -> StdResult<Option<String>> {
+        match memos.get(&position.to_be_bytes()) {
+            Some(bytes) => Ok(Some(
+                String::from_utf8(bytes).map_err(|_| generic_err("Corrupted transfer memo"))?,
+            )),
+            None => Ok(None),
+        }
+    };
+    let spenders = ReadonlyVersionedStorage::multilevel(&[PREFIX_TX_SPENDERS, for_address.as_slice()], storage);
+    let read_spender = |position: u32| -> StdResult<Option<HumanAddr>> {
+        match spenders.get(&position.to_be_bytes()) {
+            Some(bytes) => Ok(Some(api.human_address(&CanonicalAddr::from(bytes))?)),
+            None => Ok(None),
+        }
+    };
+    let heights = ReadonlyVersionedStorage::multilevel(&[PREFIX_TX_HEIGHTS, for_address.as_slice()], storage);
+    let read_height = |position: u32| -> StdResult<Option<u64>> {
+        match heights.get(&position.to_be_bytes()) {
+            Some(bytes) => Ok(Some(u64::from_be_bytes(
+                bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| generic_err("Corrupted transfer height"))?,
+            ))),
+            None => Ok(None),
+        }
+    };
+    let denoms = ReadonlyVersionedStorage::multilevel(&[PREFIX_TX_DENOMS, for_address.as_slice()], storage);
+    let read_denom = |position: u32| -> StdResult<Option<String>> {
+        match denoms.get(&position.to_be_bytes()) {
+            Some(bytes) => Ok(Some(
+                String::from_utf8(bytes).map_err(|_| generic_err("Corrupted transfer denom"))?,
+            )),
+            None => Ok(None),
+        }
+    };
+
+    let index = match filter.direction {
+        Some(direction) => Some(ReadonlyVersionedStorage::multilevel(
+            &[direction.index_prefix(), for_address.as_slice()],
+            storage,
+        )),
+        None => None,
+    };
+    // history positions, newest first
+    let positions: Box<dyn Iterator<Item = StdResult<u32>>> = match &index {
+        Some(index) => match AppendStore::<u32, _>::attach(index) {
+            Some(index) => Box::new(index?.iter().rev()),
+            None => return Ok(vec![]),
+        },
+        None => {
+            let first = store.first_index();
+            Box::new((first..first + store.len()).rev().map(Ok))
+        }
+    };
 
-    if let Some(tx_bytes) = store.get(for_address.as_slice()) {
-        let txs: Vec<Tx> = bincode2::deserialize(&tx_bytes).unwrap();
-        Ok(txs)
+    // without a counterparty or time range, skipping doesn't need to read the skipped transfers.
+    // With one, every transfer has to be looked at to know whether it counts
+    let (skip_positions, mut skip_matches) = if filter.counterparty.is_some() || filter.has_time_range() {
+        (0, skip)
     } else {
-        Ok(vec![])
+        (skip, 0)
+    };
+    let mut positions = positions.skip(skip_positions);
+
+    let mut txs = vec![];
+    let mut reads = 0;
+    while txs.len() < page_size as usize && reads < max_reads {
+        let position = match positions.next() {
+            Some(position) => position?,
+            None => break,
+        };
+        reads += 1;
+
+        // positions only get older from here, and everything older was pruned as well
+        let tx = if position >= compact_start {
+            match compact_store.get_pushed(position) {
+                Some(tx) => HistoryEntry::Compact(tx?),
+                None => break,
+            }
+        } else {
+            match store.get_pushed(position) {
+                Some(tx) => HistoryEntry::Stored(tx?, read_time(position)?),
+                None => break,
+            }
+        };
+
+        if filter.has_time_range() {
+            match tx.time() {
+                // times only get earlier from here, and transfers recorded before times were
+                // are older than any that has one
+                None => break,
+                Some(time) if filter.start_time.map_or(false, |start| time < start) => break,
+                Some(time) if filter.end_time.map_or(false, |end| time >= end) => continue,
+                Some(_) => {}
+            }
+        }
+        if let Some((counterparty, counterparty_raw)) = &counterparty {
+            let involved = match &tx {
+                HistoryEntry::Stored(tx, _) => tx.involves(counterparty),
+                HistoryEntry::Compact(tx) => tx.involves(counterparty_raw),
+            };
+            if !involved {
+                continue;
+            }
+        }
+        if skip_matches > 0 {
+            skip_matches -= 1;
+            continue;
+        }
+
+        let reference = read_reference(position)?;
+        // with a direction filter the index says which side the account was on, which also
+        // tells the two entries of a transfer to oneself apart
+        let mut tx = match tx {
+            HistoryEntry::Stored(tx, time) => {
+                let direction = match filter.direction {
+                    Some(direction) => direction,
+                    None => tx.direction_for(&api.human_address(for_address)?),
+                };
+                tx.into_tx(reference, time, direction)
+            }
+            HistoryEntry::Compact(tx) => {
+                let direction = filter.direction.unwrap_or_else(|| tx.direction_for(for_address));
+                tx.into_tx(api, &symbol, reference, direction)?
+            }
+        };
+        tx.memo = read_memo(position)?;
+        tx.spender = read_spender(position)?;
+        tx.block_height = read_height(position)?;
+        tx.denom = read_denom(position)?;
+        txs.push(tx);
+    }
+    Ok(txs)
+}
+
+/// Version of the storage layout written by this code
+///
+/// Every namespace is stored under this version byte. Keys written before namespaces were
+/// versioned (v1) are still read, see `VersionedStorage`
+pub const STORAGE_VERSION: u8 = 2;
+const VERSION_NAMESPACE: &[u8] = &[STORAGE_VERSION];
+
+/// A namespace that writes under the current `STORAGE_VERSION` and falls back to the unversioned
+/// v1 location when reading a key that hasn't been written since the upgrade
+///
+/// v1 entries are never rewritten in place: the first write to a key shadows its v1 entry, so
+/// state migrates lazily as it is touched. Removing a key removes both entries
+pub struct VersionedStorage<'a, S: Storage> {
+    storage: &'a mut S,
+    namespaces: Vec<&'a [u8]>,
+}
+
+impl<'a, S: Storage> VersionedStorage<'a, S> {
+    pub fn new(namespace: &'a [u8], storage: &'a mut S) -> Self {
+        Self::multilevel(&[namespace], storage)
+    }
+
+    pub fn multilevel(namespaces: &[&'a [u8]], storage: &'a mut S) -> Self {
+        Self {
+            storage,
+            namespaces: namespaces.to_vec(),
+        }
+    }
+
+    fn current(&mut self) -> PrefixedStorage<'_, S> {
+        PrefixedStorage::multilevel(&versioned_namespaces(&self.namespaces), self.storage)
+    }
+
+    fn legacy(&mut self) -> PrefixedStorage<'_, S> {
+        PrefixedStorage::multilevel(&self.namespaces, self.storage)
+    }
+}
+
+impl<'a, S: Storage> ReadonlyStorage for VersionedStorage<'a, S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        versioned_get(&*self.storage, &self.namespaces, key)
+    }
+}
+
+impl<'a, S: Storage> Storage for VersionedStorage<'a, S> {
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        debug_access("write", &self.namespaces, key);
+        self.current().set(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        debug_access("remove", &self.namespaces, key);
+        self.current().remove(key);
+        self.legacy().remove(key);
+    }
+}
+
+/// Read-only view of a `VersionedStorage`
+pub struct ReadonlyVersionedStorage<'a, S: ReadonlyStorage> {
+    storage: &'a S,
+    namespaces: Vec<&'a [u8]>,
+}
+
+impl<'a, S: ReadonlyStorage> ReadonlyVersionedStorage<'a, S> {
+    pub fn new(namespace: &'a [u8], storage: &'a S) -> Self {
+        Self::multilevel(&[namespace], storage)
+    }
+
+    pub fn multilevel(namespaces: &[&'a [u8]], storage: &'a S) -> Self {
+        Self {
+            storage,
+            namespaces: namespaces.to_vec(),
+        }
+    }
+}
+
+impl<'a, S: ReadonlyStorage> ReadonlyStorage for ReadonlyVersionedStorage<'a, S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        versioned_get(self.storage, &self.namespaces, key)
+    }
+}
+
+fn versioned_namespaces<'a>(namespaces: &[&'a [u8]]) -> Vec<&'a [u8]> {
+    let mut versioned = Vec::with_capacity(namespaces.len() + 1);
+    versioned.push(VERSION_NAMESPACE);
+    versioned.extend_from_slice(namespaces);
+    versioned
+}
+
+fn versioned_get<S: ReadonlyStorage>(storage: &S, namespaces: &[&[u8]], key: &[u8]) -> Option<Vec<u8>> {
+    debug_access("read", namespaces, key);
+    ReadonlyPrefixedStorage::multilevel(&versioned_namespaces(namespaces), storage)
+        .get(key)
+        .or_else(|| ReadonlyPrefixedStorage::multilevel(namespaces, storage).get(key))
+}
+
+/// With the `debug-print` feature, logs a storage access to the enclave's debug output, so
+/// changes to access patterns can be checked. Only the outer namespace is shown as is. Inner
+/// namespaces and keys often hold addresses, so only the first bytes of their hash are
+#[cfg(feature = "debug-print")]
+fn debug_access(operation: &str, namespaces: &[&[u8]], key: &[u8]) {
+    let (prefix, inner): (&[u8], &[&[u8]]) = match namespaces.split_first() {
+        Some((prefix, inner)) => (prefix, inner),
+        None => (b"", &[]),
+    };
+    let full_key = [inner.concat().as_slice(), key].concat();
+    let key_hash: String = sha_256(&full_key)[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    debug_print!(
+        "storage {} {} {}",
+        operation,
+        String::from_utf8_lossy(prefix),
+        key_hash
+    );
+}
+
+#[cfg(not(feature = "debug-print"))]
+fn debug_access(_operation: &str, _namespaces: &[&[u8]], _key: &[u8]) {}
+
+/// Stores values of type `T` under arbitrary keys, serialized with bincode
+pub struct TypedStoreMut<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: Storage,
+{
+    storage: &'a mut S,
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T, S> TypedStoreMut<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: Storage,
+{
+    pub fn attach(storage: &'a mut S) -> Self {
+        Self {
+            storage,
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn store(&mut self, key: &[u8], item: &T) -> StdResult<()> {
+        let item_bytes = bincode2::serialize(item)
+            .map_err(|_| generic_err("Failed to serialize item"))?;
+        self.storage.set(key, &item_bytes);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.storage.remove(key);
+    }
+
+    pub fn load(&self, key: &[u8]) -> StdResult<T> {
+        TypedStore::attach(&*self.storage).load(key)
+    }
+
+    pub fn may_load(&self, key: &[u8]) -> StdResult<Option<T>> {
+        TypedStore::attach(&*self.storage).may_load(key)
+    }
+}
+
+/// Read-only view of a `TypedStoreMut`
+pub struct TypedStore<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    storage: &'a S,
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T, S> TypedStore<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    pub fn attach(storage: &'a S) -> Self {
+        Self {
+            storage,
+            item_type: PhantomData,
+        }
+    }
+
+    /// Fails if nothing is stored under `key`
+    pub fn load(&self, key: &[u8]) -> StdResult<T> {
+        self.may_load(key)?
+            .ok_or_else(|| generic_err(format!("No {} found", std::any::type_name::<T>())))
+    }
+
+    pub fn may_load(&self, key: &[u8]) -> StdResult<Option<T>> {
+        match self.storage.get(key) {
+            Some(item_bytes) => bincode2::deserialize(&item_bytes).map(Some).map_err(|_| {
+                generic_err(format!("Corrupted {} found", std::any::type_name::<T>()))
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Token-wide configuration: constants, total supply, history limit, admin and the native
+/// denoms the token wraps
+pub struct Config<'a, S: Storage> {
+    storage: VersionedStorage<'a, S>,
+}
+
+impl<'a, S: Storage> Config<'a, S> {
+    pub fn from_storage(storage: &'a mut S) -> Self {
+        Self {
+            storage: VersionedStorage::new(CONFIG_KEY, storage),
+        }
+    }
+
+    fn as_readonly(&self) -> ReadonlyConfigImpl<'_, VersionedStorage<'a, S>> {
+        ReadonlyConfigImpl(&self.storage)
     }
-}
\ No newline at end of file
+
+    pub fn constants(&self) -> StdResult<Constants> {
+        self.as_readonly().constants()
+    }
+
+    /// Also stores the symbol on its own, so recording a transfer doesn't have to read all
+    /// constants
+    pub fn set_constants(&mut self, constants: &Constants) -> StdResult<()> {
+        self.storage.set(KEY_SYMBOL, constants.symbol.as_bytes());
+        TypedStoreMut::attach(&mut self.storage).store(KEY_CONSTANTS, constants)
+    }
+
+    pub fn symbol(&self) -> StdResult<String> {
+        self.as_readonly().symbol()
+    }
+
+    pub fn total_supply(&self) -> StdResult<u128> {
+        self.as_readonly().total_supply()
+    }
+
+    pub fn set_total_supply(&mut self, supply: u128) {
+        self.storage.set(KEY_TOTAL_SUPPLY, &supply.to_be_bytes());
+    }
+
+    pub fn history_limit(&self) -> StdResult<Option<u32>> {
+        self.as_readonly().history_limit()
+    }
+
+    /// `None` keeps every transfer
+    pub fn set_history_limit(&mut self, limit: Option<u32>) {
+        match limit {
+            Some(limit) => self.storage.set(KEY_HISTORY_LIMIT, &limit.to_be_bytes()),
+            None => self.storage.remove(KEY_HISTORY_LIMIT),
+        }
+    }
+
+    pub fn admin(&self) -> StdResult<CanonicalAddr> {
+        self.as_readonly().admin()
+    }
+
+    pub fn set_admin(&mut self, admin: &CanonicalAddr) {
+        self.storage.set(KEY_ADMIN, admin.as_slice());
+    }
+
+    pub fn contract_address(&self) -> StdResult<CanonicalAddr> {
+        self.as_readonly().contract_address()
+    }
+
+    /// Queries don't know which contract they run in, so it is stored
+    pub fn set_contract_address(&mut self, address: &CanonicalAddr) {
+        self.storage.set(KEY_CONTRACT_ADDRESS, address.as_slice());
+    }
+
+    pub fn treasury(&self) -> StdResult<CanonicalAddr> {
+        self.as_readonly().treasury()
+    }
+
+    pub fn set_treasury(&mut self, treasury: &CanonicalAddr) {
+        self.storage.set(KEY_TREASURY, treasury.as_slice());
+    }
+
+    pub fn supported_denoms(&self) -> StdResult<Vec<String>> {
+        self.as_readonly().supported_denoms()
+    }
+
+    pub fn set_supported_denoms(&mut self, denoms: &[String]) -> StdResult<()> {
+        TypedStoreMut::attach(&mut self.storage).store(KEY_SUPPORTED_DENOMS, &denoms.to_vec())
+    }
+
+    pub fn reserve(&self, denom: &str) -> StdResult<u128> {
+        self.as_readonly().reserve(denom)
+    }
+
+    pub fn set_reserve(&mut self, denom: &str, amount: u128) {
+        self.storage.set(&reserve_key(denom), &amount.to_be_bytes());
+    }
+
+    pub fn remove_reserve(&mut self, denom: &str) {
+        self.storage.remove(&reserve_key(denom));
+    }
+
+    pub fn redeem_only(&self) -> bool {
+        self.as_readonly().redeem_only()
+    }
+
+    /// There is no way back, see `HandleMsg::PermanentRedeemOnly`
+    pub fn set_redeem_only(&mut self) {
+        self.storage.set(KEY_REDEEM_ONLY, &[1]);
+    }
+}
+
+/// Read-only view of `Config`
+pub struct ReadonlyConfig<'a, S: ReadonlyStorage> {
+    storage: ReadonlyVersionedStorage<'a, S>,
+}
+
+impl<'a, S: ReadonlyStorage> ReadonlyConfig<'a, S> {
+    pub fn from_storage(storage: &'a S) -> Self {
+        Self {
+            storage: ReadonlyVersionedStorage::new(CONFIG_KEY, storage),
+        }
+    }
+
+    fn as_readonly(&self) -> ReadonlyConfigImpl<'_, ReadonlyVersionedStorage<'a, S>> {
+        ReadonlyConfigImpl(&self.storage)
+    }
+
+    pub fn constants(&self) -> StdResult<Constants> {
+        self.as_readonly().constants()
+    }
+
+    pub fn symbol(&self) -> StdResult<String> {
+        self.as_readonly().symbol()
+    }
+
+    pub fn total_supply(&self) -> StdResult<u128> {
+        self.as_readonly().total_supply()
+    }
+
+    pub fn history_limit(&self) -> StdResult<Option<u32>> {
+        self.as_readonly().history_limit()
+    }
+
+    pub fn admin(&self) -> StdResult<CanonicalAddr> {
+        self.as_readonly().admin()
+    }
+
+    pub fn contract_address(&self) -> StdResult<CanonicalAddr> {
+        self.as_readonly().contract_address()
+    }
+
+    pub fn treasury(&self) -> StdResult<CanonicalAddr> {
+        self.as_readonly().treasury()
+    }
+
+    pub fn supported_denoms(&self) -> StdResult<Vec<String>> {
+        self.as_readonly().supported_denoms()
+    }
+
+    pub fn reserve(&self, denom: &str) -> StdResult<u128> {
+        self.as_readonly().reserve(denom)
+    }
+
+    pub fn redeem_only(&self) -> bool {
+        self.as_readonly().redeem_only()
+    }
+}
+
+fn reserve_key(denom: &str) -> Vec<u8> {
+    [PREFIX_RESERVES, denom.as_bytes()].concat()
+}
+
+/// The reads shared by `Config` and `ReadonlyConfig`
+struct ReadonlyConfigImpl<'a, S: ReadonlyStorage>(&'a S);
+
+impl<'a, S: ReadonlyStorage> ReadonlyConfigImpl<'a, S> {
+    fn constants(&self) -> StdResult<Constants> {
+        TypedStore::attach(self.0).load(KEY_CONSTANTS)
+    }
+
+    /// Tokens created before the symbol was stored on its own only have it in the constants
+    fn symbol(&self) -> StdResult<String> {
+        match self.0.get(KEY_SYMBOL) {
+            Some(symbol_bytes) => {
+                String::from_utf8(symbol_bytes).map_err(|_| generic_err("Corrupted symbol"))
+            }
+            None => Ok(self.constants()?.symbol),
+        }
+    }
+
+    fn total_supply(&self) -> StdResult<u128> {
+        let supply_bytes = self
+            .0
+            .get(KEY_TOTAL_SUPPLY)
+            .ok_or_else(|| generic_err("No total supply stored"))?;
+        bytes_to_u128(&supply_bytes)
+    }
+
+    fn history_limit(&self) -> StdResult<Option<u32>> {
+        match self.0.get(KEY_HISTORY_LIMIT) {
+            Some(limit_bytes) => bytes_to_u32(&limit_bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn admin(&self) -> StdResult<CanonicalAddr> {
+        self.0
+            .get(KEY_ADMIN)
+            .map(CanonicalAddr::from)
+            .ok_or_else(|| generic_err("No admin stored"))
+    }
+
+    /// Tokens created before it was stored learn it from the first message they handle
+    fn contract_address(&self) -> StdResult<CanonicalAddr> {
+        self.0
+            .get(KEY_CONTRACT_ADDRESS)
+            .map(CanonicalAddr::from)
+            .ok_or_else(|| generic_err("The contract doesn't know its address yet"))
+    }
+
+    /// Where fees are credited. Until the admin sets one, the contract keeps them itself
+    fn treasury(&self) -> StdResult<CanonicalAddr> {
+        match self.0.get(KEY_TREASURY) {
+            Some(treasury) => Ok(CanonicalAddr::from(treasury)),
+            None => self.contract_address(),
+        }
+    }
+
+    /// The first denom is the default for withdrawals
+    fn supported_denoms(&self) -> StdResult<Vec<String>> {
+        Ok(TypedStore::attach(self.0)
+            .may_load(KEY_SUPPORTED_DENOMS)?
+            .unwrap_or_else(|| vec![DEFAULT_DENOM.to_string()]))
+    }
+
+    /// How much of `denom` the contract holds for withdrawals
+    fn reserve(&self, denom: &str) -> StdResult<u128> {
+        match self.0.get(&reserve_key(denom)) {
+            Some(reserve_bytes) => bytes_to_u128(&reserve_bytes),
+            // before reserves were tracked uscrt backed the whole supply
+            None if denom == DEFAULT_DENOM && self.0.get(KEY_SUPPORTED_DENOMS).is_none() => {
+                self.total_supply()
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn redeem_only(&self) -> bool {
+        self.0.get(KEY_REDEEM_ONLY).is_some()
+    }
+}
+
+/// Whether a change of the total supply creates or destroys tokens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SupplyChange {
+    Mint(u128),
+    Burn(u128),
+}
+
+/// The total supply, failing if what is stored isn't a u128
+pub fn read_total_supply<S: ReadonlyStorage>(storage: &S) -> StdResult<u128> {
+    ReadonlyConfig::from_storage(storage).total_supply()
+}
+
+/// Applies `change` to the total supply and returns the new one. Balances have to be updated
+/// first: with the `debug-supply` feature, this asserts that they changed by as much since the
+/// last time the supply did
+pub fn write_total_supply<S: Storage>(storage: &mut S, change: SupplyChange) -> StdResult<u128> {
+    let total_supply = read_total_supply(storage)?;
+    let total_supply = match change {
+        SupplyChange::Mint(amount) => total_supply
+            .checked_add(amount)
+            .ok_or_else(|| generic_err("Mint overflows the total supply"))?,
+        SupplyChange::Burn(amount) => total_supply
+            .checked_sub(amount)
+            .ok_or_else(|| generic_err("Total supply is smaller than the burned amount"))?,
+    };
+    Config::from_storage(storage).set_total_supply(total_supply);
+
+    #[cfg(feature = "debug-supply")]
+    {
+        let expected = match change {
+            SupplyChange::Mint(amount) => amount as i128,
+            SupplyChange::Burn(amount) => -(amount as i128),
+        };
+        assert_eq!(
+            take_balance_delta(storage),
+            expected,
+            "Balances and total supply changed by different amounts"
+        );
+    }
+
+    Ok(total_supply)
+}
+
+/// How much all balances changed by since the delta was last taken. Transfers add nothing to it
+#[cfg(feature = "debug-supply")]
+pub fn take_balance_delta<S: Storage>(storage: &mut S) -> i128 {
+    let delta = storage
+        .get(KEY_BALANCE_DELTA)
+        .and_then(|bytes| bytes.as_slice().try_into().ok())
+        .map_or(0, i128::from_be_bytes);
+    storage.remove(KEY_BALANCE_DELTA);
+    delta
+}
+
+#[cfg(feature = "debug-supply")]
+fn add_balance_delta<S: Storage>(storage: &mut S, old_balance: u128, new_balance: u128) {
+    let delta = storage
+        .get(KEY_BALANCE_DELTA)
+        .and_then(|bytes| bytes.as_slice().try_into().ok())
+        .map_or(0, i128::from_be_bytes)
+        + (new_balance as i128 - old_balance as i128);
+    storage.set(KEY_BALANCE_DELTA, &delta.to_be_bytes());
+}
+
+/// Account balances, stored as 16 byte big-endian integers under `KEY_PREFIX_BALANCE`
+///
+/// Balances written before that are in the `PREFIX_BALANCES` namespace, and are moved the first
+/// time they change
+pub struct Balances<'a, S: Storage> {
+    storage: &'a mut S,
+}
+
+impl<'a, S: Storage> Balances<'a, S> {
+    pub fn from_storage(storage: &'a mut S) -> Self {
+        Self { storage }
+    }
+
+    /// Returns zero for accounts that never held tokens
+    pub fn balance(&self, account: &CanonicalAddr) -> StdResult<u128> {
+        ReadonlyBalances::from_storage(&*self.storage).balance(account)
+    }
+
+    /// Settles the rewards earned with the old balance before replacing it, so that rewards
+    /// are always paid on what the account held at the time
+    pub fn set_balance(&mut self, account: &CanonicalAddr, amount: u128) -> StdResult<()> {
+        let key = balance_key(account);
+        debug_access("read", &[KEY_PREFIX_BALANCE], account.as_slice());
+        let (old_balance, moved) = match self.storage.get(&key) {
+            Some(balance_bytes) => (bytes_to_u128(&balance_bytes)?, true),
+            None => (read_legacy_balance(&*self.storage, account)?, false),
+        };
+        settle_rewards(self.storage, account, old_balance)?;
+        #[cfg(feature = "debug-supply")]
+        add_balance_delta(self.storage, old_balance, amount);
+
+        debug_access("write", &[KEY_PREFIX_BALANCE], account.as_slice());
+        self.storage.set(&key, &amount.to_be_bytes());
+        if !moved {
+            VersionedStorage::new(PREFIX_BALANCES, self.storage).remove(account.as_slice());
+        }
+        Ok(())
+    }
+
+    /// Deletes the balance, after settling the rewards it earned. Reads as zero afterwards
+    pub fn remove_balance(&mut self, account: &CanonicalAddr) -> StdResult<()> {
+        self.set_balance(account, 0)?;
+        debug_access("remove", &[KEY_PREFIX_BALANCE], account.as_slice());
+        self.storage.remove(&balance_key(account));
+        Ok(())
+    }
+}
+
+/// Read-only view of `Balances`
+pub struct ReadonlyBalances<'a, S: ReadonlyStorage> {
+    storage: &'a S,
+}
+
+impl<'a, S: ReadonlyStorage> ReadonlyBalances<'a, S> {
+    pub fn from_storage(storage: &'a S) -> Self {
+        Self { storage }
+    }
+
+    /// Returns zero for accounts that never held tokens
+    pub fn balance(&self, account: &CanonicalAddr) -> StdResult<u128> {
+        debug_access("read", &[KEY_PREFIX_BALANCE], account.as_slice());
+        match self.storage.get(&balance_key(account)) {
+            Some(balance_bytes) => bytes_to_u128(&balance_bytes),
+            None => read_legacy_balance(self.storage, account),
+        }
+    }
+}
+
+fn balance_key(account: &CanonicalAddr) -> Vec<u8> {
+    [KEY_PREFIX_BALANCE, account.as_slice()].concat()
+}
+
+fn read_legacy_balance<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> StdResult<u128> {
+    read_u128(&ReadonlyVersionedStorage::new(PREFIX_BALANCES, storage), account.as_slice())
+}
+
+/// Rewards an account earned up to the moment the global `reward_per_token` was
+/// `reward_per_token`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RewardCheckpoint {
+    pub reward_per_token: u128,
+    pub accrued: u128,
+}
+
+/// Sum of all distributed rewards divided by the total supply at the time, times `REWARD_SCALE`
+pub fn read_reward_per_token<S: ReadonlyStorage>(storage: &S) -> StdResult<u128> {
+    let config = ReadonlyVersionedStorage::new(CONFIG_KEY, storage);
+    read_u128(&config, KEY_REWARD_PER_TOKEN)
+}
+
+pub fn write_reward_per_token<S: Storage>(storage: &mut S, reward_per_token: u128) {
+    let mut config = VersionedStorage::new(CONFIG_KEY, storage);
+    config.set(KEY_REWARD_PER_TOKEN, &reward_per_token.to_be_bytes());
+}
+
+/// Returns `account`'s checkpoint moved forward to the current `reward_per_token`, as if it
+/// held `balance` since the last one
+fn current_checkpoint<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+    balance: u128,
+) -> StdResult<RewardCheckpoint> {
+    let reward_per_token = read_reward_per_token(storage)?;
+    let store = ReadonlyVersionedStorage::new(PREFIX_REWARDS, storage);
+    let checkpoint: RewardCheckpoint = TypedStore::attach(&store)
+        .may_load(account.as_slice())?
+        .unwrap_or_default();
+
+    let earned = balance
+        .checked_mul(reward_per_token - checkpoint.reward_per_token)
+        .ok_or_else(|| generic_err("Reward overflow"))?
+        / REWARD_SCALE;
+    let accrued = checkpoint
+        .accrued
+        .checked_add(earned)
+        .ok_or_else(|| generic_err("Reward overflow"))?;
+
+    Ok(RewardCheckpoint {
+        reward_per_token,
+        accrued,
+    })
+}
+
+fn settle_rewards<S: Storage>(storage: &mut S, account: &CanonicalAddr, balance: u128) -> StdResult<()> {
+    let checkpoint = current_checkpoint(storage, account, balance)?;
+    let mut store = VersionedStorage::new(PREFIX_REWARDS, storage);
+    TypedStoreMut::attach(&mut store).store(account.as_slice(), &checkpoint)
+}
+
+/// Rewards `account` can claim right now
+pub fn pending_rewards<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> StdResult<u128> {
+    let balance = ReadonlyBalances::from_storage(storage).balance(account)?;
+    Ok(current_checkpoint(storage, account, balance)?.accrued)
+}
+
+/// Resets `account`'s rewards to zero and returns how much they were
+pub fn take_rewards<S: Storage>(storage: &mut S, account: &CanonicalAddr) -> StdResult<u128> {
+    let balance = ReadonlyBalances::from_storage(&*storage).balance(account)?;
+    let mut checkpoint = current_checkpoint(storage, account, balance)?;
+    let rewards = checkpoint.accrued;
+    checkpoint.accrued = 0;
+
+    let mut store = VersionedStorage::new(PREFIX_REWARDS, storage);
+    TypedStoreMut::attach(&mut store).store(account.as_slice(), &checkpoint)?;
+    Ok(rewards)
+}
+
+/// Allowances are stored under `KEY_PREFIX_ALLOWANCE`, followed by the owner's length, the owner
+/// and the spender. Allowances written before that are nested under the owner in the
+/// `PREFIX_ALLOWANCES` namespace, and are moved the first time they change
+pub fn read_allowance<S: ReadonlyStorage>(
+    store: &S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+) -> StdResult<u128> {
+    let key = allowance_key(owner, spender);
+    debug_access("read", &[KEY_PREFIX_ALLOWANCE], &key[KEY_PREFIX_ALLOWANCE.len()..]);
+    match store.get(&key) {
+        Some(allowance_bytes) => bytes_to_u128(&allowance_bytes),
+        None => {
+            let allowances_store = ReadonlyVersionedStorage::new(PREFIX_ALLOWANCES, store);
+            let owner_store = ReadonlyPrefixedStorage::new(owner.as_slice(), &allowances_store);
+            read_u128(&owner_store, spender.as_slice())
+        }
+    }
+}
+
+/// Reads the allowance `owner` gave `spender`, and replaces it with what `update` returns. A
+/// check and the change it guards go in one closure, so they always see the same allowance.
+/// Nothing is written if `update` fails
+pub fn update_allowance<S: Storage, F>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+    update: F,
+) -> StdResult<u128>
+where
+    F: FnOnce(u128) -> StdResult<u128>,
+{
+    let allowance = update(read_allowance(store, owner, spender)?)?;
+    write_allowance(store, owner, spender, allowance)?;
+    Ok(allowance)
+}
+
+fn write_allowance<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+    amount: u128,
+) -> StdResult<()> {
+    let key = allowance_key(owner, spender);
+    debug_access("read", &[KEY_PREFIX_ALLOWANCE], &key[KEY_PREFIX_ALLOWANCE.len()..]);
+    let moved = store.get(&key).is_some();
+    debug_access("write", &[KEY_PREFIX_ALLOWANCE], &key[KEY_PREFIX_ALLOWANCE.len()..]);
+    store.set(&key, &amount.to_be_bytes());
+
+    if !moved {
+        remove_legacy_allowance(store, owner, spender);
+    }
+
+    let mut spenders = read_allowance_spenders(store, owner)?;
+    let listed = spenders.contains(spender);
+    if amount > 0 && !listed {
+        spenders.push(spender.clone());
+    } else if amount == 0 && listed {
+        spenders.retain(|listed_spender| listed_spender != spender);
+    }
+    // written even if nothing changed, so every allowance update touches the same keys
+    let mut spenders_store = VersionedStorage::new(PREFIX_ALLOWANCE_SPENDERS, store);
+    TypedStoreMut::attach(&mut spenders_store).store(owner.as_slice(), &spenders)
+}
+
+/// The spenders `owner` gave a nonzero allowance to. Allowances that haven't changed since the
+/// list was introduced are missing from it
+pub fn read_allowance_spenders<S: ReadonlyStorage>(
+    store: &S,
+    owner: &CanonicalAddr,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let spenders_store = ReadonlyVersionedStorage::new(PREFIX_ALLOWANCE_SPENDERS, store);
+    Ok(TypedStore::attach(&spenders_store)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default())
+}
+
+/// Deletes every allowance `owner` gave that is in the spender list or to one of
+/// `legacy_spenders`, and the list itself. Allowances that haven't changed since the list was
+/// introduced are only found through `legacy_spenders`, storage can't be scanned for them
+pub fn remove_allowances<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    legacy_spenders: &[CanonicalAddr],
+) -> StdResult<()> {
+    let mut spenders = read_allowance_spenders(store, owner)?;
+    spenders.extend_from_slice(legacy_spenders);
+    for spender in spenders {
+        let key = allowance_key(owner, &spender);
+        debug_access("remove", &[KEY_PREFIX_ALLOWANCE], &key[KEY_PREFIX_ALLOWANCE.len()..]);
+        store.remove(&key);
+        remove_legacy_allowance(store, owner, &spender);
+    }
+    let mut spenders_store = VersionedStorage::new(PREFIX_ALLOWANCE_SPENDERS, store);
+    TypedStoreMut::<Vec<CanonicalAddr>, _>::attach(&mut spenders_store).remove(owner.as_slice());
+    Ok(())
+}
+
+fn remove_legacy_allowance<S: Storage>(store: &mut S, owner: &CanonicalAddr, spender: &CanonicalAddr) {
+    let mut allowances_store = VersionedStorage::new(PREFIX_ALLOWANCES, store);
+    let mut owner_store = PrefixedStorage::new(owner.as_slice(), &mut allowances_store);
+    owner_store.remove(spender.as_slice());
+}
+
+fn allowance_key(owner: &CanonicalAddr, spender: &CanonicalAddr) -> Vec<u8> {
+    [
+        KEY_PREFIX_ALLOWANCE,
+        &[owner.len() as u8],
+        owner.as_slice(),
+        spender.as_slice(),
+    ]
+    .concat()
+}
+
+/// Viewing keys are never stored, only their hashes
+pub fn write_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr, key: &ViewingKey) {
+    let mut vk_store = VersionedStorage::new(PREFIX_VIEW_KEY, store);
+    vk_store.set(owner.as_slice(), key.to_hashed().as_ref());
+}
+
+pub fn read_viewing_key<S: ReadonlyStorage>(store: &S, owner: &CanonicalAddr) -> Option<Vec<u8>> {
+    let vk_store = ReadonlyVersionedStorage::new(PREFIX_VIEW_KEY, store);
+    vk_store.get(owner.as_slice())
+}
+
+pub fn remove_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr) {
+    let mut vk_store = VersionedStorage::new(PREFIX_VIEW_KEY, store);
+    vk_store.remove(owner.as_slice());
+}
+
+// Converts 16 bytes value into u128
+// Errors if data found that is not 16 bytes
+pub fn bytes_to_u128(data: &[u8]) -> StdResult<u128> {
+    match data.try_into() {
+        Ok(bytes) => Ok(u128::from_be_bytes(bytes)),
+        Err(_) => Err(generic_err(
+            "Corrupted data found. 16 byte expected.",
+        )),
+    }
+}
+
+// Reads 16 byte storage value into u128
+// Returns zero if key does not exist. Errors if data found that is not 16 bytes
+pub fn read_u128<S: ReadonlyStorage>(store: &S, key: &[u8]) -> StdResult<u128> {
+    let result = store.get(key);
+    match result {
+        Some(data) => bytes_to_u128(&data),
+        None => Ok(0u128),
+    }
+}
+
+const LEN_KEY: &[u8] = b"len";
+const START_KEY: &[u8] = b"start";
+
+/// An append-only list of items, stored as a length and one entry per index. Pushing costs a
+/// single write no matter how long the list already is. The oldest items can be dropped, in
+/// which case `start` is the index of the oldest item still stored
+pub struct AppendStoreMut<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: Storage,
+{
+    storage: &'a mut S,
+    start: u32,
+    end: u32,
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T, S> AppendStoreMut<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: Storage,
+{
+    /// Opens the store in `storage`, initializing it as empty if it doesn't exist yet
+    pub fn attach_or_create(storage: &'a mut S) -> StdResult<Self> {
+        let end = match storage.get(LEN_KEY) {
+            Some(len_bytes) => bytes_to_u32(&len_bytes)?,
+            None => {
+                storage.set(LEN_KEY, &0u32.to_be_bytes());
+                0
+            }
+        };
+        let start = read_start(&*storage)?;
+
+        Ok(Self {
+            storage,
+            start,
+            end,
+            item_type: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end - self.start
+    }
+
+    /// The index `push` returned for the oldest item still stored
+    pub fn first_index(&self) -> u32 {
+        self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the item at `pos`, counting from the oldest item still stored
+    pub fn get_at(&self, pos: u32) -> StdResult<T> {
+        if pos >= self.len() {
+            return Err(generic_err(format!("Index {} is out of bounds", pos)));
+        }
+        get_item(&*self.storage, self.start + pos, self.end)
+    }
+
+    /// Appends `item` and returns the index it was stored at, which stays valid for
+    /// `AppendStore::get_pushed` until the item is truncated away
+    pub fn push(&mut self, item: &T) -> StdResult<u32> {
+        let item_bytes = bincode2::serialize(item)
+            .map_err(|_| generic_err("Failed to serialize item"))?;
+        let index = self.end;
+        self.storage.set(&index.to_be_bytes(), &item_bytes);
+
+        self.end = self
+            .end
+            .checked_add(1)
+            .ok_or_else(|| generic_err("Append store is full"))?;
+        self.storage.set(LEN_KEY, &self.end.to_be_bytes());
+
+        Ok(index)
+    }
+
+    /// Deletes the oldest items so that at most `keep_last` remain. Returns how many were deleted
+    pub fn truncate_front(&mut self, keep_last: u32) -> u32 {
+        if self.len() <= keep_last {
+            return 0;
+        }
+
+        let new_start = self.end - keep_last;
+        for pos in self.start..new_start {
+            self.storage.remove(&pos.to_be_bytes());
+        }
+        let removed = new_start - self.start;
+
+        self.start = new_start;
+        self.storage.set(START_KEY, &self.start.to_be_bytes());
+
+        removed
+    }
+
+    /// Rewrites the newest item and the length with their current values
+    pub fn touch(&mut self) -> StdResult<()> {
+        if self.end > self.start {
+            let last = self.end - 1;
+            if let Some(item_bytes) = self.storage.get(&last.to_be_bytes()) {
+                self.storage.set(&last.to_be_bytes(), &item_bytes);
+            }
+        }
+        self.storage.set(LEN_KEY, &self.end.to_be_bytes());
+        Ok(())
+    }
+}
+
+/// Read-only view of an `AppendStoreMut`
+pub struct AppendStore<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    storage: &'a S,
+    start: u32,
+    end: u32,
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T, S> AppendStore<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    /// Opens the store in `storage`, returns `None` if nothing was ever pushed to it
+    pub fn attach(storage: &'a S) -> Option<StdResult<Self>> {
+        let len_bytes = storage.get(LEN_KEY)?;
+        let store = bytes_to_u32(&len_bytes).and_then(|end| {
+            Ok(Self {
+                storage,
+                start: read_start(storage)?,
+                end,
+                item_type: PhantomData,
+            })
+        });
+        Some(store)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end - self.start
+    }
+
+    /// The index `push` returned for the oldest item still stored
+    pub fn first_index(&self) -> u32 {
+        self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the item at `pos`, counting from the oldest item still stored
+    pub fn get_at(&self, pos: u32) -> StdResult<T> {
+        if pos >= self.len() {
+            return Err(generic_err(format!("Index {} is out of bounds", pos)));
+        }
+        get_item(self.storage, self.start + pos, self.end)
+    }
+
+    /// Returns the item `push` stored at `index`, or `None` if it was truncated away since
+    pub fn get_pushed(&self, index: u32) -> Option<StdResult<T>> {
+        if index < self.start {
+            return None;
+        }
+        Some(get_item(self.storage, index, self.end))
+    }
+
+    /// Iterates over the items from oldest to newest, use `.rev()` for newest first
+    pub fn iter(&self) -> AppendStoreIter<'a, T, S> {
+        AppendStoreIter {
+            storage: self.storage,
+            start: self.start,
+            end: self.end,
+            item_type: PhantomData,
+        }
+    }
+}
+
+pub struct AppendStoreIter<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    storage: &'a S,
+    start: u32,
+    end: u32,
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T, S> Iterator for AppendStoreIter<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    type Item = StdResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+            return None;
+        }
+        let item = get_item(self.storage, self.start, self.end);
+        self.start += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.end - self.start) as usize;
+        (len, Some(len))
+    }
+
+    // skipping doesn't need to read the skipped items
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let remaining = (self.end - self.start) as usize;
+        if n >= remaining {
+            self.start = self.end;
+            return None;
+        }
+        self.start += n as u32;
+        self.next()
+    }
+}
+
+impl<'a, T, S> ExactSizeIterator for AppendStoreIter<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+}
+
+impl<'a, T, S> DoubleEndedIterator for AppendStoreIter<'a, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    S: ReadonlyStorage,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(get_item(self.storage, self.end, self.end + 1))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        let remaining = (self.end - self.start) as usize;
+        if n >= remaining {
+            self.end = self.start;
+            return None;
+        }
+        self.end -= n as u32;
+        self.next_back()
+    }
+}
+
+fn get_item<T: DeserializeOwned, S: ReadonlyStorage>(storage: &S, pos: u32, len: u32) -> StdResult<T> {
+    if pos >= len {
+        return Err(generic_err(format!("Index {} is out of bounds", pos)));
+    }
+
+    let item_bytes = storage
+        .get(&pos.to_be_bytes())
+        .ok_or_else(|| generic_err(format!("No item stored at index {}", pos)))?;
+    bincode2::deserialize(&item_bytes).map_err(|_| generic_err("Corrupted item in append store"))
+}
+
+fn read_start<S: ReadonlyStorage>(storage: &S) -> StdResult<u32> {
+    match storage.get(START_KEY) {
+        Some(start_bytes) => bytes_to_u32(&start_bytes),
+        None => Ok(0),
+    }
+}
+
+fn bytes_to_u32(data: &[u8]) -> StdResult<u32> {
+    match data.try_into() {
+        Ok(bytes) => Ok(u32::from_be_bytes(bytes)),
+        Err(_) => Err(generic_err("Corrupted data found. 4 byte expected.")),
+    }
+}
+
+/// A transfer that has left the sender's balance but hasn't reached the recipient yet. The
+/// recipient can claim it until `expiry` (block time, in seconds), after which only the sender
+/// can take it back
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingTransfer {
+    pub sender: CanonicalAddr,
+    pub recipient: CanonicalAddr,
+    pub amount: u128,
+    pub expiry: u64,
+}
+
+pub fn store_pending_transfer<S: Storage>(storage: &mut S, pending: &PendingTransfer) -> StdResult<u64> {
+    let mut store = VersionedStorage::new(PREFIX_PENDING, storage);
+
+    let id = TypedStore::<u64, _>::attach(&store).may_load(KEY_PENDING_COUNT)?.unwrap_or(0) + 1;
+
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), pending)?;
+    TypedStoreMut::attach(&mut store).store(KEY_PENDING_COUNT, &id)?;
+
+    Ok(id)
+}
+
+pub fn get_pending_transfer<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<Option<PendingTransfer>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_PENDING, storage);
+    TypedStore::attach(&store).may_load(&id.to_be_bytes())
+}
+
+pub fn remove_pending_transfer<S: Storage>(storage: &mut S, id: u64) {
+    let mut store = VersionedStorage::new(PREFIX_PENDING, storage);
+    store.remove(&id.to_be_bytes());
+}
+
+/// Funds locked under the SHA-256 hash of a secret. Whoever presents the preimage before `expiry`
+/// receives the funds, after that they go back to the creator
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HashClaim {
+    pub creator: CanonicalAddr,
+    pub amount: u128,
+    pub expiry: u64,
+}
+
+pub fn store_claim<S: Storage>(storage: &mut S, hash: &[u8], claim: &HashClaim) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_CLAIMS, storage);
+    TypedStoreMut::attach(&mut store).store(hash, claim)
+}
+
+pub fn get_claim<S: ReadonlyStorage>(storage: &S, hash: &[u8]) -> StdResult<Option<HashClaim>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_CLAIMS, storage);
+    TypedStore::attach(&store).may_load(hash)
+}
+
+pub fn remove_claim<S: Storage>(storage: &mut S, hash: &[u8]) {
+    let mut store = VersionedStorage::new(PREFIX_CLAIMS, storage);
+    store.remove(hash);
+}
+
+/// How many times an account sent, received, deposited and redeemed tokens. Counts aren't
+/// reduced when the history is pruned, so a change tells wallets to fetch the history again
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct AccountStats {
+    pub transfers_in: u64,
+    pub transfers_out: u64,
+    pub deposits: u64,
+    pub redeems: u64,
+}
+
+pub fn get_account_stats<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> StdResult<AccountStats> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_ACCOUNT_STATS, storage);
+    Ok(TypedStore::attach(&store).may_load(account.as_slice())?.unwrap_or_default())
+}
+
+/// Applies `update` to `account`'s counters and stores the result
+pub fn update_account_stats<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    update: impl FnOnce(&mut AccountStats),
+) -> StdResult<()> {
+    let mut stats = get_account_stats(storage, account)?;
+    update(&mut stats);
+
+    let mut store = VersionedStorage::new(PREFIX_ACCOUNT_STATS, storage);
+    TypedStoreMut::attach(&mut store).store(account.as_slice(), &stats)
+}
+
+/// How much an account received and sent in transfers over its lifetime. Kept separately from
+/// `AccountStats` so stats stored before keep their layout
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AccountTotals {
+    pub received: u128,
+    pub sent: u128,
+}
+
+pub fn get_account_totals<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> StdResult<AccountTotals> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_ACCOUNT_TOTALS, storage);
+    Ok(TypedStore::attach(&store).may_load(account.as_slice())?.unwrap_or_default())
+}
+
+/// Applies `update` to `account`'s totals and stores the result
+fn update_account_totals<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    update: impl FnOnce(&mut AccountTotals),
+) -> StdResult<()> {
+    let mut totals = get_account_totals(storage, account)?;
+    update(&mut totals);
+
+    let mut store = VersionedStorage::new(PREFIX_ACCOUNT_TOTALS, storage);
+    TypedStoreMut::attach(&mut store).store(account.as_slice(), &totals)
+}
+
+pub fn set_receiver_hash<S: Storage>(storage: &mut S, account: &CanonicalAddr, code_hash: String) {
+    let mut store = VersionedStorage::new(PREFIX_RECEIVERS, storage);
+    store.set(account.as_slice(), code_hash.as_bytes());
+}
+
+pub fn get_receiver_hash<S: ReadonlyStorage>(storage: &S, account: &CanonicalAddr) -> Option<StdResult<String>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_RECEIVERS, storage);
+    store.get(account.as_slice()).map(|data| {
+        String::from_utf8(data).map_err(|_| generic_err("Corrupted receiver code hash"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockApi;
+
+    #[test]
+    fn compact_transfers_have_a_fixed_size() {
+        let record = |api: &MockApi, amount| {
+            let sender = api.canonical_address(&HumanAddr::from("alice")).unwrap();
+            let receiver = api.canonical_address(&HumanAddr::from("bob")).unwrap();
+            let tx = CompactTx::new(&sender, &receiver, &Uint128(amount), 1_600_000_000).unwrap();
+            assert!(tx.involves(&receiver));
+            assert_eq!(tx.direction_for(&sender), TxDirection::Sent);
+            tx
+        };
+        let full = record(&MockApi::new(ADDRESS_WIDTH), 1);
+        let full_len = bincode2::serialize(&full).unwrap().len();
+        assert_eq!(full_len, 2 * ADDRESS_WIDTH + 2 + 16 + 8);
+
+        for &length in &[8, ADDRESS_WIDTH - 1] {
+            let api = MockApi::new(length);
+            let short = record(&api, u128::MAX);
+            assert_eq!(bincode2::serialize(&short).unwrap().len(), full_len);
+
+            let tx = short.into_tx(&api, "SECSEC", None, TxDirection::Sent).unwrap();
+            assert_eq!(tx.sender, HumanAddr::from("alice"));
+            assert_eq!(tx.receiver, HumanAddr::from("bob"));
+        }
+
+        let long = CanonicalAddr::from(vec![5; ADDRESS_WIDTH + 1]);
+        assert!(CompactTx::new(&long, &long, &Uint128(1), 0).is_err());
+    }
+}
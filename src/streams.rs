@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr,
+    Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::compliance::check_account_compliance;
+use crate::freeze::check_not_frozen;
+use crate::state::{
+    store_transfer, Balances, ReadonlyVersionedStorage, TypedStore, TypedStoreMut,
+    VersionedStorage,
+};
+
+pub const PREFIX_STREAMS: &[u8] = b"streams";
+pub const KEY_STREAM_COUNT: &[u8] = b"stream_count";
+
+/// Tokens taken from the sender that accrue to the recipient linearly between `start` and `end`
+/// (block times, in seconds). The recipient withdraws what accrued whenever they like
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Stream {
+    pub sender: CanonicalAddr,
+    pub recipient: CanonicalAddr,
+    pub total: u128,
+    pub withdrawn: u128,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Stream {
+    /// How much of the stream has accrued to the recipient at `time`, withdrawn or not
+    pub fn streamed_at(&self, time: u64) -> u128 {
+        if time <= self.start {
+            return 0;
+        }
+        if time >= self.end {
+            return self.total;
+        }
+
+        let elapsed = (time - self.start) as u128;
+        let duration = (self.end - self.start) as u128;
+        // split up so that `total * elapsed` can't overflow
+        self.total / duration * elapsed + self.total % duration * elapsed / duration
+    }
+}
+
+fn store_stream<S: Storage>(storage: &mut S, stream: &Stream) -> StdResult<u64> {
+    let mut store = VersionedStorage::new(PREFIX_STREAMS, storage);
+
+    let id = TypedStore::<u64, _>::attach(&store).may_load(KEY_STREAM_COUNT)?.unwrap_or(0) + 1;
+
+    TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), stream)?;
+    TypedStoreMut::attach(&mut store).store(KEY_STREAM_COUNT, &id)?;
+
+    Ok(id)
+}
+
+fn get_stream<S: ReadonlyStorage>(storage: &S, id: u64) -> StdResult<Stream> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_STREAMS, storage);
+    TypedStore::attach(&store)
+        .may_load(&id.to_be_bytes())?
+        .ok_or_else(|| generic_err(format!("No stream with id {}", id)))
+}
+
+fn update_stream<S: Storage>(storage: &mut S, id: u64, stream: Option<&Stream>) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_STREAMS, storage);
+    match stream {
+        Some(stream) => TypedStoreMut::attach(&mut store).store(&id.to_be_bytes(), stream),
+        None => {
+            store.remove(&id.to_be_bytes());
+            Ok(())
+        }
+    }
+}
+
+fn credit<S: Storage>(storage: &mut S, account: &CanonicalAddr, amount: u128) -> StdResult<()> {
+    let mut balances = Balances::from_storage(storage);
+    let account_balance = balances
+        .balance(account)?
+        .checked_add(amount)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    balances.set_balance(account, account_balance)
+}
+
+/// Credits the recipient with what accrued since their last withdrawal and records it as a
+/// transfer from the sender
+fn pay_out<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    stream: &mut Stream,
+    time: u64,
+) -> StdResult<u128> {
+    let amount = stream.streamed_at(time) - stream.withdrawn;
+    if amount == 0 {
+        return Ok(0);
+    }
+    stream.withdrawn += amount;
+
+    credit(&mut deps.storage, &stream.recipient, amount)?;
+
+    store_transfer(
+        &mut deps.storage,
+        &stream.sender,
+        &stream.recipient,
+        &Uint128(amount),
+        time,
+    )?;
+    Ok(amount)
+}
+
+pub fn try_create_stream<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+    start: u64,
+    end: u64,
+) -> StdResult<HandleResponse> {
+    let sender = &env.message.sender;
+    let amount_raw = amount.u128();
+
+    if end <= start || end <= env.block.time {
+        return Err(generic_err("A stream must end after it starts, in the future"));
+    }
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = balances.balance(sender)?;
+    let account_balance = account_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient funds: balance={}, required={}",
+            account_balance, amount_raw
+        ))
+    })?;
+    balances.set_balance(sender, account_balance)?;
+
+    let id = store_stream(
+        &mut deps.storage,
+        &Stream {
+            sender: sender.clone(),
+            recipient: deps.api.canonical_address(recipient)?,
+            total: amount_raw,
+            withdrawn: 0,
+            start,
+            end,
+        },
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "create_stream"), log("id", id)],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Pays the recipient what accrued so far. Only the recipient can do this
+pub fn try_withdraw_from_stream<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let mut stream = get_stream(&deps.storage, id)?;
+    if stream.recipient != env.message.sender {
+        return Err(unauthorized());
+    }
+    check_not_frozen(&deps.storage, &stream.recipient)?;
+    check_account_compliance(&deps.storage, &stream.recipient)?;
+
+    let amount = pay_out(deps, &mut stream, env.block.time)?;
+    if amount == 0 {
+        return Err(generic_err("Nothing has accrued since the last withdrawal"));
+    }
+
+    if stream.withdrawn == stream.total {
+        update_stream(&mut deps.storage, id, None)?;
+    } else {
+        update_stream(&mut deps.storage, id, Some(&stream))?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "withdraw_from_stream"),
+            log("id", id),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Stops the stream. The recipient gets what accrued so far and the sender the rest. Only the
+/// sender can do this
+pub fn try_cancel_stream<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let mut stream = get_stream(&deps.storage, id)?;
+    if stream.sender != env.message.sender {
+        return Err(unauthorized());
+    }
+
+    pay_out(deps, &mut stream, env.block.time)?;
+    let refund = stream.total - stream.withdrawn;
+    credit(&mut deps.storage, &stream.sender, refund)?;
+
+    update_stream(&mut deps.storage, id, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "cancel_stream"),
+            log("id", id),
+            log("refund", refund),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
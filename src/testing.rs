@@ -0,0 +1,244 @@
+//! Helpers for testing contracts that integrate with the token against mock dependencies, so
+//! integrators don't need a chain or a copy of the contract's internals. Not available when
+//! building for wasm
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+
+use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier};
+use cosmwasm_std::{
+    from_binary, generic_err, Coin, Env, Extern, HandleResponse, HumanAddr, ReadonlyStorage,
+    StdResult, Storage, Uint128,
+};
+
+use crate::contract::{handle, init, query};
+use crate::msg::{HandleMsg, InitMsg, InitialBalance, QueryMsg};
+use crate::viewing_key::API_KEY_LENGTH;
+
+pub type MockDeps = Extern<TestStorage, MockApi, MockQuerier>;
+
+/// In-memory storage that can be cloned, so failed messages can be rolled back like on chain.
+/// Counts what it's asked to do, see `take_ops`
+#[derive(Default, Clone, Debug)]
+pub struct TestStorage {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+    ops: Cell<StorageOps>,
+}
+
+/// Storage operations and the bytes they moved, keys included
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct StorageOps {
+    pub reads: u64,
+    pub writes: u64,
+    pub removes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl StorageOps {
+    /// What the operations would cost with the Cosmos SDK's default KV store gas prices
+    pub fn gas(&self) -> u64 {
+        self.reads * 1_000
+            + self.bytes_read * 3
+            + self.writes * 2_000
+            + self.bytes_written * 30
+            + self.removes * 1_000
+    }
+}
+
+impl TestStorage {
+    /// The operations since the last call
+    pub fn take_ops(&self) -> StorageOps {
+        self.ops.take()
+    }
+
+    fn count(&self, update: impl FnOnce(&mut StorageOps)) {
+        let mut ops = self.ops.get();
+        update(&mut ops);
+        self.ops.set(ops);
+    }
+}
+
+impl ReadonlyStorage for TestStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.data.get(key).cloned();
+        let len = key.len() + value.as_ref().map_or(0, Vec::len);
+        self.count(|ops| {
+            ops.reads += 1;
+            ops.bytes_read += len as u64;
+        });
+        value
+    }
+}
+
+impl Storage for TestStorage {
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.count(|ops| {
+            ops.writes += 1;
+            ops.bytes_written += (key.len() + value.len()) as u64;
+        });
+        self.data.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.count(|ops| ops.removes += 1);
+        self.data.remove(key);
+    }
+}
+
+/// Length of the mock API's canonical addresses
+pub const CANONICAL_LENGTH: usize = 20;
+
+/// An `InitMsg` for a plain 6 decimal token with `initial_balances` and nothing else set up
+pub fn default_init_msg(initial_balances: &[(&str, u128)]) -> InitMsg {
+    InitMsg {
+        name: "Secret Secret".to_string(),
+        symbol: "SECSEC".to_string(),
+        decimals: 6,
+        initial_balances: initial_balances
+            .iter()
+            .map(|(address, amount)| InitialBalance {
+                address: HumanAddr::from(*address),
+                amount: Uint128(*amount),
+            })
+            .collect(),
+        history_limit: None,
+        callback: None,
+        staking: None,
+        admin: None,
+        supported_denoms: None,
+        compliance: None,
+        clawback: None,
+        freezing: None,
+        prng_seed: None,
+    }
+}
+
+/// Instantiates the token with `msg` as `admin`, on fresh mock dependencies
+pub fn init_token(admin: &str, msg: InitMsg) -> StdResult<MockDeps> {
+    let mocks = mock_dependencies(CANONICAL_LENGTH, &[]);
+    let mut deps = Extern {
+        storage: TestStorage::default(),
+        api: mocks.api,
+        querier: mocks.querier,
+    };
+    let env = mock_env(&deps.api, admin, &[]);
+    init(&mut deps, env, msg)?;
+    Ok(deps)
+}
+
+/// The env of a message from `sender` with `funds` attached, executed at block time `time`
+pub fn env_at(deps: &MockDeps, sender: &str, funds: &[Coin], time: u64) -> Env {
+    let mut env = mock_env(&deps.api, sender, funds);
+    env.block.time = time;
+    env
+}
+
+/// Executes `msg` as `sender`, without funds
+pub fn handle_as(deps: &mut MockDeps, sender: &str, msg: HandleMsg) -> StdResult<HandleResponse> {
+    handle_with_funds(deps, sender, &[], msg)
+}
+
+/// Executes `msg` as `sender`, with `funds` attached. If it fails, whatever it wrote is rolled
+/// back
+pub fn handle_with_funds(
+    deps: &mut MockDeps,
+    sender: &str,
+    funds: &[Coin],
+    msg: HandleMsg,
+) -> StdResult<HandleResponse> {
+    let env = mock_env(&deps.api, sender, funds);
+    let snapshot = deps.storage.clone();
+    let res = handle(deps, env, msg);
+    if res.is_err() {
+        deps.storage = snapshot;
+    }
+    res
+}
+
+/// Sets `key` as `address`'s viewing key. Fails if the contract rejects the key, which it
+/// otherwise only reports in the logs
+pub fn set_viewing_key(deps: &mut MockDeps, address: &str, key: &str) -> StdResult<()> {
+    let msg = HandleMsg::SetViewingKey {
+        key: key.to_string(),
+    };
+    let res = handle_as(deps, address, msg)?;
+    if res.log.iter().any(|log| log.key == "result" && log.value.trim_end() != "success") {
+        return Err(generic_err(format!(
+            "Viewing keys must be {} characters long",
+            API_KEY_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// Runs `msg` and decodes its JSON answer. Fails to decode the `ViewingKeyError` answer to a
+/// wrong viewing key
+pub fn query_answer<T: DeserializeOwned>(deps: &MockDeps, msg: QueryMsg) -> StdResult<T> {
+    from_binary(&query(deps, msg)?)
+}
+
+/// `address`'s balance as the `Balance` query shows it, e.g. "2 SECSEC", without the padding
+pub fn query_balance(deps: &MockDeps, address: &str, key: &str) -> StdResult<String> {
+    let msg = QueryMsg::Balance {
+        address: HumanAddr::from(address),
+        key: key.to_string(),
+        denom: None,
+    };
+    let answer = query(deps, msg)?;
+    Ok(String::from_utf8_lossy(answer.as_slice()).trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::TransferCountResponse;
+
+    const ALICE_KEY: &str = "api_key_AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const BOB_KEY: &str = "api_key_BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+
+    #[test]
+    fn transfer_between_mock_accounts() {
+        let mut deps = init_token("admin", default_init_msg(&[("alice", 5_000_000)])).unwrap();
+        set_viewing_key(&mut deps, "alice", ALICE_KEY).unwrap();
+        set_viewing_key(&mut deps, "bob", BOB_KEY).unwrap();
+
+        let transfer = HandleMsg::Transfer {
+            recipient: HumanAddr::from("bob"),
+            amount: Uint128(2_000_000),
+        };
+        handle_as(&mut deps, "alice", transfer).unwrap();
+
+        assert_eq!(query_balance(&deps, "alice", ALICE_KEY).unwrap(), "3 SECSEC");
+        assert_eq!(query_balance(&deps, "bob", BOB_KEY).unwrap(), "2 SECSEC");
+
+        let count: TransferCountResponse = query_answer(
+            &deps,
+            QueryMsg::TransferCount {
+                address: HumanAddr::from("bob"),
+                key: BOB_KEY.to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(count.count, 1);
+    }
+
+    #[test]
+    fn transfer_beyond_balance_fails() {
+        let mut deps = init_token("admin", default_init_msg(&[("alice", 100)])).unwrap();
+
+        let transfer = HandleMsg::Transfer {
+            recipient: HumanAddr::from("bob"),
+            amount: Uint128(101),
+        };
+        assert!(handle_as(&mut deps, "alice", transfer).is_err());
+    }
+
+    #[test]
+    fn short_viewing_key_is_rejected() {
+        let mut deps = init_token("admin", default_init_msg(&[])).unwrap();
+        assert!(set_viewing_key(&mut deps, "alice", "too short").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::incoming::check_incoming;
+use crate::state::{
+    store_transfer, Balances, ReadonlyVersionedStorage, TypedStore,
+    TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_LOCKED: &[u8] = b"locked";
+
+/// Tokens that already belong to the recipient but can't be spent before `unlock_at` (block
+/// time, in seconds)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LockedTransfer {
+    pub sender: CanonicalAddr,
+    pub amount: u128,
+    pub unlock_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockedAmount {
+    pub sender: HumanAddr,
+    pub amount: Uint128,
+    pub unlock_at: u64,
+}
+
+/// Locks that reached `unlock_at` are listed until the account's next transaction releases them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockedBalanceResponse {
+    pub locked: Uint128,
+    pub locks: Vec<LockedAmount>,
+}
+
+pub fn get_locked_transfers<S: ReadonlyStorage>(
+    storage: &S,
+    account: &CanonicalAddr,
+) -> StdResult<Vec<LockedTransfer>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_LOCKED, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(account.as_slice())?
+        .unwrap_or_default())
+}
+
+fn set_locked_transfers<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    locks: &[LockedTransfer],
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_LOCKED, storage);
+    if locks.is_empty() {
+        TypedStoreMut::<Vec<LockedTransfer>, _>::attach(&mut store).remove(account.as_slice());
+        Ok(())
+    } else {
+        TypedStoreMut::attach(&mut store).store(account.as_slice(), &locks.to_vec())
+    }
+}
+
+/// Moves every lock of `account` that reached its unlock time into its balance. Runs before
+/// each of the account's transactions, so unlocked tokens are spendable right away
+pub fn release_unlocked<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    now: u64,
+) -> StdResult<()> {
+    let locks = get_locked_transfers(storage, account)?;
+    let (released, locked): (Vec<_>, Vec<_>) =
+        locks.into_iter().partition(|lock| lock.unlock_at <= now);
+    if released.is_empty() {
+        return Ok(());
+    }
+
+    let mut balances = Balances::from_storage(storage);
+    let mut account_balance = balances.balance(account)?;
+    for lock in released.iter() {
+        account_balance = account_balance
+            .checked_add(lock.amount)
+            .ok_or_else(|| generic_err("Balance overflow"))?;
+    }
+    balances.set_balance(account, account_balance)?;
+
+    set_locked_transfers(storage, account, &locked)
+}
+
+pub fn try_transfer_locked<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+    unlock_at: u64,
+) -> StdResult<HandleResponse> {
+    if unlock_at <= env.block.time {
+        return Err(generic_err("Unlock time must be in the future"));
+    }
+    let amount_raw = amount.u128();
+    if amount_raw == 0 {
+        return Err(generic_err("Can't lock zero tokens"));
+    }
+
+    let sender_address_raw = &env.message.sender;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    check_incoming(&deps.storage, sender_address_raw, &recipient_address_raw)?;
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let sender_balance = balances.balance(sender_address_raw)?;
+    let sender_balance = sender_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "insufficient funds: balance={}, required={}",
+            sender_balance, amount_raw
+        ))
+    })?;
+    balances.set_balance(sender_address_raw, sender_balance)?;
+
+    let mut locks = get_locked_transfers(&deps.storage, &recipient_address_raw)?;
+    locks.push(LockedTransfer {
+        sender: sender_address_raw.clone(),
+        amount: amount_raw,
+        unlock_at,
+    });
+    set_locked_transfers(&mut deps.storage, &recipient_address_raw, &locks)?;
+
+    store_transfer(
+        &mut deps.storage,
+        sender_address_raw,
+        &recipient_address_raw,
+        amount,
+        env.block.time,
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "transfer_locked"),
+            log("sender", deps.api.human_address(sender_address_raw)?.as_str()),
+            log("recipient", recipient.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_locked_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+
+    let mut locked = 0u128;
+    let mut locks = vec![];
+    for lock in get_locked_transfers(&deps.storage, &address)? {
+        locked = locked
+            .checked_add(lock.amount)
+            .ok_or_else(|| generic_err("Locked balance overflow"))?;
+        locks.push(LockedAmount {
+            sender: deps.api.human_address(&lock.sender)?,
+            amount: Uint128(lock.amount),
+            unlock_at: lock.unlock_at,
+        });
+    }
+
+    to_binary(&LockedBalanceResponse {
+        locked: Uint128(locked),
+        locks,
+    })
+}
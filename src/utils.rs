@@ -2,12 +2,22 @@ use subtle::ConstantTimeEq;
 use core::fmt;
 use serde::export::Formatter;
 use bcrypt_pbkdf::bcrypt_pbkdf;
+use cosmwasm_std::{Binary, HandleResponse, StdResult};
+use sha2::{Digest, Sha256};
 
 
 // 5 rounds == ~300M gas (doesn't work with query) - creation/validation takes ~1.5s
 // 2 rounds == ~120M gas (works with query) - creation/validation takes ~1s
 const DEFAULT_COST: u32 = 2;
-const OUTPUT_SIZE: usize = 24;
+/// The length of viewing key hashes stored before they were hashed with SHA-256
+pub const LEGACY_OUTPUT_SIZE: usize = 24;
+
+/// Every secret the token derives is hashed with its own tag, so a value derived for one
+/// purpose can never pass for another, e.g. a governance key for a viewing key
+pub const VIEWING_KEY_TAG: &[u8] = b"snip20-viewing-key";
+pub const GOVERNANCE_KEY_TAG: &[u8] = b"snip20-governance-key";
+pub const PRNG_SEED_TAG: &[u8] = b"snip20-prng-seed";
+pub const ENTROPY_POOL_TAG: &[u8] = b"snip20-entropy-pool";
 
 pub fn ct_slice_compare(s1: &[u8], s2: &[u8]) -> bool {
     bool::from(s1.ct_eq(s2))
@@ -23,9 +33,79 @@ impl fmt::Display for ConstLenStr {
     }
 }
 
-pub fn create_hashed_password(s1: &String) -> [u8; OUTPUT_SIZE] {
-    let mut output = [0u8; OUTPUT_SIZE];
+/// SHA-256 of `data`, prefixed with `tag` and its length so no tag is a prefix of another's input
+pub fn tagged_hash(tag: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(&[tag.len() as u8]);
+    hasher.update(tag);
+    hasher.update(data);
+
+    let mut result = [0u8; 32];
+    result.copy_from_slice(&hasher.finalize());
+    result
+}
+
+pub fn create_hashed_password(s1: &str) -> [u8; 32] {
+    tagged_hash(VIEWING_KEY_TAG, s1.as_bytes())
+}
+
+/// How viewing keys were hashed before, still checked for keys set back then
+pub fn create_legacy_hashed_password(s1: &str) -> [u8; LEGACY_OUTPUT_SIZE] {
+    let mut output = [0u8; LEGACY_OUTPUT_SIZE];
     let _ = bcrypt_pbkdf(s1, b"bestspiceintheEU", DEFAULT_COST, &mut output);
     output
 }
 
+/// Pads `message` with trailing spaces up to the next multiple of `block_size`. Trailing
+/// whitespace is ignored by JSON parsers, so this only hides the length of the message
+pub fn space_pad(message: &mut Vec<u8>, block_size: usize) -> &mut Vec<u8> {
+    if block_size == 0 {
+        return message;
+    }
+
+    let len = message.len();
+    let surplus = len % block_size;
+    if surplus == 0 {
+        return message;
+    }
+
+    let missing = block_size - surplus;
+    message.reserve(missing);
+    message.extend(std::iter::repeat(b' ').take(missing));
+    message
+}
+
+/// Something a contract answers with, whose length `pad_response` can hide
+pub trait Padded {
+    fn pad(&mut self, block_size: usize);
+}
+
+impl Padded for Binary {
+    fn pad(&mut self, block_size: usize) {
+        space_pad(&mut self.0, block_size);
+    }
+}
+
+/// Pads every log value and the data. Log values have to be trimmed before they're compared
+impl Padded for HandleResponse {
+    fn pad(&mut self, block_size: usize) {
+        for attribute in self.log.iter_mut() {
+            let mut value = std::mem::take(&mut attribute.value).into_bytes();
+            space_pad(&mut value, block_size);
+            // only spaces were added
+            attribute.value = String::from_utf8(value).unwrap_or_default();
+        }
+        if let Some(data) = self.data.as_mut() {
+            data.pad(block_size);
+        }
+    }
+}
+
+/// Pads a successful response to multiples of `block_size`, so its length doesn't tell which
+/// message or query it answers, or what amounts are in it
+pub fn pad_response<T: Padded>(response: StdResult<T>, block_size: usize) -> StdResult<T> {
+    response.map(|mut response| {
+        response.pad(block_size);
+        response
+    })
+}
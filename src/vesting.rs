@@ -0,0 +1,268 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    to_binary, Api, Binary, CanonicalAddr, Extern, HumanAddr, Querier, ReadonlyStorage,
+    StdResult, Storage, Uint128,
+};
+#[cfg(feature = "vesting")]
+use cosmwasm_std::{generic_err, log, Env, HandleResponse};
+
+#[cfg(feature = "vesting")]
+use crate::compliance::check_account_compliance;
+#[cfg(feature = "vesting")]
+use crate::freeze::check_not_frozen;
+#[cfg(feature = "vesting")]
+use crate::incoming::check_incoming;
+use crate::state::{ReadonlyVersionedStorage, TypedStore};
+#[cfg(feature = "vesting")]
+use crate::state::{store_transfer, Balances, ReadonlyBalances, TypedStoreMut, VersionedStorage};
+
+pub const PREFIX_VESTING: &[u8] = b"vesting";
+
+/// Tokens that left the grantor's balance and are released to the recipient linearly between
+/// `start` and `end`, but not before `cliff`. Times are block times, in seconds
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VestingGrant {
+    pub grantor: CanonicalAddr,
+    pub total: u128,
+    pub claimed: u128,
+    pub start: u64,
+    pub cliff: u64,
+    pub end: u64,
+}
+
+impl VestingGrant {
+    /// How much of the grant has been released at `time`, claimed or not
+    pub fn vested_at(&self, time: u64) -> u128 {
+        if time < self.cliff {
+            return 0;
+        }
+        if time >= self.end {
+            return self.total;
+        }
+
+        let elapsed = (time - self.start) as u128;
+        let duration = (self.end - self.start) as u128;
+        // split up so that `total * elapsed` can't overflow
+        self.total / duration * elapsed + self.total % duration * elapsed / duration
+    }
+}
+
+/// A vesting grant as returned by the `VestingSchedules` query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingSchedule {
+    pub grantor: HumanAddr,
+    pub total: Uint128,
+    pub claimed: Uint128,
+    pub start: u64,
+    pub cliff: u64,
+    pub end: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingSchedulesResponse {
+    pub schedules: Vec<VestingSchedule>,
+}
+
+pub fn get_vesting_grants<S: ReadonlyStorage>(
+    storage: &S,
+    recipient: &CanonicalAddr,
+) -> StdResult<Vec<VestingGrant>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_VESTING, storage);
+    Ok(TypedStore::attach(&store)
+        .may_load(recipient.as_slice())?
+        .unwrap_or_default())
+}
+
+#[cfg(feature = "vesting")]
+fn set_vesting_grants<S: Storage>(
+    storage: &mut S,
+    recipient: &CanonicalAddr,
+    grants: &[VestingGrant],
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_VESTING, storage);
+    if grants.is_empty() {
+        TypedStoreMut::<Vec<VestingGrant>, _>::attach(&mut store).remove(recipient.as_slice());
+        Ok(())
+    } else {
+        TypedStoreMut::attach(&mut store).store(recipient.as_slice(), &grants.to_vec())
+    }
+}
+
+/// Locks `amount` of the sender's tokens for `recipient`. Nothing can be claimed for `cliff`
+/// seconds, everything can be claimed after `duration` seconds
+#[cfg(feature = "vesting")]
+pub fn try_transfer_vesting<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: &HumanAddr,
+    amount: &Uint128,
+    cliff: u64,
+    duration: u64,
+) -> StdResult<HandleResponse> {
+    if duration == 0 {
+        return Err(generic_err("Vesting duration must be at least one second"));
+    }
+    if cliff > duration {
+        return Err(generic_err("Vesting cliff must not be after the end of the vesting"));
+    }
+    let amount_raw = amount.u128();
+    if amount_raw == 0 {
+        return Err(generic_err("Can't vest zero tokens"));
+    }
+
+    let sender_address_raw = &env.message.sender;
+    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    check_incoming(&deps.storage, sender_address_raw, &recipient_address_raw)?;
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let sender_balance = balances.balance(sender_address_raw)?;
+    let sender_balance = sender_balance.checked_sub(amount_raw).ok_or_else(|| {
+        generic_err(format!(
+            "insufficient funds: balance={}, required={}",
+            sender_balance, amount_raw
+        ))
+    })?;
+    balances.set_balance(sender_address_raw, sender_balance)?;
+
+    let start = env.block.time;
+    let end = start
+        .checked_add(duration)
+        .ok_or_else(|| generic_err("Vesting ends too far in the future"))?;
+
+    let mut grants = get_vesting_grants(&deps.storage, &recipient_address_raw)?;
+    grants.push(VestingGrant {
+        grantor: sender_address_raw.clone(),
+        total: amount_raw,
+        claimed: 0,
+        start,
+        cliff: start + cliff,
+        end,
+    });
+    set_vesting_grants(&mut deps.storage, &recipient_address_raw, &grants)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "transfer_vesting"),
+            log("sender", deps.api.human_address(sender_address_raw)?.as_str()),
+            log("recipient", recipient.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Moves everything that has vested so far from the sender's grants to their balance
+#[cfg(feature = "vesting")]
+pub fn try_claim_vested<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let recipient_address_raw = &env.message.sender;
+    check_not_frozen(&deps.storage, recipient_address_raw)?;
+    check_account_compliance(&deps.storage, recipient_address_raw)?;
+
+    let mut grants = get_vesting_grants(&deps.storage, recipient_address_raw)?;
+    let mut claims = vec![];
+    for grant in grants.iter_mut() {
+        let claimable = grant.vested_at(env.block.time) - grant.claimed;
+        if claimable > 0 {
+            grant.claimed += claimable;
+            claims.push((grant.grantor.clone(), claimable));
+        }
+    }
+    if claims.is_empty() {
+        return Err(generic_err("Nothing has vested yet"));
+    }
+    grants.retain(|grant| grant.claimed < grant.total);
+    set_vesting_grants(&mut deps.storage, recipient_address_raw, &grants)?;
+
+    let total_claimed = claims
+        .iter()
+        .try_fold(0u128, |sum, (_, amount)| sum.checked_add(*amount))
+        .ok_or_else(|| generic_err("Claimed amount overflow"))?;
+    let account_balance = ReadonlyBalances::from_storage(&deps.storage)
+        .balance(recipient_address_raw)?
+        .checked_add(total_claimed)
+        .ok_or_else(|| generic_err("Balance overflow"))?;
+    Balances::from_storage(&mut deps.storage).set_balance(recipient_address_raw, account_balance)?;
+
+    for (grantor, amount) in claims {
+        store_transfer(
+            &mut deps.storage,
+            &grantor,
+            recipient_address_raw,
+            &Uint128(amount),
+            env.block.time,
+        )?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "claim_vested"),
+            log(
+                "recipient",
+                deps.api.human_address(recipient_address_raw)?.as_str(),
+            ),
+            log("amount", &total_claimed.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn query_vesting_schedules<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<Binary> {
+    let address = deps.api.canonical_address(account)?;
+
+    let schedules = get_vesting_grants(&deps.storage, &address)?
+        .into_iter()
+        .map(|grant| {
+            Ok(VestingSchedule {
+                grantor: deps.api.human_address(&grant.grantor)?,
+                total: Uint128(grant.total),
+                claimed: Uint128(grant.claimed),
+                start: grant.start,
+                cliff: grant.cliff,
+                end: grant.end,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_binary(&VestingSchedulesResponse { schedules })
+}
+
+/// Builds without the `vesting` feature can't create grants, so there's never anything to claim
+#[cfg(not(feature = "vesting"))]
+mod disabled {
+    use cosmwasm_std::{
+        generic_err, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdResult, Storage,
+        Uint128,
+    };
+
+    pub fn try_transfer_vesting<S: Storage, A: Api, Q: Querier>(
+        _deps: &mut Extern<S, A, Q>,
+        _env: Env,
+        _recipient: &HumanAddr,
+        _amount: &Uint128,
+        _cliff: u64,
+        _duration: u64,
+    ) -> StdResult<HandleResponse> {
+        Err(generic_err("Vesting is not supported by this build of the token"))
+    }
+
+    pub fn try_claim_vested<S: Storage, A: Api, Q: Querier>(
+        _deps: &mut Extern<S, A, Q>,
+        _env: Env,
+    ) -> StdResult<HandleResponse> {
+        Err(generic_err("Vesting is not supported by this build of the token"))
+    }
+}
+
+#[cfg(not(feature = "vesting"))]
+pub use disabled::*;
@@ -1,9 +1,7 @@
-use core::{mem, fmt};
+use core::fmt;
 
-use cosmwasm_std::{Env};
-
-use crate::rand::{Prng, sha_256};
-use crate::utils::{create_hashed_password, ct_slice_compare};
+use crate::rand::Prng;
+use crate::utils::{create_hashed_password, create_legacy_hashed_password, ct_slice_compare};
 
 pub const API_KEY_LENGTH: usize = 44 + 8;
 
@@ -11,29 +9,24 @@ pub const API_KEY_LENGTH: usize = 44 + 8;
 pub struct ViewingKey(pub String);
 
 impl ViewingKey {
+    /// Keys set before viewing keys were hashed with SHA-256 are checked the old way. Every check
+    /// computes and compares both hashes, so neither its time nor its gas tells whether the stored
+    /// hash is a legacy one
     pub fn check_viewing_key(&self, hashed_pw: &[u8]) -> bool {
-
         let mine_hashed = create_hashed_password(&self.0);
+        let mine_legacy_hashed = create_legacy_hashed_password(&self.0);
 
-        ct_slice_compare(mine_hashed.to_vec().as_slice(), hashed_pw)
+        let matches = ct_slice_compare(&mine_hashed, hashed_pw);
+        let matches_legacy = ct_slice_compare(&mine_legacy_hashed, hashed_pw);
+        matches | matches_legacy
     }
 
-    pub fn new(env: &Env, seed: &[u8], entropy: &[u8]) -> Self {
-
-        let mut rng_entropy: Vec<u8> = vec![];
-        rng_entropy.extend_from_slice(&env.block.height.to_be_bytes());
-        rng_entropy.extend_from_slice(&env.block.time.to_be_bytes());
-        rng_entropy.extend_from_slice(&env.message.sender.as_slice());
-        rng_entropy.extend_from_slice(entropy);
-
-        let mut rng = Prng::new(seed, &*rng_entropy);
-
-        let key = sha_256(unsafe { mem::transmute::<[u32; 8], [u8; 32]>(rng.rand_slice()) }.as_ref() );
-
-        Self("api_key_".to_string() + &base64::encode(key))
+    /// A fresh key drawn from `rng`, see `rand::contract_rng`
+    pub fn new(rng: &mut Prng) -> Self {
+        Self("api_key_".to_string() + &base64::encode(rng.rand_bytes()))
     }
 
-    pub fn to_hashed(&self) -> [u8; 24] {
+    pub fn to_hashed(&self) -> [u8; 32] {
         create_hashed_password(&self.0)
     }
 
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    generic_err, log, unauthorized, Api, CanonicalAddr, Env, Extern, HandleResponse,
+    HumanAddr, Querier, ReadonlyStorage, StdResult, Storage, Uint128,
+};
+
+use crate::contract::{assert_admin, convert_decimals, credit, debit};
+use crate::snip20::{register_receive_msg, transfer_msg, DEFAULT_BLOCK_SIZE};
+use crate::staking::get_staking_state;
+use crate::state::{
+    store_mint, store_tagged_transfer, write_total_supply, Balances, ReadonlyConfig,
+    ReadonlyVersionedStorage, SupplyChange, TypedStore, TypedStoreMut, VersionedStorage,
+};
+
+pub const PREFIX_WRAPPED_TOKENS: &[u8] = b"wrapped_tokens";
+
+/// The references wrapping and unwrapping are tagged with in the history
+pub const WRAP_REFERENCE: &str = "wrap";
+pub const UNWRAP_REFERENCE: &str = "unwrap";
+
+/// A SNIP-20 whose tokens can be sent to this contract to be wrapped
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WrappedToken {
+    pub code_hash: String,
+    pub decimals: u8,
+    /// How much of the token this contract holds, which is what can be unwrapped
+    pub held: u128,
+}
+
+fn get_wrapped_token<S: ReadonlyStorage>(
+    storage: &S,
+    contract: &CanonicalAddr,
+) -> StdResult<Option<WrappedToken>> {
+    let store = ReadonlyVersionedStorage::new(PREFIX_WRAPPED_TOKENS, storage);
+    TypedStore::attach(&store).may_load(contract.as_slice())
+}
+
+fn set_wrapped_token<S: Storage>(
+    storage: &mut S,
+    contract: &CanonicalAddr,
+    token: &WrappedToken,
+) -> StdResult<()> {
+    let mut store = VersionedStorage::new(PREFIX_WRAPPED_TOKENS, storage);
+    TypedStoreMut::attach(&mut store).store(contract.as_slice(), token)
+}
+
+/// Admin only, lets holders of the SNIP-20 at `contract` wrap it by sending it here. Also
+/// registers this contract with the token, which needs `own_code_hash` to call `Receive`
+pub fn try_register_wrapped_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: HumanAddr,
+    code_hash: String,
+    decimals: u8,
+    own_code_hash: String,
+) -> StdResult<HandleResponse> {
+    assert_admin(&deps.storage, &env)?;
+    if get_staking_state(&deps.storage)?.is_some() {
+        return Err(generic_err(
+            "Tokens can't be wrapped in staking mode, they aren't backed by delegations",
+        ));
+    }
+
+    let contract_raw = deps.api.canonical_address(&contract)?;
+    let held = get_wrapped_token(&deps.storage, &contract_raw)?.map_or(0, |token| token.held);
+    let token = WrappedToken {
+        code_hash: code_hash.clone(),
+        decimals,
+        held,
+    };
+    set_wrapped_token(&mut deps.storage, &contract_raw, &token)?;
+
+    let messages = vec![register_receive_msg(
+        own_code_hash,
+        DEFAULT_BLOCK_SIZE,
+        code_hash,
+        contract.clone(),
+    )?];
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "register_wrapped_token"),
+            log("contract", contract.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Called by a registered SNIP-20 when its tokens are sent here. Mints the same value in this
+/// token to `from`, whose balance the tokens came out of
+pub fn try_receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let token_raw = &env.message.sender;
+    let mut token = get_wrapped_token(&deps.storage, token_raw)?.ok_or_else(unauthorized)?;
+
+    let decimals = ReadonlyConfig::from_storage(&deps.storage).constants()?.decimals;
+    let minted = convert_decimals(amount.u128(), token.decimals, decimals)?;
+
+    token.held = credit(token.held, amount.u128())?;
+    set_wrapped_token(&mut deps.storage, token_raw, &token)?;
+
+    let from_raw = deps.api.canonical_address(from)?;
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = credit(balances.balance(&from_raw)?, minted)?;
+    balances.set_balance(&from_raw, account_balance)?;
+
+    write_total_supply(&mut deps.storage, SupplyChange::Mint(minted))?;
+
+    store_mint(
+        &mut deps.storage,
+        token_raw,
+        &from_raw,
+        &Uint128(minted),
+        env.block.time,
+        env.block.height,
+        WRAP_REFERENCE,
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "wrap"),
+            log("token", deps.api.human_address(token_raw)?.as_str()),
+            log("amount", &minted.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Burns `amount` of the sender's tokens and sends them what it's worth in `token`
+pub fn try_unwrap_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    token_address: HumanAddr,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let token_raw = deps.api.canonical_address(&token_address)?;
+    let mut token = get_wrapped_token(&deps.storage, &token_raw)?
+        .ok_or_else(|| generic_err(format!("{} is not a wrapped token", token_address)))?;
+
+    let owner = &env.message.sender;
+    let amount_raw = amount.u128();
+    let decimals = ReadonlyConfig::from_storage(&deps.storage).constants()?.decimals;
+    let released = convert_decimals(amount_raw, decimals, token.decimals)?;
+
+    token.held = token.held.checked_sub(released).ok_or_else(|| {
+        generic_err(format!(
+            "Insufficient {} held: available={}, required={}",
+            token_address, token.held, released
+        ))
+    })?;
+    set_wrapped_token(&mut deps.storage, &token_raw, &token)?;
+
+    let mut balances = Balances::from_storage(&mut deps.storage);
+    let account_balance = debit(balances.balance(owner)?, amount_raw)?;
+    balances.set_balance(owner, account_balance)?;
+
+    write_total_supply(&mut deps.storage, SupplyChange::Burn(amount_raw))?;
+
+    store_tagged_transfer(
+        &mut deps.storage,
+        owner,
+        &env.contract.address,
+        &amount,
+        env.block.time,
+        Some(UNWRAP_REFERENCE),
+    )?;
+
+    let messages = vec![transfer_msg(
+        deps.api.human_address(owner)?,
+        Uint128(released),
+        DEFAULT_BLOCK_SIZE,
+        token.code_hash,
+        token_address.clone(),
+    )?];
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "unwrap"),
+            log("token", token_address.as_str()),
+            log("amount", &amount.to_string()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
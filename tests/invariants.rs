@@ -0,0 +1,222 @@
+//! Property tests for the token's bookkeeping: whatever sequence of messages accounts send,
+//! balances have to add up to the total supply and allowances can only be spent once
+
+use std::collections::BTreeMap;
+
+use proptest::prelude::*;
+
+use cosmwasm_std::{Api, CanonicalAddr, Coin, HumanAddr, Uint128};
+
+use secret_secret::contract::{convert_decimals, credit, debit, spend_allowance};
+use secret_secret::msg::HandleMsg;
+use secret_secret::state::{read_allowance, ReadonlyBalances, ReadonlyConfig};
+use secret_secret::testing::{
+    default_init_msg, handle_as, handle_with_funds, init_token, MockDeps,
+};
+
+const ACCOUNTS: [&str; 3] = ["alice", "bob", "carol"];
+
+/// Small enough that most messages succeed, large enough that some overdraw
+const MAX_AMOUNT: u128 = 2_000;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Deposit { who: usize, amount: u128 },
+    Withdraw { who: usize, amount: u128 },
+    Transfer { from: usize, to: usize, amount: u128 },
+    Approve { owner: usize, spender: usize, amount: u128 },
+    TransferFrom { spender: usize, owner: usize, to: usize, amount: u128 },
+    Burn { who: usize, amount: u128 },
+}
+
+fn account() -> impl Strategy<Value = usize> {
+    0..ACCOUNTS.len()
+}
+
+fn amount() -> impl Strategy<Value = u128> {
+    0..=MAX_AMOUNT
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (account(), amount()).prop_map(|(who, amount)| Op::Deposit { who, amount }),
+        (account(), amount()).prop_map(|(who, amount)| Op::Withdraw { who, amount }),
+        (account(), account(), amount())
+            .prop_map(|(from, to, amount)| Op::Transfer { from, to, amount }),
+        (account(), account(), amount())
+            .prop_map(|(owner, spender, amount)| Op::Approve { owner, spender, amount }),
+        (account(), account(), account(), amount()).prop_map(|(spender, owner, to, amount)| {
+            Op::TransferFrom {
+                spender,
+                owner,
+                to,
+                amount,
+            }
+        }),
+        (account(), amount()).prop_map(|(who, amount)| Op::Burn { who, amount }),
+    ]
+}
+
+fn new_token() -> MockDeps {
+    init_token("admin", default_init_msg(&[("alice", 1_000), ("bob", 1_000)])).unwrap()
+}
+
+/// Sends `op`, returning whether the contract accepted it
+fn apply(deps: &mut MockDeps, op: &Op) -> bool {
+    let res = match *op {
+        Op::Deposit { who, amount } => {
+            let funds = [Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint128(amount),
+            }];
+            handle_with_funds(deps, ACCOUNTS[who], &funds, HandleMsg::Deposit {})
+        }
+        Op::Withdraw { who, amount } => {
+            let msg = HandleMsg::Withdraw {
+                amount: Uint128(amount),
+                denom: None,
+            };
+            handle_as(deps, ACCOUNTS[who], msg)
+        }
+        Op::Transfer { from, to, amount } => {
+            let msg = HandleMsg::Transfer {
+                recipient: HumanAddr::from(ACCOUNTS[to]),
+                amount: Uint128(amount),
+            };
+            handle_as(deps, ACCOUNTS[from], msg)
+        }
+        Op::Approve {
+            owner,
+            spender,
+            amount,
+        } => {
+            let msg = HandleMsg::Approve {
+                spender: HumanAddr::from(ACCOUNTS[spender]),
+                amount: Uint128(amount),
+                expected_current: None,
+                notify: None,
+            };
+            handle_as(deps, ACCOUNTS[owner], msg)
+        }
+        Op::TransferFrom {
+            spender,
+            owner,
+            to,
+            amount,
+        } => {
+            let msg = HandleMsg::TransferFrom {
+                owner: HumanAddr::from(ACCOUNTS[owner]),
+                recipient: HumanAddr::from(ACCOUNTS[to]),
+                amount: Uint128(amount),
+            };
+            handle_as(deps, ACCOUNTS[spender], msg)
+        }
+        Op::Burn { who, amount } => {
+            let msg = HandleMsg::Burn {
+                amount: Uint128(amount),
+            };
+            handle_as(deps, ACCOUNTS[who], msg)
+        }
+    };
+    res.is_ok()
+}
+
+fn canonical(deps: &MockDeps, who: usize) -> CanonicalAddr {
+    deps.api
+        .canonical_address(&HumanAddr::from(ACCOUNTS[who]))
+        .unwrap()
+}
+
+fn balance(deps: &MockDeps, who: usize) -> u128 {
+    ReadonlyBalances::from_storage(&deps.storage)
+        .balance(&canonical(deps, who))
+        .unwrap()
+}
+
+fn allowance(deps: &MockDeps, owner: usize, spender: usize) -> u128 {
+    read_allowance(
+        &deps.storage,
+        &canonical(deps, owner),
+        &canonical(deps, spender),
+    )
+    .unwrap()
+}
+
+proptest! {
+    #[test]
+    fn balances_add_up_to_total_supply(ops in prop::collection::vec(op(), 0..40)) {
+        let mut deps = new_token();
+        for op in ops.iter() {
+            apply(&mut deps, op);
+
+            let sum: u128 = (0..ACCOUNTS.len()).map(|who| balance(&deps, who)).sum();
+            let total_supply = ReadonlyConfig::from_storage(&deps.storage).total_supply().unwrap();
+            prop_assert_eq!(sum, total_supply, "after {:?}", op);
+        }
+    }
+
+    #[test]
+    fn allowances_are_spent_at_most_once(ops in prop::collection::vec(op(), 0..40)) {
+        let mut deps = new_token();
+        // what each (owner, spender) pair should have left
+        let mut expected = BTreeMap::new();
+        for op in ops.iter() {
+            let accepted = apply(&mut deps, op);
+
+            match *op {
+                Op::Approve { owner, spender, amount } => {
+                    prop_assert!(accepted);
+                    expected.insert((owner, spender), amount);
+                }
+                Op::TransferFrom { spender, owner, amount, .. } => {
+                    let left = expected.get(&(owner, spender)).cloned().unwrap_or(0);
+                    if amount > left {
+                        prop_assert!(!accepted, "spent {} of an allowance of {}", amount, left);
+                    }
+                    if accepted {
+                        expected.insert((owner, spender), left - amount);
+                    }
+                }
+                _ => {}
+            }
+
+            for owner in 0..ACCOUNTS.len() {
+                for spender in 0..ACCOUNTS.len() {
+                    let left = expected.get(&(owner, spender)).cloned().unwrap_or(0);
+                    prop_assert_eq!(allowance(&deps, owner, spender), left, "after {:?}", op);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn debit_and_credit_conserve_funds(balance: u128, amount: u128) {
+        match debit(balance, amount) {
+            Ok(left) => prop_assert_eq!(left + amount, balance),
+            Err(_) => prop_assert!(amount > balance),
+        }
+        match credit(balance, amount) {
+            Ok(total) => prop_assert_eq!(total - amount, balance),
+            Err(_) => prop_assert!(balance.checked_add(amount).is_none()),
+        }
+    }
+
+    #[test]
+    fn allowance_never_goes_negative(allowance: u128, amount: u128) {
+        match spend_allowance(allowance, amount) {
+            Ok(left) => prop_assert!(left <= allowance && left + amount == allowance),
+            Err(_) => prop_assert!(amount > allowance),
+        }
+    }
+
+    #[test]
+    fn decimal_conversion_round_trips(amount in 0u128..1u128 << 64, from in 0u8..=18, to in 0u8..=18) {
+        if let Ok(converted) = convert_decimals(amount, from, to) {
+            prop_assert_eq!(convert_decimals(converted, to, from).unwrap(), amount);
+        } else {
+            // only converting to fewer decimals can fail, and only for inexact amounts
+            prop_assert!(to < from);
+            prop_assert!(amount % 10u128.pow((from - to) as u32) != 0);
+        }
+    }
+}